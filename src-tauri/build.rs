@@ -1,3 +1,24 @@
+const SNAPSHOT: &str = "resources/spoolmandb-filaments.json";
+
 fn main() {
+    // The bundled SpoolmanDB comes from the committed snapshot, or from a
+    // fresher download named by `SPOOLMANDB_SNAPSHOT`; either way it is
+    // copied into OUT_DIR, so a build never touches the tracked file.
+    println!("cargo:rerun-if-env-changed=SPOOLMANDB_SNAPSHOT");
+    let source = std::env::var("SPOOLMANDB_SNAPSHOT").unwrap_or_else(|_| SNAPSHOT.to_string());
+    println!("cargo:rerun-if-changed={}", source);
+
+    let snapshot = std::fs::read_to_string(&source)
+        .unwrap_or_else(|e| panic!("Failed to read SpoolmanDB snapshot {}: {}", source, e));
+    if std::env::var("PROFILE").as_deref() == Ok("release") && matches!(snapshot.trim(), "" | "[]") {
+        println!(
+            "cargo:warning={} is empty; this build needs the network on first run. Run `npm run update:spoolmandb` to refresh it",
+            source
+        );
+    }
+    let out_dir = std::env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+    std::fs::write(std::path::Path::new(&out_dir).join("spoolmandb-filaments.json"), snapshot)
+        .expect("Failed to write SpoolmanDB snapshot to OUT_DIR");
+
     tauri_build::build()
 }
@@ -1,7 +1,12 @@
+use crate::db::EffectiveCalibration;
+use crate::error::{AppError, AppResult, ErrorCode};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
 
+/// Model presets are generated for when no printer is configured.
+pub const DEFAULT_PRINTER_MODEL: &str = "X1C";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BambuFilamentProfile {
     pub version: String,
@@ -41,6 +46,12 @@ pub struct BambuFilamentProfile {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub filament_max_volumetric_speed: Option<Vec<String>>,
     
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enable_pressure_advance: Option<Vec<String>>,
+    
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pressure_advance: Option<Vec<String>>,
+    
     #[serde(skip_serializing_if = "Option::is_none")]
     pub fan_cooling_layer_time: Option<Vec<String>>,
     
@@ -113,8 +124,10 @@ impl BambuFilamentProfile {
             hot_plate_temp: None,
             hot_plate_temp_initial_layer: None,
             chamber_temperature: None,
-            filament_flow_ratio: Some(vec!["1".to_string()]),
+            filament_flow_ratio: None,
             filament_max_volumetric_speed: None,
+            enable_pressure_advance: None,
+            pressure_advance: None,
             fan_cooling_layer_time: None,
             fan_max_speed: None,
             fan_min_speed: None,
@@ -128,6 +141,21 @@ impl BambuFilamentProfile {
             compatible_prints_condition: None,
         }
     }
+
+    /// Overrides the inherited flow ratio, volumetric speed and pressure
+    /// advance with measured values; unmeasured ones stay inherited.
+    pub fn apply_calibration(&mut self, calibration: &EffectiveCalibration) {
+        if let Some(ratio) = calibration.flow_ratio {
+            self.filament_flow_ratio = Some(vec![ratio.to_string()]);
+        }
+        if let Some(speed) = calibration.max_volumetric_speed {
+            self.filament_max_volumetric_speed = Some(vec![speed.to_string()]);
+        }
+        if let Some(k) = calibration.pressure_advance {
+            self.enable_pressure_advance = Some(vec!["1".to_string()]);
+            self.pressure_advance = Some(vec![k.to_string()]);
+        }
+    }
 }
 
 pub struct BambuStudioManager {
@@ -136,39 +164,39 @@ pub struct BambuStudioManager {
 }
 
 impl BambuStudioManager {
-    pub fn new() -> Result<Self, String> {
+    pub fn new() -> AppResult<Self> {
         let home = std::env::var("HOME")
-            .map_err(|_| "Could not determine HOME directory".to_string())?;
+            .map_err(|_| unavailable("Could not determine HOME directory"))?;
         
         let base_dir = PathBuf::from(home).join(".config/BambuStudio/user");
         
         if !base_dir.exists() {
-            return Err(format!(
+            return Err(unavailable(format!(
                 "Bambu Studio config directory not found: {}\nMake sure Bambu Studio is installed.",
                 base_dir.display()
-            ));
+            )));
         }
         
         let mut user_dirs = Vec::new();
         
         for entry in fs::read_dir(&base_dir)
-            .map_err(|e| format!("Failed to read user directory: {}", e))? 
+            .map_err(|e| io_error("Failed to read user directory", e))? 
         {
-            let entry = entry.map_err(|e| format!("Failed to read entry: {}", e))?;
+            let entry = entry.map_err(|e| io_error("Failed to read entry", e))?;
             let path = entry.path();
             
             if path.is_dir() {
                 let filament_dir = path.join("filament");
                 if !filament_dir.exists() {
                     fs::create_dir_all(&filament_dir)
-                        .map_err(|e| format!("Failed to create filament dir: {}", e))?;
+                        .map_err(|e| io_error("Failed to create filament dir", e))?;
                 }
                 user_dirs.push(filament_dir);
             }
         }
         
         if user_dirs.is_empty() {
-            return Err("No user profiles found in Bambu Studio".to_string());
+            return Err(unavailable("No user profiles found in Bambu Studio"));
         }
         
         println!("✅ Found {} Bambu Studio user profile(s)", user_dirs.len());
@@ -176,7 +204,7 @@ impl BambuStudioManager {
         Ok(Self { base_dir, user_dirs })
     }
     
-    pub fn list_profiles(&self) -> Result<Vec<String>, String> {
+    pub fn list_profiles(&self) -> AppResult<Vec<String>> {
         let mut profiles = Vec::new();
         
         for user_dir in &self.user_dirs {
@@ -205,20 +233,22 @@ impl BambuStudioManager {
         Ok(profiles)
     }
     
-    pub fn read_profile(&self, name: &str) -> Result<BambuFilamentProfile, String> {
+    pub fn read_profile(&self, name: &str) -> AppResult<BambuFilamentProfile> {
         for user_dir in &self.user_dirs {
             let path = user_dir.join(format!("{}.json", name));
             
             if path.exists() {
                 let content = fs::read_to_string(&path)
-                    .map_err(|e| format!("Failed to read profile {}: {}", name, e))?;
+                    .map_err(|e| io_error(&format!("Failed to read profile {}", name), e))?;
                 
-                return serde_json::from_str(&content)
-                    .map_err(|e| format!("Failed to parse profile {}: {}", name, e));
+                return serde_json::from_str(&content).map_err(|e| {
+                    let message = format!("Failed to parse profile {}: {}", name, e);
+                    AppError::new(ErrorCode::Parse, message)
+                });
             }
         }
         
-        Err(format!("Profile '{}' not found", name))
+        Err(AppError::not_found(format!("Profile '{}' not found", name)))
     }
     
     pub fn create_from_spoolman(
@@ -230,7 +260,8 @@ impl BambuStudioManager {
         nozzle_temp: u16,
         bed_temp: u16,
         printer: &str,
-    ) -> Result<String, String> {
+        calibration: &EffectiveCalibration,
+    ) -> AppResult<String> {
         let profile_name = format!("{} {} {} @{}", vendor, material, name, printer);
         
         let mut profile = BambuFilamentProfile::new_for_material(material, printer);
@@ -240,13 +271,14 @@ impl BambuStudioManager {
         profile.nozzle_temperature = Some(vec![nozzle_temp.to_string()]);
         profile.hot_plate_temp = Some(vec![bed_temp.to_string()]);
         profile.hot_plate_temp_initial_layer = Some(vec![bed_temp.to_string()]);
+        profile.apply_calibration(calibration);
         
         self.create_profile(&profile)
     }
     
-    pub fn create_profile(&self, profile: &BambuFilamentProfile) -> Result<String, String> {
+    pub fn create_profile(&self, profile: &BambuFilamentProfile) -> AppResult<String> {
         if self.user_dirs.is_empty() {
-            return Err("No user directories found".to_string());
+            return Err(unavailable("No user directories found"));
         }
         
         let user_dir = &self.user_dirs[0];
@@ -260,10 +292,10 @@ impl BambuStudioManager {
         }
         
         let json_content = serde_json::to_string_pretty(profile)
-            .map_err(|e| format!("Failed to serialize profile: {}", e))?;
+            .map_err(|e| AppError::internal(format!("Failed to serialize profile: {}", e)))?;
         
         fs::write(&json_path, json_content)
-            .map_err(|e| format!("Failed to write profile JSON: {}", e))?;
+            .map_err(|e| io_error("Failed to write profile JSON", e))?;
         
         let user_id = self.extract_user_id(user_dir);
         let timestamp = std::time::SystemTime::now()
@@ -280,7 +312,7 @@ impl BambuStudioManager {
         );
         
         fs::write(&info_path, info_content.clone())
-            .map_err(|e| format!("Failed to write .info file: {}", e))?;
+            .map_err(|e| io_error("Failed to write .info file", e))?;
         
         println!("\n✅ Profilo creato: {}", json_path.display());
         println!("   File ID: {}", profile.filament_id[0]);
@@ -296,17 +328,17 @@ impl BambuStudioManager {
         Ok(filename)
     }
     
-    pub fn update_profile(&self, name: &str, profile: &BambuFilamentProfile) -> Result<(), String> {
+    pub fn update_profile(&self, name: &str, profile: &BambuFilamentProfile) -> AppResult<()> {
         for user_dir in &self.user_dirs {
             let filename = self.sanitize_filename(name);
             let json_path = user_dir.join(format!("{}.json", filename));
             
             if json_path.exists() {
                 let json_content = serde_json::to_string_pretty(profile)
-                    .map_err(|e| format!("Failed to serialize profile: {}", e))?;
+                    .map_err(|e| AppError::internal(format!("Failed to serialize profile: {}", e)))?;
                 
                 fs::write(&json_path, json_content)
-                    .map_err(|e| format!("Failed to update profile: {}", e))?;
+                    .map_err(|e| io_error("Failed to update profile", e))?;
                 
                 let info_path = user_dir.join(format!("{}.info", filename));
                 let user_id = self.extract_user_id(user_dir);
@@ -324,17 +356,17 @@ impl BambuStudioManager {
                 );
                 
                 fs::write(&info_path, info_content)
-                    .map_err(|e| format!("Failed to update .info file: {}", e))?;
+                    .map_err(|e| io_error("Failed to update .info file", e))?;
                 
                 println!("✅ Profilo aggiornato: {}", name);
                 return Ok(());
             }
         }
         
-        Err(format!("Profile '{}' not found", name))
+        Err(AppError::not_found(format!("Profile '{}' not found", name)))
     }
     
-    pub fn delete_profile(&self, name: &str) -> Result<(), String> {
+    pub fn delete_profile(&self, name: &str) -> AppResult<()> {
         let filename = self.sanitize_filename(name);
         let mut deleted = false;
         
@@ -344,13 +376,13 @@ impl BambuStudioManager {
             
             if json_path.exists() {
                 fs::remove_file(&json_path)
-                    .map_err(|e| format!("Failed to delete JSON: {}", e))?;
+                    .map_err(|e| io_error("Failed to delete JSON", e))?;
                 deleted = true;
             }
             
             if info_path.exists() {
                 fs::remove_file(&info_path)
-                    .map_err(|e| format!("Failed to delete .info: {}", e))?;
+                    .map_err(|e| io_error("Failed to delete .info", e))?;
             }
         }
         
@@ -358,7 +390,7 @@ impl BambuStudioManager {
             println!("✅ Profilo eliminato: {}", name);
             Ok(())
         } else {
-            Err(format!("Profile '{}' not found", name))
+            Err(AppError::not_found(format!("Profile '{}' not found", name)))
         }
     }
     
@@ -380,3 +412,11 @@ impl BambuStudioManager {
             .to_string()
     }
 }
+
+fn unavailable(message: impl Into<String>) -> AppError {
+    AppError::new(ErrorCode::BambuStudioUnavailable, message)
+}
+
+fn io_error(context: &str, error: std::io::Error) -> AppError {
+    AppError::new(ErrorCode::Io, format!("{}: {}", context, error))
+}
@@ -1,8 +1,52 @@
-use rusqlite::{params, Connection, Result};
+use crate::error::{AppError, AppResult};
+use crate::spoolman::SpoolmanFilament;
+use exchange::ProfileKind;
+use r2d2::PooledConnection;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, OptionalExtension, Result, Row};
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+mod backup;
+mod calibration;
+mod costs;
+mod drying;
+mod exchange;
+mod history;
+mod migrations;
+mod pool;
+mod printers;
+mod revisions;
+mod search;
+mod spoolman_server;
+mod spools;
+mod tags;
+mod trash;
+mod trays;
+mod upstream;
+mod validation;
+
+pub use backup::BackupInfo;
+pub use calibration::{CalibrationResult, EffectiveCalibration, DEFAULT_NOZZLE_DIAMETER};
+pub use costs::{AmsUsage, CostEstimate, JobUsageInput, PrintJob};
+pub use drying::{DryingEntry, SpoolDryingStatus};
+pub use exchange::{parse_document, ExportSelection, ImportReport, ProfileDocument};
+pub use history::{HistoryEntry, HistoryEvent, HistoryFilter};
+pub use pool::DbPool;
+pub use printers::Printer;
+pub use revisions::{ProfileRevision, RevisionDiff};
+pub use search::{SearchQuery, SearchResults};
+pub use spoolman_server::SpoolmanImportReport;
+pub use spools::{Spool, SpoolWeightEntry};
+pub use tags::{Collection, Tag};
+pub use trash::TrashedProfile;
+pub use trays::TrayAssignment;
+pub use upstream::FavoriteUpdate;
+
+/// A local filament profile. The optional fields mirror `SpoolmanFilament`
+/// so favorites keep everything SpoolmanDB knows about them; temperatures
+/// of 0 and an empty `color` mean SpoolmanDB had no single value.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
 pub struct FilamentProfile {
     pub id: Option<i64>,
     pub brand: String,
@@ -14,6 +58,165 @@ pub struct FilamentProfile {
     pub diameter: f64,
     pub is_favorite: bool,
     pub is_custom: bool,
+    #[serde(default)]
+    pub spoolman_id: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub color_hexes: Option<Vec<String>>,
+    #[serde(default)]
+    pub nozzle_temp_range: Option<Vec<i32>>,
+    #[serde(default)]
+    pub bed_temp_range: Option<Vec<i32>>,
+    #[serde(default)]
+    pub weight: Option<f64>,
+    #[serde(default)]
+    pub spool_weight: Option<f64>,
+    #[serde(default)]
+    pub spool_type: Option<String>,
+    #[serde(default)]
+    pub finish: Option<String>,
+    #[serde(default)]
+    pub multi_color_direction: Option<String>,
+    #[serde(default)]
+    pub pattern: Option<String>,
+    #[serde(default)]
+    pub translucent: bool,
+    #[serde(default)]
+    pub glow: bool,
+    #[serde(default)]
+    pub notes: Option<String>,
+    /// Default price used to cost prints when a spool has no purchase price.
+    #[serde(default)]
+    pub price_per_kg: Option<f64>,
+    #[serde(default)]
+    pub currency: Option<String>,
+}
+
+impl From<&SpoolmanFilament> for FilamentProfile {
+    fn from(filament: &SpoolmanFilament) -> Self {
+        Self {
+            id: None,
+            brand: filament.manufacturer.clone(),
+            material: filament.material.clone(),
+            color: filament
+                .color_hex
+                .as_ref()
+                .or_else(|| filament.color_hexes.as_ref().and_then(|hexes| hexes.first()))
+                .map(|hex| format!("#{}", hex.trim_start_matches('#')))
+                .unwrap_or_default(),
+            nozzle_temp: filament.extruder_temp.unwrap_or(0),
+            bed_temp: filament.bed_temp.unwrap_or(0),
+            density: filament.density,
+            diameter: filament.diameter,
+            is_favorite: false,
+            is_custom: false,
+            spoolman_id: Some(filament.id.clone()),
+            name: Some(filament.name.clone()),
+            color_hexes: filament.color_hexes.clone(),
+            nozzle_temp_range: filament.extruder_temp_range.clone(),
+            bed_temp_range: filament.bed_temp_range.clone(),
+            weight: filament.weight,
+            spool_weight: filament.spool_weight,
+            spool_type: filament.spool_type.clone(),
+            finish: filament.finish.clone(),
+            multi_color_direction: filament.multi_color_direction.clone(),
+            pattern: filament.pattern.clone(),
+            translucent: filament.translucent,
+            glow: filament.glow,
+            notes: None,
+            price_per_kg: None,
+            currency: None,
+        }
+    }
+}
+
+impl From<&FilamentProfile> for SpoolmanFilament {
+    fn from(profile: &FilamentProfile) -> Self {
+        let color = profile.color.trim_start_matches('#');
+        let color_is_first_hex = profile
+            .color_hexes
+            .as_ref()
+            .and_then(|hexes| hexes.first())
+            .is_some_and(|first| first.trim_start_matches('#') == color);
+
+        Self {
+            id: profile
+                .spoolman_id
+                .clone()
+                .unwrap_or_else(|| format!("local_{}", profile.id.unwrap_or_default())),
+            manufacturer: profile.brand.clone(),
+            name: profile.name.clone().unwrap_or_default(),
+            material: profile.material.clone(),
+            density: profile.density,
+            diameter: profile.diameter,
+            color_hex: if color.is_empty() || color_is_first_hex {
+                None
+            } else {
+                Some(color.to_string())
+            },
+            color_hexes: profile.color_hexes.clone(),
+            weight: profile.weight,
+            spool_weight: profile.spool_weight,
+            spool_type: profile.spool_type.clone(),
+            extruder_temp: (profile.nozzle_temp != 0).then_some(profile.nozzle_temp),
+            extruder_temp_range: profile.nozzle_temp_range.clone(),
+            bed_temp: (profile.bed_temp != 0).then_some(profile.bed_temp),
+            bed_temp_range: profile.bed_temp_range.clone(),
+            finish: profile.finish.clone(),
+            multi_color_direction: profile.multi_color_direction.clone(),
+            pattern: profile.pattern.clone(),
+            translucent: profile.translucent,
+            glow: profile.glow,
+        }
+    }
+}
+
+const PROFILE_COLUMNS: &str = "id, brand, material, color, nozzle_temp, bed_temp, density, diameter, is_favorite, is_custom, \
+    spoolman_id, name, color_hexes, nozzle_temp_range, bed_temp_range, weight, spool_weight, spool_type, finish, multi_color_direction, pattern, translucent, glow, notes, \
+    price_per_kg, currency";
+
+/// Number of columns in `PROFILE_COLUMNS`; queries that select extra
+/// columns after it read them from this index on.
+const PROFILE_COLUMN_COUNT: usize = 26;
+
+fn to_json<T: Serialize>(value: &Option<T>) -> Option<String> {
+    value.as_ref().and_then(|v| serde_json::to_string(v).ok())
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(value: Option<String>) -> Option<T> {
+    value.and_then(|v| serde_json::from_str(&v).ok())
+}
+
+fn row_to_profile(row: &Row) -> Result<FilamentProfile> {
+    Ok(FilamentProfile {
+        id: Some(row.get(0)?),
+        brand: row.get(1)?,
+        material: row.get(2)?,
+        color: row.get(3)?,
+        nozzle_temp: row.get(4)?,
+        bed_temp: row.get(5)?,
+        density: row.get(6)?,
+        diameter: row.get(7)?,
+        is_favorite: row.get::<_, i32>(8)? == 1,
+        is_custom: row.get::<_, i32>(9)? == 1,
+        spoolman_id: row.get(10)?,
+        name: row.get(11)?,
+        color_hexes: from_json(row.get(12)?),
+        nozzle_temp_range: from_json(row.get(13)?),
+        bed_temp_range: from_json(row.get(14)?),
+        weight: row.get(15)?,
+        spool_weight: row.get(16)?,
+        spool_type: row.get(17)?,
+        finish: row.get(18)?,
+        multi_color_direction: row.get(19)?,
+        pattern: row.get(20)?,
+        translucent: row.get::<_, i32>(21)? == 1,
+        glow: row.get::<_, i32>(22)? == 1,
+        notes: row.get(23)?,
+        price_per_kg: row.get(24)?,
+        currency: row.get(25)?,
+    })
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -25,136 +228,91 @@ pub struct Settings {
     pub default_ams: i32,
     pub default_tray: i32,
     pub auto_sync: bool,
+    #[serde(default = "default_backup_retention")]
+    pub backup_retention: u32,
+    /// Base URL, `filaments.json` path or SpoolmanDB checkout; empty for SpoolmanDB.
+    #[serde(default)]
+    pub spoolmandb_source: Option<String>,
+    /// Self-hosted Spoolman, e.g. `http://localhost:7912`; empty for none.
+    #[serde(default)]
+    pub spoolman_server_url: Option<String>,
+}
+
+fn default_backup_retention() -> u32 {
+    backup::DEFAULT_RETENTION
 }
 
+/// One checked-out connection; see `DbPool::get`.
 pub struct Database {
-    conn: Connection,
+    conn: PooledConnection<SqliteConnectionManager>,
+    backup_dir: PathBuf,
 }
 
 impl Database {
-    pub fn new() -> Result<Self> {
-        let data_dir = dirs::data_local_dir()
-            .ok_or_else(|| rusqlite::Error::InvalidPath(PathBuf::from("No data directory")))?;
-        let app_dir = data_dir.join("spoolsync-desktop");
-        
-        std::fs::create_dir_all(&app_dir)
-            .map_err(|e| rusqlite::Error::InvalidPath(PathBuf::from(format!("Failed to create dir: {}", e))))?;
-
-        let db_path = app_dir.join("spoolsync.db");
-        println!("📂 Database path: {:?}", db_path);
-
-        let conn = Connection::open(db_path)?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS filament_profiles (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                brand TEXT NOT NULL,
-                material TEXT NOT NULL,
-                color TEXT NOT NULL,
-                nozzle_temp INTEGER NOT NULL,
-                bed_temp INTEGER NOT NULL,
-                density REAL NOT NULL,
-                diameter REAL NOT NULL,
-                is_favorite INTEGER NOT NULL DEFAULT 0,
-                is_custom INTEGER NOT NULL DEFAULT 0
-            )",
-            [],
-        )?;
-
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS settings (
-                id INTEGER PRIMARY KEY CHECK (id = 1),
-                printer_name TEXT,
-                printer_ip TEXT NOT NULL DEFAULT '',
-                printer_serial TEXT NOT NULL DEFAULT '',
-                printer_access_code TEXT NOT NULL DEFAULT '',
-                default_ams INTEGER NOT NULL DEFAULT 0,
-                default_tray INTEGER NOT NULL DEFAULT 0,
-                auto_sync INTEGER NOT NULL DEFAULT 0
-            )",
-            [],
-        )?;
-
-        conn.execute(
-            "INSERT OR IGNORE INTO settings (id, printer_ip, printer_serial, printer_access_code) VALUES (1, '', '', '')",
-            [],
-        )?;
-
-        Ok(Database { conn })
-    }
-
-    pub fn get_favorites(&self) -> Result<Vec<FilamentProfile>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, brand, material, color, nozzle_temp, bed_temp, density, diameter, is_favorite, is_custom 
-             FROM filament_profiles WHERE is_favorite = 1",
-        )?;
+    pub fn get_favorites(&self) -> AppResult<Vec<FilamentProfile>> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {} FROM filament_profiles WHERE is_favorite = 1 AND deleted_at IS NULL",
+            PROFILE_COLUMNS
+        ))?;
 
         let profiles = stmt
-            .query_map([], |row| {
-                Ok(FilamentProfile {
-                    id: Some(row.get(0)?),
-                    brand: row.get(1)?,
-                    material: row.get(2)?,
-                    color: row.get(3)?,
-                    nozzle_temp: row.get(4)?,
-                    bed_temp: row.get(5)?,
-                    density: row.get(6)?,
-                    diameter: row.get(7)?,
-                    is_favorite: row.get::<_, i32>(8)? == 1,
-                    is_custom: row.get::<_, i32>(9)? == 1,
-                })
-            })?
+            .query_map([], row_to_profile)?
             .collect::<Result<Vec<_>>>()?;
 
         Ok(profiles)
     }
 
-    pub fn get_custom_profiles(&self) -> Result<Vec<FilamentProfile>> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, brand, material, color, nozzle_temp, bed_temp, density, diameter, is_favorite, is_custom 
-             FROM filament_profiles WHERE is_custom = 1",
-        )?;
+    pub fn get_custom_profiles(&self) -> AppResult<Vec<FilamentProfile>> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {} FROM filament_profiles WHERE is_custom = 1 AND deleted_at IS NULL",
+            PROFILE_COLUMNS
+        ))?;
 
         let profiles = stmt
-            .query_map([], |row| {
-                Ok(FilamentProfile {
-                    id: Some(row.get(0)?),
-                    brand: row.get(1)?,
-                    material: row.get(2)?,
-                    color: row.get(3)?,
-                    nozzle_temp: row.get(4)?,
-                    bed_temp: row.get(5)?,
-                    density: row.get(6)?,
-                    diameter: row.get(7)?,
-                    is_favorite: row.get::<_, i32>(8)? == 1,
-                    is_custom: row.get::<_, i32>(9)? == 1,
-                })
-            })?
+            .query_map([], row_to_profile)?
             .collect::<Result<Vec<_>>>()?;
 
         Ok(profiles)
     }
 
-    pub fn get_brands(&self) -> Result<Vec<String>> {
-        let mut stmt = self.conn.prepare("SELECT DISTINCT brand FROM filament_profiles ORDER BY brand")?;
+    pub fn get_profile(&self, id: i64) -> AppResult<FilamentProfile> {
+        self.find_profile(id)?
+            .ok_or_else(|| AppError::not_found(format!("No profile with id {}", id)))
+    }
+
+    fn find_profile(&self, id: i64) -> AppResult<Option<FilamentProfile>> {
+        Ok(self
+            .conn
+            .query_row(
+                &format!("SELECT {} FROM filament_profiles WHERE id = ?1", PROFILE_COLUMNS),
+                params![id],
+                row_to_profile,
+            )
+            .optional()?)
+    }
+
+    pub fn get_brands(&self) -> AppResult<Vec<String>> {
+        let mut stmt = self.conn.prepare_cached("SELECT DISTINCT brand FROM filament_profiles WHERE deleted_at IS NULL ORDER BY brand")?;
         let brands = stmt
             .query_map([], |row| row.get(0))?
             .collect::<Result<Vec<_>>>()?;
         Ok(brands)
     }
 
-    pub fn get_materials(&self) -> Result<Vec<String>> {
-        let mut stmt = self.conn.prepare("SELECT DISTINCT material FROM filament_profiles ORDER BY material")?;
+    pub fn get_materials(&self) -> AppResult<Vec<String>> {
+        let mut stmt = self.conn.prepare_cached("SELECT DISTINCT material FROM filament_profiles WHERE deleted_at IS NULL ORDER BY material")?;
         let materials = stmt
             .query_map([], |row| row.get(0))?
             .collect::<Result<Vec<_>>>()?;
         Ok(materials)
     }
 
-    pub fn add_favorite(&self, profile: FilamentProfile) -> Result<i64> {
+    fn insert_profile(&self, profile: &FilamentProfile, is_favorite: bool, is_custom: bool) -> AppResult<i64> {
         self.conn.execute(
-            "INSERT INTO filament_profiles (brand, material, color, nozzle_temp, bed_temp, density, diameter, is_favorite, is_custom)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 1, 0)",
+            "INSERT INTO filament_profiles (brand, material, color, nozzle_temp, bed_temp, density, diameter, is_favorite, is_custom,
+                spoolman_id, name, color_hexes, nozzle_temp_range, bed_temp_range, weight, spool_weight, spool_type, finish, multi_color_direction, pattern, translucent, glow, notes,
+                price_per_kg, currency)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19, ?20, ?21, ?22, ?23, ?24, ?25)",
             params![
                 profile.brand,
                 profile.material,
@@ -163,37 +321,83 @@ impl Database {
                 profile.bed_temp,
                 profile.density,
                 profile.diameter,
+                is_favorite as i32,
+                is_custom as i32,
+                profile.spoolman_id,
+                profile.name,
+                to_json(&profile.color_hexes),
+                to_json(&profile.nozzle_temp_range),
+                to_json(&profile.bed_temp_range),
+                profile.weight,
+                profile.spool_weight,
+                profile.spool_type,
+                profile.finish,
+                profile.multi_color_direction,
+                profile.pattern,
+                profile.translucent as i32,
+                profile.glow as i32,
+                profile.notes,
+                profile.price_per_kg,
+                profile.currency,
             ],
         )?;
-        Ok(self.conn.last_insert_rowid())
+        let id = self.conn.last_insert_rowid();
+
+        let mut entry = HistoryEntry::new(HistoryEvent::ProfileCreate);
+        entry.profile_id = Some(id);
+        entry.subject = Some(history::profile_label(profile));
+        entry.payload = serde_json::to_value(profile).ok();
+        self.record_history(&entry)?;
+
+        Ok(id)
     }
 
-    pub fn remove_favorite(&self, id: i64) -> Result<()> {
-        self.conn.execute("DELETE FROM filament_profiles WHERE id = ?1", params![id])?;
-        Ok(())
+    /// Saves a favorite. Favorites linked to a SpoolmanDB entry are stored
+    /// once; adding the same entry again returns the existing id. See
+    /// `add_favorite_from_spoolman` to track the entry for updates.
+    pub fn add_favorite(&self, profile: FilamentProfile) -> AppResult<i64> {
+        if let Some(spoolman_id) = &profile.spoolman_id {
+            if let Some(id) = self.find_favorite_by_spoolman_id(spoolman_id)? {
+                return Ok(id);
+            }
+        }
+
+        self.insert_profile(&profile, true, false)
     }
 
-    pub fn create_custom_profile(&self, profile: FilamentProfile) -> Result<i64> {
-        self.conn.execute(
-            "INSERT INTO filament_profiles (brand, material, color, nozzle_temp, bed_temp, density, diameter, is_favorite, is_custom)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0, 1)",
-            params![
-                profile.brand,
-                profile.material,
-                profile.color,
-                profile.nozzle_temp,
-                profile.bed_temp,
-                profile.density,
-                profile.diameter,
-            ],
-        )?;
-        Ok(self.conn.last_insert_rowid())
+    /// Moves a favorite to the trash; see `restore_profile`.
+    pub fn remove_favorite(&self, id: i64) -> AppResult<()> {
+        self.trash_profile(id, ProfileKind::Favorite)
+    }
+
+    /// Rejects implausible values with field-level `ValidationErrors` and
+    /// stores colors in canonical hex.
+    pub fn create_custom_profile(&self, mut profile: FilamentProfile) -> AppResult<i64> {
+        validation::validate_profile(&mut profile)?;
+        self.insert_profile(&profile, false, true)
+    }
+
+    /// Validated like `create_custom_profile`. Every update that changes
+    /// something is kept as a revision, with an optional note, see
+    /// `get_profile_revisions`.
+    pub fn update_custom_profile(&self, mut profile: FilamentProfile, note: Option<&str>) -> AppResult<()> {
+        validation::validate_profile(&mut profile)?;
+        self.update_profile_fields(profile, note)
     }
 
-    pub fn update_custom_profile(&self, profile: FilamentProfile) -> Result<()> {
+    /// Writes a profile's fields without validation, for data that is
+    /// already trusted: SpoolmanDB updates and reverts.
+    fn update_profile_fields(&self, profile: FilamentProfile, note: Option<&str>) -> AppResult<()> {
+        let before = match profile.id {
+            Some(id) => self.find_profile(id)?,
+            None => None,
+        };
         self.conn.execute(
-            "UPDATE filament_profiles SET brand = ?1, material = ?2, color = ?3, nozzle_temp = ?4, bed_temp = ?5, density = ?6, diameter = ?7
-             WHERE id = ?8",
+            "UPDATE filament_profiles SET brand = ?1, material = ?2, color = ?3, nozzle_temp = ?4, bed_temp = ?5, density = ?6, diameter = ?7,
+                spoolman_id = ?8, name = ?9, color_hexes = ?10, nozzle_temp_range = ?11, bed_temp_range = ?12, weight = ?13, spool_weight = ?14,
+                spool_type = ?15, finish = ?16, multi_color_direction = ?17, pattern = ?18, translucent = ?19, glow = ?20, notes = ?21,
+                price_per_kg = ?22, currency = ?23
+             WHERE id = ?24",
             params![
                 profile.brand,
                 profile.material,
@@ -202,20 +406,51 @@ impl Database {
                 profile.bed_temp,
                 profile.density,
                 profile.diameter,
+                profile.spoolman_id,
+                profile.name,
+                to_json(&profile.color_hexes),
+                to_json(&profile.nozzle_temp_range),
+                to_json(&profile.bed_temp_range),
+                profile.weight,
+                profile.spool_weight,
+                profile.spool_type,
+                profile.finish,
+                profile.multi_color_direction,
+                profile.pattern,
+                profile.translucent as i32,
+                profile.glow as i32,
+                profile.notes,
+                profile.price_per_kg,
+                profile.currency,
                 profile.id,
             ],
         )?;
+
+        if let Some(before) = before {
+            let mut after = profile;
+            after.is_favorite = before.is_favorite;
+            after.is_custom = before.is_custom;
+            let changes = history::profile_changes(&before, &after);
+            if changes.as_object().is_some_and(|changes| !changes.is_empty()) {
+                let mut entry = HistoryEntry::new(HistoryEvent::ProfileUpdate);
+                entry.profile_id = after.id;
+                entry.subject = Some(history::profile_label(&after));
+                entry.payload = Some(changes);
+                self.record_history(&entry)?;
+                self.record_revision(&before, &after, note)?;
+            }
+        }
         Ok(())
     }
 
-    pub fn delete_custom_profile(&self, id: i64) -> Result<()> {
-        self.conn.execute("DELETE FROM filament_profiles WHERE id = ?1", params![id])?;
-        Ok(())
+    /// Moves a custom profile to the trash; see `restore_profile`.
+    pub fn delete_custom_profile(&self, id: i64) -> AppResult<()> {
+        self.trash_profile(id, ProfileKind::Custom)
     }
 
-    pub fn get_settings(&self) -> Result<Settings> {
-        let mut stmt = self.conn.prepare(
-            "SELECT printer_name, printer_ip, printer_serial, printer_access_code, default_ams, default_tray, auto_sync FROM settings WHERE id = 1",
+    pub fn get_settings(&self) -> AppResult<Settings> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT printer_name, printer_ip, printer_serial, printer_access_code, default_ams, default_tray, auto_sync, backup_retention, spoolmandb_source, spoolman_server_url FROM settings WHERE id = 1",
         )?;
 
         let settings = stmt.query_row([], |row| {
@@ -227,15 +462,31 @@ impl Database {
                 default_ams: row.get(4)?,
                 default_tray: row.get(5)?,
                 auto_sync: row.get::<_, i32>(6)? == 1,
+                backup_retention: row.get(7)?,
+                spoolmandb_source: row.get(8)?,
+                spoolman_server_url: row.get(9)?,
             })
         })?;
 
         Ok(settings)
     }
 
-    pub fn save_settings(&self, settings: Settings) -> Result<()> {
+    pub fn set_settings_access_code(&self, access_code: &str) -> AppResult<()> {
         self.conn.execute(
-            "UPDATE settings SET printer_name = ?1, printer_ip = ?2, printer_serial = ?3, printer_access_code = ?4, default_ams = ?5, default_tray = ?6, auto_sync = ?7 WHERE id = 1",
+            "UPDATE settings SET printer_access_code = ?1 WHERE id = 1",
+            params![access_code],
+        )?;
+        Ok(())
+    }
+
+    /// Saves settings and mirrors the printer fields onto the default entry
+    /// of the printer registry, creating it on first save.
+    pub fn save_settings(&self, settings: Settings) -> AppResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "UPDATE settings SET printer_name = ?1, printer_ip = ?2, printer_serial = ?3, printer_access_code = ?4, default_ams = ?5, default_tray = ?6, auto_sync = ?7,
+                backup_retention = ?8, spoolmandb_source = NULLIF(TRIM(?9), ''),
+                spoolman_server_url = NULLIF(TRIM(?10), '') WHERE id = 1",
             params![
                 settings.printer_name,
                 settings.printer_ip,
@@ -244,8 +495,51 @@ impl Database {
                 settings.default_ams,
                 settings.default_tray,
                 settings.auto_sync as i32,
+                settings.backup_retention.max(1),
+                settings.spoolmandb_source,
+                settings.spoolman_server_url,
             ],
         )?;
+
+        if !settings.printer_ip.is_empty() {
+            let updated = tx.execute(
+                "UPDATE printers SET name = COALESCE(NULLIF(?1, ''), name), ip_address = ?2, serial_number = ?3, access_code = ?4, default_ams = ?5, default_tray = ?6
+                 WHERE is_default = 1",
+                params![
+                    settings.printer_name,
+                    settings.printer_ip,
+                    settings.printer_serial,
+                    settings.printer_access_code,
+                    settings.default_ams,
+                    settings.default_tray,
+                ],
+            )?;
+            if updated == 0 {
+                tx.execute(
+                    "INSERT INTO printers (name, ip_address, serial_number, access_code, default_ams, default_tray, is_default)
+                     VALUES (COALESCE(NULLIF(?1, ''), 'Printer'), ?2, ?3, ?4, ?5, ?6, 1)",
+                    params![
+                        settings.printer_name,
+                        settings.printer_ip,
+                        settings.printer_serial,
+                        settings.printer_access_code,
+                        settings.default_ams,
+                        settings.default_tray,
+                    ],
+                )?;
+            }
+        }
+
+        tx.commit()?;
         Ok(())
     }
 }
+
+/// A migrated database in a temporary directory, removed when the
+/// `TempDir` is dropped.
+#[cfg(test)]
+pub(crate) fn open_temp() -> (tempfile::TempDir, DbPool) {
+    let dir = tempfile::tempdir().unwrap();
+    let pool = DbPool::open_in(dir.path()).unwrap();
+    (dir, pool)
+}
@@ -0,0 +1,306 @@
+use super::{migrations, Database};
+use crate::error::{AppError, AppResult};
+use rusqlite::{params, Connection, DatabaseName, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_RETENTION: u32 = 10;
+const FILE_PREFIX: &str = "spoolsync-";
+/// Columns that have held printer access codes across schema versions.
+const ACCESS_CODE_COLUMNS: &[(&str, &str)] = &[("settings", "printer_access_code"), ("printers", "access_code")];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BackupInfo {
+    pub file_name: String,
+    /// Why the backup was taken: `startup`, `pre-v3`, `pre-import`, `manual`, …
+    pub reason: String,
+    pub created_at: u64,
+    pub size_bytes: u64,
+}
+
+fn io_error(context: &str, e: std::io::Error) -> AppError {
+    AppError { message: format!("{}: {}", context, e), ..e.into() }
+}
+
+/// Copies the live database into `backup_dir` with SQLite's online backup
+/// API, so readers and writers on `conn` are never blocked for long. Access
+/// codes not yet moved into secret storage are blanked in the copy.
+pub fn create(conn: &Connection, backup_dir: &Path, reason: &str) -> AppResult<PathBuf> {
+    std::fs::create_dir_all(backup_dir).map_err(|e| io_error("Failed to create dir", e))?;
+
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    let backup_path = backup_dir.join(format!("{}{}-{}.db", FILE_PREFIX, reason, timestamp));
+
+    conn.backup(DatabaseName::Main, &backup_path, None)?;
+    redact_access_codes(&backup_path)?;
+    Ok(backup_path)
+}
+
+/// Blanks every access code in a backup file that is not a secret handle,
+/// so no backup keeps one in plaintext; restoring it means entering the
+/// code again. `secure_delete` zeroes the old values rather than leaving
+/// them in free space. Returns whether anything was blanked.
+fn redact_access_codes(path: &Path) -> AppResult<bool> {
+    let conn = Connection::open(path)?;
+    conn.pragma_update(None, "secure_delete", true)?;
+
+    let mut redacted = false;
+    for (table, column) in ACCESS_CODE_COLUMNS {
+        let exists: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info(?1) WHERE name = ?2",
+            params![table, column],
+            |row| row.get(0),
+        )?;
+        if exists {
+            redacted |= conn.execute(
+                &format!(
+                    "UPDATE {0} SET {1} = '' WHERE {1} != '' AND {1} NOT LIKE 'secret:%'",
+                    table, column
+                ),
+                [],
+            )? > 0;
+        }
+    }
+    Ok(redacted)
+}
+
+/// Runs `redact_access_codes` over every backup, for those taken before
+/// backups were redacted. Returns how many had plaintext codes.
+pub fn redact_all(backup_dir: &Path) -> AppResult<usize> {
+    let mut redacted = 0;
+    for backup in list(backup_dir)? {
+        if redact_access_codes(&backup_dir.join(&backup.file_name))? {
+            redacted += 1;
+        }
+    }
+    Ok(redacted)
+}
+
+pub fn list(backup_dir: &Path) -> AppResult<Vec<BackupInfo>> {
+    if !backup_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut backups = Vec::new();
+    for entry in std::fs::read_dir(backup_dir).map_err(|e| io_error("Failed to read backups", e))? {
+        let entry = entry.map_err(|e| io_error("Failed to read backups", e))?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let Some((reason, stamp)) = file_name
+            .strip_prefix(FILE_PREFIX)
+            .and_then(|name| name.strip_suffix(".db"))
+            .and_then(|stem| stem.rsplit_once('-'))
+        else {
+            continue;
+        };
+        // Backups taken before rotation existed carry a seconds timestamp.
+        let Ok(stamp) = stamp.parse::<u128>() else {
+            continue;
+        };
+        let millis = if stamp < 100_000_000_000 { stamp * 1000 } else { stamp };
+
+        let metadata = entry.metadata().map_err(|e| io_error("Failed to read backups", e))?;
+        backups.push((
+            millis,
+            BackupInfo {
+                reason: reason.to_string(),
+                created_at: (millis / 1000) as u64,
+                size_bytes: metadata.len(),
+                file_name,
+            },
+        ));
+    }
+
+    backups.sort_by_key(|(millis, _)| std::cmp::Reverse(*millis));
+    Ok(backups.into_iter().map(|(_, backup)| backup).collect())
+}
+
+/// Deletes all but the newest `keep` backups of each reason, so the backup
+/// taken on every start never rotates out a `pre-v3` or `pre-import` one.
+pub fn prune(backup_dir: &Path, keep: u32) -> AppResult<usize> {
+    let mut kept: HashMap<String, u32> = HashMap::new();
+    let mut removed = 0;
+    for backup in list(backup_dir)? {
+        let count = kept.entry(backup.reason.clone()).or_default();
+        if *count < keep.max(1) {
+            *count += 1;
+            continue;
+        }
+        std::fs::remove_file(backup_dir.join(&backup.file_name))
+            .map_err(|e| io_error("Failed to remove backup", e))?;
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+/// Opens a backup read-only and checks it is an intact SpoolSync database
+/// this version can open. Returns its schema version.
+pub fn verify(path: &Path) -> AppResult<i64> {
+    let conn = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+
+    let integrity: String = conn.query_row("PRAGMA integrity_check", [], |row| row.get(0))?;
+    if integrity != "ok" {
+        return Err(AppError::invalid(format!(
+            "Backup failed integrity check: {}",
+            integrity
+        )));
+    }
+
+    let has_profiles: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = 'filament_profiles'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_profiles {
+        return Err(AppError::invalid("Backup is not a SpoolSync database"));
+    }
+
+    let version = migrations::current_version(&conn)?;
+    if version > migrations::latest_version() {
+        return Err(AppError::invalid(format!(
+            "Backup schema v{} is newer than this app supports (v{})",
+            version,
+            migrations::latest_version()
+        )));
+    }
+
+    Ok(version)
+}
+
+impl Database {
+    pub fn backup_retention(&self) -> AppResult<u32> {
+        Ok(self
+            .conn
+            .query_row("SELECT backup_retention FROM settings WHERE id = 1", [], |row| row.get(0))?)
+    }
+
+    /// Takes a backup and rotates old ones out according to the retention setting.
+    pub fn create_backup(&self, reason: &str) -> AppResult<BackupInfo> {
+        let path = create(&self.conn, &self.backup_dir, reason)?;
+        prune(&self.backup_dir, self.backup_retention()?)?;
+        println!("💾 Backup ({}): {:?}", reason, path);
+
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        list(&self.backup_dir)?
+            .into_iter()
+            .find(|backup| backup.file_name == file_name)
+            .ok_or_else(|| AppError::not_found(format!("Backup {} disappeared", file_name)))
+    }
+
+    pub fn list_backups(&self) -> AppResult<Vec<BackupInfo>> {
+        list(&self.backup_dir)
+    }
+
+}
+
+/// Replaces the database behind `conn` with a verified backup. The current
+/// state is backed up first, and older backups are migrated after the swap.
+/// `conn` must be the only open connection; see `DbPool::restore_backup`.
+pub fn restore(conn: &mut Connection, backup_dir: &Path, file_name: &str) -> AppResult<()> {
+    let backup = list(backup_dir)?
+        .into_iter()
+        .find(|backup| backup.file_name == file_name)
+        .ok_or_else(|| AppError::not_found(format!("Unknown backup '{}'", file_name)))?;
+    let path = backup_dir.join(&backup.file_name);
+
+    let version = verify(&path)?;
+    // Rotate only after the swap so the chosen backup cannot be pruned away.
+    create(conn, backup_dir, "pre-restore")?;
+
+    conn.restore(DatabaseName::Main, &path, None::<fn(rusqlite::backup::Progress)>)?;
+    println!("♻️  Restored backup {} (schema v{})", backup.file_name, version);
+
+    migrations::run(conn, backup_dir)?;
+    let retention = conn.query_row("SELECT backup_retention FROM settings WHERE id = 1", [], |row| row.get(0))?;
+    prune(backup_dir, retention)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{open_temp, Printer};
+    use super::{list, prune, redact_all};
+    use rusqlite::{Connection, DatabaseName};
+
+    const CODE: &str = "31415926";
+
+    fn printer(name: &str, access_code: &str) -> Printer {
+        serde_json::from_value(serde_json::json!({
+            "name": name,
+            "ip_address": "192.168.1.20",
+            "access_code": access_code,
+            "serial_number": "00M00A000000000"
+        }))
+        .unwrap()
+    }
+
+    fn codes(path: &std::path::Path) -> Vec<String> {
+        let conn = Connection::open(path).unwrap();
+        let mut codes: Vec<String> = conn
+            .prepare("SELECT access_code FROM printers ORDER BY name")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<rusqlite::Result<_>>()
+            .unwrap();
+        codes.push(conn.query_row("SELECT printer_access_code FROM settings", [], |row| row.get(0)).unwrap());
+        codes
+    }
+
+    fn contains_code(path: &std::path::Path) -> bool {
+        let bytes = std::fs::read(path).unwrap();
+        bytes.windows(CODE.len()).any(|window| window == CODE.as_bytes())
+    }
+
+    #[test]
+    fn backups_never_keep_plaintext_access_codes() {
+        let (_dir, pool) = open_temp();
+        let db = pool.get().unwrap();
+        db.create_printer(printer("A1", CODE)).unwrap();
+        db.create_printer(printer("X1C", "secret:6d1c")).unwrap();
+        db.set_settings_access_code(CODE).unwrap();
+
+        let backup = db.create_backup("manual").unwrap();
+        let path = db.backup_dir.join(&backup.file_name);
+        assert_eq!(codes(&path), ["", "secret:6d1c", ""]);
+        assert!(!contains_code(&path));
+
+        // A copy taken before backups were redacted.
+        let old = db.backup_dir.join("spoolsync-manual-1700000000.db");
+        db.conn.backup(DatabaseName::Main, &old, None).unwrap();
+        assert!(contains_code(&old));
+        assert_eq!(redact_all(&db.backup_dir).unwrap(), 1);
+        assert_eq!(codes(&old), ["", "secret:6d1c", ""]);
+        assert!(!contains_code(&old));
+        assert_eq!(redact_all(&db.backup_dir).unwrap(), 0);
+    }
+
+    #[test]
+    fn startup_backups_do_not_rotate_out_other_reasons() {
+        let dir = tempfile::tempdir().unwrap();
+        let backup = |reason: &str, stamp: u64| {
+            std::fs::write(dir.path().join(format!("spoolsync-{}-{}.db", reason, stamp)), b"").unwrap();
+        };
+        backup("pre-v3", 1_700_000_000_000);
+        backup("pre-import", 1_700_000_001_000);
+        for stamp in 0..5 {
+            backup("startup", 1_700_000_002_000 + stamp);
+        }
+
+        assert_eq!(prune(dir.path(), 2).unwrap(), 3);
+        let mut left: Vec<_> = list(dir.path()).unwrap().into_iter().map(|b| b.file_name).collect();
+        left.sort();
+        assert_eq!(
+            left,
+            [
+                "spoolsync-pre-import-1700000001000.db",
+                "spoolsync-pre-v3-1700000000000.db",
+                "spoolsync-startup-1700000002003.db",
+                "spoolsync-startup-1700000002004.db",
+            ]
+        );
+    }
+}
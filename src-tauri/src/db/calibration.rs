@@ -0,0 +1,262 @@
+use super::Database;
+use crate::error::{AppError, AppResult};
+use rusqlite::{params, Result, Row};
+use serde::{Deserialize, Serialize};
+
+/// Nozzle diameter assumed when the caller does not know it, in mm.
+pub const DEFAULT_NOZZLE_DIAMETER: f64 = 0.4;
+/// Two diameters closer than this are the same nozzle.
+const DIAMETER_TOLERANCE: f64 = 0.01;
+
+/// One calibration session for a profile on a printer and nozzle. Only the
+/// values that were measured are set.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CalibrationResult {
+    pub id: Option<i64>,
+    pub profile_id: i64,
+    /// `None` applies to every printer.
+    #[serde(default)]
+    pub printer_id: Option<i64>,
+    #[serde(default = "default_nozzle_diameter")]
+    pub nozzle_diameter: f64,
+    /// e.g. "hardened_steel"; `None` applies to every nozzle type.
+    #[serde(default)]
+    pub nozzle_type: Option<String>,
+    #[serde(default)]
+    pub flow_ratio: Option<f64>,
+    /// Pressure advance, the K-value the AMS tray is set to.
+    #[serde(default)]
+    pub pressure_advance: Option<f64>,
+    /// mm³/s
+    #[serde(default)]
+    pub max_volumetric_speed: Option<f64>,
+    /// Best temperature from a temperature tower, °C.
+    #[serde(default)]
+    pub nozzle_temp: Option<i32>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    /// UTC, `YYYY-MM-DD HH:MM:SS`; defaults to now.
+    #[serde(default)]
+    pub calibrated_at: Option<String>,
+}
+
+fn default_nozzle_diameter() -> f64 {
+    DEFAULT_NOZZLE_DIAMETER
+}
+
+/// The values to use for a profile on a printer and nozzle. Each value
+/// comes from the most specific matching result that measured it, newest
+/// first among equally specific ones.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct EffectiveCalibration {
+    pub flow_ratio: Option<f64>,
+    pub pressure_advance: Option<f64>,
+    pub max_volumetric_speed: Option<f64>,
+    pub nozzle_temp: Option<i32>,
+}
+
+const CALIBRATION_COLUMNS: &str = "id, profile_id, printer_id, nozzle_diameter, nozzle_type, flow_ratio, \
+    pressure_advance, max_volumetric_speed, nozzle_temp, notes, calibrated_at";
+
+fn row_to_calibration(row: &Row) -> Result<CalibrationResult> {
+    Ok(CalibrationResult {
+        id: Some(row.get(0)?),
+        profile_id: row.get(1)?,
+        printer_id: row.get(2)?,
+        nozzle_diameter: row.get(3)?,
+        nozzle_type: row.get(4)?,
+        flow_ratio: row.get(5)?,
+        pressure_advance: row.get(6)?,
+        max_volumetric_speed: row.get(7)?,
+        nozzle_temp: row.get(8)?,
+        notes: row.get(9)?,
+        calibrated_at: row.get(10)?,
+    })
+}
+
+fn check_calibration(result: &mut CalibrationResult) -> AppResult<()> {
+    result.nozzle_type = result
+        .nozzle_type
+        .as_deref()
+        .map(|t| t.trim().to_lowercase())
+        .filter(|t| !t.is_empty());
+
+    let problem = if !(0.1..=2.0).contains(&result.nozzle_diameter) {
+        Some(format!("Nozzle diameter {} mm is out of range", result.nozzle_diameter))
+    } else if result.flow_ratio.is_some_and(|ratio| !(0.5..=1.5).contains(&ratio)) {
+        Some("Flow ratio must be between 0.5 and 1.5".to_string())
+    } else if result.pressure_advance.is_some_and(|k| !(0.0..=2.0).contains(&k)) {
+        Some("Pressure advance must be between 0 and 2".to_string())
+    } else if result.max_volumetric_speed.is_some_and(|speed| speed <= 0.0 || speed > 100.0) {
+        Some("Max volumetric speed must be between 0 and 100 mm³/s".to_string())
+    } else if result.nozzle_temp.is_some_and(|temp| !(150..=450).contains(&temp)) {
+        Some("Nozzle temperature must be between 150 and 450 °C".to_string())
+    } else {
+        None
+    };
+    match problem {
+        Some(message) => Err(AppError::invalid(message)),
+        None => Ok(()),
+    }
+}
+
+impl Database {
+    pub fn add_calibration_result(&self, mut result: CalibrationResult) -> AppResult<i64> {
+        check_calibration(&mut result)?;
+        self.conn.execute(
+            "INSERT INTO calibration_results (profile_id, printer_id, nozzle_diameter, nozzle_type, flow_ratio,
+                pressure_advance, max_volumetric_speed, nozzle_temp, notes, calibrated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, COALESCE(?10, datetime('now')))",
+            params![
+                result.profile_id,
+                result.printer_id,
+                result.nozzle_diameter,
+                result.nozzle_type,
+                result.flow_ratio,
+                result.pressure_advance,
+                result.max_volumetric_speed,
+                result.nozzle_temp,
+                result.notes,
+                result.calibrated_at,
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn update_calibration_result(&self, mut result: CalibrationResult) -> AppResult<()> {
+        let id = result
+            .id
+            .ok_or_else(|| AppError::invalid("Calibration result has no id"))?;
+        check_calibration(&mut result)?;
+        let updated = self.conn.execute(
+            "UPDATE calibration_results SET profile_id = ?1, printer_id = ?2, nozzle_diameter = ?3,
+                nozzle_type = ?4, flow_ratio = ?5, pressure_advance = ?6, max_volumetric_speed = ?7,
+                nozzle_temp = ?8, notes = ?9, calibrated_at = COALESCE(?10, calibrated_at)
+             WHERE id = ?11",
+            params![
+                result.profile_id,
+                result.printer_id,
+                result.nozzle_diameter,
+                result.nozzle_type,
+                result.flow_ratio,
+                result.pressure_advance,
+                result.max_volumetric_speed,
+                result.nozzle_temp,
+                result.notes,
+                result.calibrated_at,
+                id,
+            ],
+        )?;
+        if updated == 0 {
+            return Err(AppError::not_found(format!("No calibration result with id {}", id)));
+        }
+        Ok(())
+    }
+
+    pub fn delete_calibration_result(&self, id: i64) -> AppResult<()> {
+        self.conn.execute("DELETE FROM calibration_results WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    /// Newest first.
+    pub fn get_calibration_results(&self, profile_id: i64) -> AppResult<Vec<CalibrationResult>> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {} FROM calibration_results WHERE profile_id = ?1
+             ORDER BY calibrated_at DESC, id DESC",
+            CALIBRATION_COLUMNS
+        ))?;
+        let results = stmt
+            .query_map(params![profile_id], row_to_calibration)?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(results)
+    }
+
+    /// Merges the results that apply to `printer_id` and a nozzle of
+    /// `nozzle_diameter`. Results for this printer beat printer-agnostic
+    /// ones, and results for `nozzle_type` beat type-agnostic ones; with no
+    /// `nozzle_type` given, results of every type apply.
+    pub fn get_effective_calibration(
+        &self,
+        profile_id: i64,
+        printer_id: Option<i64>,
+        nozzle_diameter: f64,
+        nozzle_type: Option<&str>,
+    ) -> AppResult<EffectiveCalibration> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {} FROM calibration_results
+             WHERE profile_id = ?1
+               AND (printer_id IS NULL OR printer_id = ?2)
+               AND ABS(nozzle_diameter - ?3) < ?4
+               AND (nozzle_type IS NULL OR ?5 IS NULL OR nozzle_type = ?5)
+             ORDER BY printer_id IS NULL, nozzle_type IS NULL, calibrated_at DESC, id DESC",
+            CALIBRATION_COLUMNS
+        ))?;
+        let results = stmt
+            .query_map(
+                params![profile_id, printer_id, nozzle_diameter, DIAMETER_TOLERANCE, nozzle_type],
+                row_to_calibration,
+            )?
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut effective = EffectiveCalibration::default();
+        for result in results {
+            effective.flow_ratio = effective.flow_ratio.or(result.flow_ratio);
+            effective.pressure_advance = effective.pressure_advance.or(result.pressure_advance);
+            effective.max_volumetric_speed = effective.max_volumetric_speed.or(result.max_volumetric_speed);
+            effective.nozzle_temp = effective.nozzle_temp.or(result.nozzle_temp);
+        }
+        Ok(effective)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{open_temp, FilamentProfile};
+    use super::CalibrationResult;
+
+    fn result(profile_id: i64, diameter: f64, nozzle_type: &str, k: f64) -> CalibrationResult {
+        CalibrationResult {
+            id: None,
+            profile_id,
+            printer_id: None,
+            nozzle_diameter: diameter,
+            nozzle_type: Some(nozzle_type.to_string()),
+            flow_ratio: None,
+            pressure_advance: Some(k),
+            max_volumetric_speed: None,
+            nozzle_temp: None,
+            notes: None,
+            calibrated_at: None,
+        }
+    }
+
+    #[test]
+    fn lookup_matches_reported_nozzle() {
+        let (_dir, pool) = open_temp();
+        let db = pool.get().unwrap();
+        let profile_id = db
+            .add_favorite(FilamentProfile {
+                brand: "Bambu Lab".into(),
+                material: "PLA-CF".into(),
+                color: "#000000".into(),
+                nozzle_temp: 220,
+                bed_temp: 60,
+                density: 1.22,
+                diameter: 1.75,
+                ..Default::default()
+            })
+            .unwrap();
+        db.add_calibration_result(result(profile_id, 0.4, "stainless_steel", 0.02)).unwrap();
+        db.add_calibration_result(result(profile_id, 0.6, "stainless_steel", 0.03)).unwrap();
+        db.add_calibration_result(result(profile_id, 0.6, "hardened_steel", 0.04)).unwrap();
+
+        let k = |diameter: f32, nozzle_type: &str| {
+            db.get_effective_calibration(profile_id, Some(1), f64::from(diameter), Some(nozzle_type))
+                .unwrap()
+                .pressure_advance
+        };
+        assert_eq!(k(0.6, "hardened_steel"), Some(0.04));
+        assert_eq!(k(0.6, "stainless_steel"), Some(0.03));
+        assert_eq!(k(0.4, "hardened_steel"), None);
+    }
+}
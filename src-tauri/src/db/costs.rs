@@ -0,0 +1,571 @@
+use super::Database;
+use crate::error::{AppError, AppResult};
+use rusqlite::{params, Result, Row};
+use serde::{Deserialize, Serialize};
+
+/// Where a usage figure came from.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum UsageSource {
+    #[default]
+    #[serde(rename = "manual")]
+    Manual,
+    #[serde(rename = "3mf")]
+    ThreeMf,
+    /// Remain % the AMS reported dropping while the spool was loaded.
+    #[serde(rename = "ams")]
+    Ams,
+}
+
+impl UsageSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UsageSource::Manual => "manual",
+            UsageSource::ThreeMf => "3mf",
+            UsageSource::Ams => "ams",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "3mf" => UsageSource::ThreeMf,
+            "ams" => UsageSource::Ams,
+            _ => UsageSource::Manual,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceBasis {
+    /// Purchase price of the spool divided by its initial net weight.
+    Spool,
+    /// The profile's default price per kg.
+    Profile,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CostEstimate {
+    pub grams: f64,
+    pub price_per_kg: Option<f64>,
+    /// `None` when neither the spool nor the profile has a price.
+    pub cost: Option<f64>,
+    pub currency: Option<String>,
+    pub basis: Option<PriceBasis>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CostTotal {
+    pub currency: Option<String>,
+    pub cost: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JobUsage {
+    pub id: i64,
+    pub job_id: i64,
+    pub spool_id: Option<i64>,
+    pub profile_id: Option<i64>,
+    pub grams: f64,
+    pub price_per_kg: Option<f64>,
+    pub cost: Option<f64>,
+    pub currency: Option<String>,
+    pub source: UsageSource,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JobUsageInput {
+    #[serde(default)]
+    pub spool_id: Option<i64>,
+    #[serde(default)]
+    pub profile_id: Option<i64>,
+    pub grams: f64,
+    #[serde(default)]
+    pub source: UsageSource,
+    /// Also subtract the grams from the spool's remaining weight.
+    #[serde(default)]
+    pub deduct_from_spool: bool,
+}
+
+/// Filament the AMS reported as used from one tray, not yet added to a job.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AmsUsage {
+    pub id: i64,
+    pub printer_id: i64,
+    pub ams_id: i32,
+    pub tray_id: i32,
+    pub spool_id: Option<i64>,
+    pub profile_id: Option<i64>,
+    pub grams: f64,
+    pub recorded_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PrintJob {
+    pub id: Option<i64>,
+    pub name: String,
+    #[serde(default)]
+    pub client: Option<String>,
+    #[serde(default)]
+    pub printer_id: Option<i64>,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub total_grams: f64,
+    /// Summed cost per currency; usage without a known price only counts
+    /// towards `total_grams`.
+    #[serde(default)]
+    pub totals: Vec<CostTotal>,
+    #[serde(default)]
+    pub usage: Vec<JobUsage>,
+}
+
+const JOB_COLUMNS: &str = "id, name, client, printer_id, notes, created_at";
+const USAGE_COLUMNS: &str = "id, job_id, spool_id, profile_id, grams, price_per_kg, cost, currency, source, created_at";
+
+fn row_to_job(row: &Row) -> Result<PrintJob> {
+    Ok(PrintJob {
+        id: Some(row.get(0)?),
+        name: row.get(1)?,
+        client: row.get(2)?,
+        printer_id: row.get(3)?,
+        notes: row.get(4)?,
+        created_at: row.get(5)?,
+        total_grams: 0.0,
+        totals: Vec::new(),
+        usage: Vec::new(),
+    })
+}
+
+fn row_to_usage(row: &Row) -> Result<JobUsage> {
+    Ok(JobUsage {
+        id: row.get(0)?,
+        job_id: row.get(1)?,
+        spool_id: row.get(2)?,
+        profile_id: row.get(3)?,
+        grams: row.get(4)?,
+        price_per_kg: row.get(5)?,
+        cost: row.get(6)?,
+        currency: row.get(7)?,
+        source: UsageSource::parse(&row.get::<_, String>(8)?),
+        created_at: row.get(9)?,
+    })
+}
+
+impl Database {
+    /// Prices `grams` of filament from the spool's purchase price, falling
+    /// back to the default price of the spool's (or the given) profile.
+    pub fn estimate_cost(
+        &self,
+        grams: f64,
+        spool_id: Option<i64>,
+        profile_id: Option<i64>,
+    ) -> AppResult<CostEstimate> {
+        let spool = spool_id.map(|id| self.get_spool(id)).transpose()?;
+
+        if let Some(spool) = &spool {
+            if let Some(price) = spool.purchase_price.filter(|_| spool.initial_weight > 0.0) {
+                let price_per_kg = price / spool.initial_weight * 1000.0;
+                return Ok(CostEstimate {
+                    grams,
+                    price_per_kg: Some(price_per_kg),
+                    cost: Some(price_per_kg * grams / 1000.0),
+                    currency: spool.currency.clone(),
+                    basis: Some(PriceBasis::Spool),
+                });
+            }
+        }
+
+        let profile_id = spool.and_then(|spool| spool.profile_id).or(profile_id);
+        let profile = profile_id.map(|id| self.get_profile(id)).transpose()?;
+        match profile.and_then(|profile| profile.price_per_kg.map(|price| (price, profile.currency))) {
+            Some((price_per_kg, currency)) => Ok(CostEstimate {
+                grams,
+                price_per_kg: Some(price_per_kg),
+                cost: Some(price_per_kg * grams / 1000.0),
+                currency,
+                basis: Some(PriceBasis::Profile),
+            }),
+            None => Ok(CostEstimate {
+                grams,
+                price_per_kg: None,
+                cost: None,
+                currency: None,
+                basis: None,
+            }),
+        }
+    }
+
+    fn fill_job_totals(&self, job: &mut PrintJob) -> AppResult<()> {
+        let id = job.id.unwrap_or_default();
+        job.total_grams = self.conn.query_row(
+            "SELECT COALESCE(SUM(grams), 0) FROM print_job_usage WHERE job_id = ?1",
+            params![id],
+            |row| row.get(0),
+        )?;
+
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT currency, SUM(cost) FROM print_job_usage WHERE job_id = ?1 AND cost IS NOT NULL
+             GROUP BY currency ORDER BY currency",
+        )?;
+        job.totals = stmt
+            .query_map(params![id], |row| {
+                Ok(CostTotal {
+                    currency: row.get(0)?,
+                    cost: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(())
+    }
+
+    /// Jobs newest first, with totals but without their usage lines.
+    pub fn get_print_jobs(&self) -> AppResult<Vec<PrintJob>> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {} FROM print_jobs ORDER BY created_at DESC, id DESC",
+            JOB_COLUMNS
+        ))?;
+        let mut jobs = stmt.query_map([], row_to_job)?.collect::<Result<Vec<_>>>()?;
+        drop(stmt);
+
+        for job in &mut jobs {
+            self.fill_job_totals(job)?;
+        }
+        Ok(jobs)
+    }
+
+    pub fn get_print_job(&self, id: i64) -> AppResult<PrintJob> {
+        let mut job = self.conn.query_row(
+            &format!("SELECT {} FROM print_jobs WHERE id = ?1", JOB_COLUMNS),
+            params![id],
+            row_to_job,
+        )?;
+        self.fill_job_totals(&mut job)?;
+
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {} FROM print_job_usage WHERE job_id = ?1 ORDER BY id",
+            USAGE_COLUMNS
+        ))?;
+        job.usage = stmt
+            .query_map(params![id], row_to_usage)?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(job)
+    }
+
+    pub fn create_print_job(&self, job: PrintJob) -> AppResult<i64> {
+        self.conn.execute(
+            "INSERT INTO print_jobs (name, client, printer_id, notes) VALUES (?1, ?2, ?3, ?4)",
+            params![job.name, job.client, job.printer_id, job.notes],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn update_print_job(&self, job: PrintJob) -> AppResult<()> {
+        self.conn.execute(
+            "UPDATE print_jobs SET name = ?1, client = ?2, printer_id = ?3, notes = ?4 WHERE id = ?5",
+            params![job.name, job.client, job.printer_id, job.notes, job.id],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_print_job(&self, id: i64) -> AppResult<()> {
+        self.conn.execute("DELETE FROM print_jobs WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    /// Costs a usage line at today's price and adds it to the job.
+    pub fn add_job_usage(&self, job_id: i64, usage: JobUsageInput) -> AppResult<i64> {
+        super::spools::check_usage_grams(usage.grams)?;
+        let estimate = self.estimate_cost(usage.grams, usage.spool_id, usage.profile_id)?;
+        let profile_id = match usage.spool_id {
+            Some(spool_id) => self.get_spool(spool_id)?.profile_id.or(usage.profile_id),
+            None => usage.profile_id,
+        };
+
+        if let (Some(spool_id), true) = (usage.spool_id, usage.deduct_from_spool) {
+            let job_name: String = self.conn.query_row(
+                "SELECT name FROM print_jobs WHERE id = ?1",
+                params![job_id],
+                |row| row.get(0),
+            )?;
+            self.record_spool_usage(spool_id, usage.grams, Some(format!("job: {}", job_name)))?;
+        }
+
+        self.conn.execute(
+            "INSERT INTO print_job_usage (job_id, spool_id, profile_id, grams, price_per_kg, cost, currency, source)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                job_id,
+                usage.spool_id,
+                profile_id,
+                usage.grams,
+                estimate.price_per_kg,
+                estimate.cost,
+                estimate.currency,
+                usage.source.as_str(),
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// AMS usage of a printer that has not been added to a job, oldest first.
+    pub fn get_ams_usage(&self, printer_id: i64) -> AppResult<Vec<AmsUsage>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT id, printer_id, ams_id, tray_id, spool_id, profile_id, grams, recorded_at
+             FROM ams_usage WHERE printer_id = ?1 AND claimed_at IS NULL ORDER BY id",
+        )?;
+        let usage = stmt
+            .query_map(params![printer_id], |row| {
+                Ok(AmsUsage {
+                    id: row.get(0)?,
+                    printer_id: row.get(1)?,
+                    ams_id: row.get(2)?,
+                    tray_id: row.get(3)?,
+                    spool_id: row.get(4)?,
+                    profile_id: row.get(5)?,
+                    grams: row.get(6)?,
+                    recorded_at: row.get(7)?,
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(usage)
+    }
+
+    /// Adds the unclaimed AMS usage of the job's printer to the job, one
+    /// line per spool (or profile, for a tray without a spool), and
+    /// deducts it from the spool. Returns the new usage line ids.
+    pub fn claim_ams_usage(&self, job_id: i64) -> AppResult<Vec<i64>> {
+        let printer_id: Option<i64> = self.conn.query_row(
+            "SELECT printer_id FROM print_jobs WHERE id = ?1",
+            params![job_id],
+            |row| row.get(0),
+        )?;
+        let printer_id = printer_id.ok_or_else(|| AppError::invalid("Set the job's printer to add its AMS usage"))?;
+
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT spool_id, profile_id, SUM(grams), MAX(id) FROM ams_usage
+             WHERE printer_id = ?1 AND claimed_at IS NULL GROUP BY spool_id, profile_id ORDER BY MIN(id)",
+        )?;
+        let groups = stmt
+            .query_map(params![printer_id], |row| {
+                Ok((
+                    row.get::<_, Option<i64>>(0)?,
+                    row.get::<_, Option<i64>>(1)?,
+                    row.get::<_, f64>(2)?,
+                    row.get::<_, i64>(3)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        drop(stmt);
+
+        let mut ids = Vec::new();
+        for (spool_id, profile_id, grams, last_id) in groups {
+            ids.push(self.add_job_usage(
+                job_id,
+                JobUsageInput {
+                    spool_id,
+                    profile_id,
+                    grams,
+                    source: UsageSource::Ams,
+                    deduct_from_spool: spool_id.is_some(),
+                },
+            )?);
+            self.conn.execute(
+                "UPDATE ams_usage SET claimed_at = datetime('now')
+                 WHERE printer_id = ?1 AND spool_id IS ?2 AND profile_id IS ?3 AND id <= ?4 AND claimed_at IS NULL",
+                params![printer_id, spool_id, profile_id, last_id],
+            )?;
+        }
+        Ok(ids)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{open_temp, Database, FilamentProfile, Printer, Spool};
+    use super::{JobUsageInput, PriceBasis, PrintJob, UsageSource};
+    use crate::mqtt::{AMSStatus, AMSTrayInfo, FilamentSyncCommand};
+
+    fn profile(db: &Database, price_per_kg: Option<f64>, currency: Option<&str>) -> i64 {
+        db.add_favorite(FilamentProfile {
+            brand: "Bambu Lab".into(),
+            material: "PLA".into(),
+            color: "#FFFFFF".into(),
+            nozzle_temp: 220,
+            bed_temp: 60,
+            density: 1.24,
+            diameter: 1.75,
+            price_per_kg,
+            currency: currency.map(String::from),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn spool(db: &Database, profile_id: Option<i64>, purchase_price: Option<f64>, currency: Option<&str>) -> i64 {
+        db.create_spool(Spool {
+            id: None,
+            profile_id,
+            spoolman_id: None,
+            initial_weight: 800.0,
+            remaining_weight: 800.0,
+            lot_number: None,
+            purchase_date: None,
+            opened_date: None,
+            location: None,
+            notes: None,
+            purchase_price,
+            currency: currency.map(String::from),
+            printer_id: None,
+            ams_id: None,
+            tray_id: None,
+            created_at: None,
+        })
+        .unwrap()
+    }
+
+    fn job(db: &Database, printer_id: Option<i64>) -> i64 {
+        db.create_print_job(PrintJob {
+            id: None,
+            name: "Bracket".into(),
+            client: None,
+            printer_id,
+            notes: None,
+            created_at: None,
+            total_grams: 0.0,
+            totals: Vec::new(),
+            usage: Vec::new(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn spool_price_is_spread_over_its_initial_weight() {
+        let (_dir, pool) = open_temp();
+        let db = pool.get().unwrap();
+        let profile_id = profile(&db, Some(30.0), Some("USD"));
+        let spool_id = spool(&db, Some(profile_id), Some(20.0), Some("EUR"));
+
+        let estimate = db.estimate_cost(40.0, Some(spool_id), None).unwrap();
+        assert_eq!(estimate.basis, Some(PriceBasis::Spool));
+        assert_eq!(estimate.price_per_kg, Some(25.0));
+        assert_eq!(estimate.cost, Some(1.0));
+        assert_eq!(estimate.currency.as_deref(), Some("EUR"));
+    }
+
+    #[test]
+    fn profile_price_is_the_fallback() {
+        let (_dir, pool) = open_temp();
+        let db = pool.get().unwrap();
+        let profile_id = profile(&db, Some(30.0), Some("USD"));
+        let spool_id = spool(&db, Some(profile_id), None, Some("EUR"));
+
+        for estimate in [
+            db.estimate_cost(50.0, Some(spool_id), None).unwrap(),
+            db.estimate_cost(50.0, None, Some(profile_id)).unwrap(),
+        ] {
+            assert_eq!(estimate.basis, Some(PriceBasis::Profile));
+            assert_eq!(estimate.price_per_kg, Some(30.0));
+            assert_eq!(estimate.cost, Some(1.5));
+            assert_eq!(estimate.currency.as_deref(), Some("USD"));
+        }
+
+        let unpriced = profile(&db, None, None);
+        let estimate = db.estimate_cost(50.0, None, Some(unpriced)).unwrap();
+        assert_eq!((estimate.basis, estimate.cost, estimate.currency), (None, None, None));
+    }
+
+    #[test]
+    fn job_totals_are_kept_per_currency() {
+        let (_dir, pool) = open_temp();
+        let db = pool.get().unwrap();
+        let eur = spool(&db, None, Some(20.0), Some("EUR"));
+        let usd = profile(&db, Some(30.0), Some("USD"));
+        let job_id = job(&db, None);
+
+        for (spool_id, profile_id) in [(Some(eur), None), (Some(eur), None), (None, Some(usd))] {
+            let usage = JobUsageInput {
+                spool_id,
+                profile_id,
+                grams: 40.0,
+                source: UsageSource::ThreeMf,
+                deduct_from_spool: true,
+            };
+            db.add_job_usage(job_id, usage).unwrap();
+        }
+
+        let job = db.get_print_job(job_id).unwrap();
+        assert_eq!(job.total_grams, 120.0);
+        let totals: Vec<_> = job.totals.iter().map(|t| (t.currency.as_deref(), t.cost)).collect();
+        assert_eq!(totals, [(Some("EUR"), 2.0), (Some("USD"), 1.2)]);
+        assert!(job.usage.iter().all(|usage| usage.source == UsageSource::ThreeMf));
+        assert_eq!(db.get_spool(eur).unwrap().remaining_weight, 720.0);
+    }
+
+    #[test]
+    fn ams_remain_drop_is_claimed_into_a_job() {
+        let (_dir, pool) = open_temp();
+        let db = pool.get().unwrap();
+        let printer_id = db
+            .create_printer(serde_json::from_value::<Printer>(serde_json::json!({
+                "name": "X1C",
+                "ip_address": "192.168.1.20",
+                "access_code": "12345678",
+                "serial_number": "00M00A000000000"
+            }))
+            .unwrap())
+            .unwrap();
+        let spool_id = spool(&db, None, Some(20.0), Some("EUR"));
+        let command: FilamentSyncCommand = serde_json::from_value(serde_json::json!({
+            "slot_id": 0,
+            "brand": "Bambu Lab",
+            "material": "PLA",
+            "color": "FFFFFF",
+            "nozzle_temp": 220,
+            "bed_temp": 60,
+            "spool_id": spool_id
+        }))
+        .unwrap();
+        db.assign_tray(printer_id, &command).unwrap();
+
+        let report = |remain: u8, material: &str| {
+            vec![AMSStatus {
+                ams_id: 0,
+                extruder_id: 0,
+                trays: vec![AMSTrayInfo {
+                    tray_id: 0,
+                    tray_type: material.into(),
+                    tray_color: "FFFFFF".into(),
+                    nozzle_temp_min: 190,
+                    nozzle_temp_max: 230,
+                    tray_uuid: None,
+                    remain: Some(remain),
+                    tray_weight: None,
+                }],
+            }]
+        };
+        db.reconcile_trays(printer_id, &report(90, "PLA")).unwrap();
+        assert!(db.get_ams_usage(printer_id).unwrap().is_empty());
+
+        db.reconcile_trays(printer_id, &report(85, "PLA")).unwrap();
+        db.reconcile_trays(printer_id, &report(60, "PETG")).unwrap();
+        db.reconcile_trays(printer_id, &report(50, "PLA")).unwrap();
+        db.reconcile_trays(printer_id, &report(45, "PLA")).unwrap();
+        let pending = db.get_ams_usage(printer_id).unwrap();
+        assert_eq!(pending.len(), 2);
+        assert_eq!(pending[0].spool_id, Some(spool_id));
+        assert_eq!(pending[0].grams + pending[1].grams, 80.0);
+
+        let job_id = job(&db, Some(printer_id));
+        assert_eq!(db.claim_ams_usage(job_id).unwrap().len(), 1);
+        let job = db.get_print_job(job_id).unwrap();
+        assert_eq!(job.usage[0].source, UsageSource::Ams);
+        assert_eq!(job.usage[0].grams, 80.0);
+        assert_eq!(job.usage[0].cost, Some(2.0));
+        assert_eq!(db.get_spool(spool_id).unwrap().remaining_weight, 720.0);
+        assert!(db.get_ams_usage(printer_id).unwrap().is_empty());
+        assert!(db.claim_ams_usage(job_id).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+use super::Database;
+use crate::error::AppResult;
+use crate::materials::{drying_recommendation, DryingRecommendation};
+use rusqlite::{params, Result, Row};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DryingEntry {
+    pub id: Option<i64>,
+    pub spool_id: i64,
+    /// UTC, `YYYY-MM-DD HH:MM:SS`; defaults to now.
+    #[serde(default)]
+    pub dried_at: Option<String>,
+    #[serde(default)]
+    pub temperature: Option<f64>,
+    #[serde(default)]
+    pub duration_minutes: Option<i64>,
+    /// e.g. "dryer", "oven", "dry box".
+    #[serde(default)]
+    pub method: Option<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SpoolDryingStatus {
+    pub spool_id: i64,
+    pub material: Option<String>,
+    pub days_since_opened: Option<f64>,
+    pub days_since_dried: Option<f64>,
+    pub last_dried_at: Option<String>,
+    pub recommendation: Option<DryingRecommendation>,
+    /// Open (or undried) for longer than the material tolerates.
+    pub overdue: bool,
+    pub reason: Option<String>,
+}
+
+const DRYING_COLUMNS: &str = "id, spool_id, dried_at, temperature, duration_minutes, method, notes";
+
+fn row_to_drying(row: &Row) -> Result<DryingEntry> {
+    Ok(DryingEntry {
+        id: Some(row.get(0)?),
+        spool_id: row.get(1)?,
+        dried_at: row.get(2)?,
+        temperature: row.get(3)?,
+        duration_minutes: row.get(4)?,
+        method: row.get(5)?,
+        notes: row.get(6)?,
+    })
+}
+
+impl Database {
+    pub fn add_drying_entry(&self, entry: DryingEntry) -> AppResult<i64> {
+        self.conn.execute(
+            "INSERT INTO spool_drying (spool_id, dried_at, temperature, duration_minutes, method, notes)
+             VALUES (?1, COALESCE(?2, datetime('now')), ?3, ?4, ?5, ?6)",
+            params![
+                entry.spool_id,
+                entry.dried_at,
+                entry.temperature,
+                entry.duration_minutes,
+                entry.method,
+                entry.notes,
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Newest drying first.
+    pub fn get_drying_log(&self, spool_id: i64) -> AppResult<Vec<DryingEntry>> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {} FROM spool_drying WHERE spool_id = ?1 ORDER BY dried_at DESC, id DESC",
+            DRYING_COLUMNS
+        ))?;
+        let entries = stmt
+            .query_map(params![spool_id], row_to_drying)?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(entries)
+    }
+
+    pub fn delete_drying_entry(&self, id: i64) -> AppResult<()> {
+        self.conn.execute("DELETE FROM spool_drying WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    /// Exposure is counted from the later of opening and the last drying.
+    /// Spools with neither date are never reported overdue.
+    pub fn get_spool_drying_status(&self, spool_id: i64) -> AppResult<SpoolDryingStatus> {
+        let (material, days_since_opened, last_dried_at, days_since_dried): (
+            Option<String>,
+            Option<f64>,
+            Option<String>,
+            Option<f64>,
+        ) = self.conn.query_row(
+            "SELECT p.material,
+                    julianday('now') - julianday(s.opened_date),
+                    d.last_dried_at,
+                    julianday('now') - julianday(d.last_dried_at)
+             FROM spools s
+             LEFT JOIN filament_profiles p ON p.id = s.profile_id
+             LEFT JOIN (SELECT spool_id, MAX(dried_at) AS last_dried_at FROM spool_drying GROUP BY spool_id) d
+                ON d.spool_id = s.id
+             WHERE s.id = ?1",
+            params![spool_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+
+        let recommendation = material.as_deref().and_then(drying_recommendation);
+        let exposure = match (days_since_opened, days_since_dried) {
+            (Some(opened), Some(dried)) => Some(opened.min(dried)),
+            (opened, dried) => opened.or(dried),
+        };
+        let reason = match (&recommendation, exposure) {
+            (Some(rec), Some(days)) => rec
+                .max_open_days
+                .filter(|max| days > *max as f64)
+                .map(|max| {
+                    format!(
+                        "{} exposed for {:.0} days (max {}); dry at {}°C for {}h",
+                        rec.family, days, max, rec.temperature_c, rec.hours
+                    )
+                }),
+            _ => None,
+        };
+
+        Ok(SpoolDryingStatus {
+            spool_id,
+            material,
+            days_since_opened,
+            days_since_dried,
+            last_dried_at,
+            recommendation,
+            overdue: reason.is_some(),
+            reason,
+        })
+    }
+
+    /// Spools with filament left that are overdue for drying.
+    pub fn get_overdue_spools(&self) -> AppResult<Vec<SpoolDryingStatus>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached("SELECT id FROM spools WHERE remaining_weight > 0 ORDER BY id")?;
+        let ids = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<Vec<i64>>>()?;
+        drop(stmt);
+
+        let mut overdue = Vec::new();
+        for id in ids {
+            let status = self.get_spool_drying_status(id)?;
+            if status.overdue {
+                overdue.push(status);
+            }
+        }
+        Ok(overdue)
+    }
+}
@@ -0,0 +1,462 @@
+use crate::error::{AppError, AppResult, ErrorCode};
+use super::validation;
+use super::{row_to_profile, Database, FilamentProfile, PROFILE_COLUMNS};
+use rusqlite::{params, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+pub const EXCHANGE_FORMAT: &str = "spoolsync-profiles";
+pub const EXCHANGE_VERSION: u32 = 1;
+
+/// Shareable profile document. Field names are part of the format; add
+/// optional fields only, and bump `EXCHANGE_VERSION` for anything else.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProfileDocument {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: u64,
+    pub profiles: Vec<ProfileRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileKind {
+    Favorite,
+    Custom,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ProfileRecord {
+    pub kind: ProfileKind,
+    pub brand: String,
+    pub material: String,
+    pub color: String,
+    pub nozzle_temp: i32,
+    pub bed_temp: i32,
+    pub density: f64,
+    pub diameter: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spoolman_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color_hexes: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub nozzle_temp_range: Option<Vec<i32>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bed_temp_range: Option<Vec<i32>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub weight: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spool_weight: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spool_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub multi_color_direction: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    #[serde(default)]
+    pub translucent: bool,
+    #[serde(default)]
+    pub glow: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub price_per_kg: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+}
+
+impl From<&FilamentProfile> for ProfileRecord {
+    fn from(profile: &FilamentProfile) -> Self {
+        Self {
+            kind: if profile.is_custom { ProfileKind::Custom } else { ProfileKind::Favorite },
+            brand: profile.brand.clone(),
+            material: profile.material.clone(),
+            color: profile.color.clone(),
+            nozzle_temp: profile.nozzle_temp,
+            bed_temp: profile.bed_temp,
+            density: profile.density,
+            diameter: profile.diameter,
+            spoolman_id: profile.spoolman_id.clone(),
+            name: profile.name.clone(),
+            color_hexes: profile.color_hexes.clone(),
+            nozzle_temp_range: profile.nozzle_temp_range.clone(),
+            bed_temp_range: profile.bed_temp_range.clone(),
+            weight: profile.weight,
+            spool_weight: profile.spool_weight,
+            spool_type: profile.spool_type.clone(),
+            finish: profile.finish.clone(),
+            multi_color_direction: profile.multi_color_direction.clone(),
+            pattern: profile.pattern.clone(),
+            translucent: profile.translucent,
+            glow: profile.glow,
+            notes: profile.notes.clone(),
+            price_per_kg: profile.price_per_kg,
+            currency: profile.currency.clone(),
+        }
+    }
+}
+
+impl From<&ProfileRecord> for FilamentProfile {
+    fn from(record: &ProfileRecord) -> Self {
+        Self {
+            id: None,
+            brand: record.brand.clone(),
+            material: record.material.clone(),
+            color: record.color.clone(),
+            nozzle_temp: record.nozzle_temp,
+            bed_temp: record.bed_temp,
+            density: record.density,
+            diameter: record.diameter,
+            is_favorite: record.kind == ProfileKind::Favorite,
+            is_custom: record.kind == ProfileKind::Custom,
+            spoolman_id: record.spoolman_id.clone(),
+            name: record.name.clone(),
+            color_hexes: record.color_hexes.clone(),
+            nozzle_temp_range: record.nozzle_temp_range.clone(),
+            bed_temp_range: record.bed_temp_range.clone(),
+            weight: record.weight,
+            spool_weight: record.spool_weight,
+            spool_type: record.spool_type.clone(),
+            finish: record.finish.clone(),
+            multi_color_direction: record.multi_color_direction.clone(),
+            pattern: record.pattern.clone(),
+            translucent: record.translucent,
+            glow: record.glow,
+            notes: record.notes.clone(),
+            price_per_kg: record.price_per_kg,
+            currency: record.currency.clone(),
+        }
+    }
+}
+
+/// Which profiles to export. Tag and collection filters narrow `ids` (or
+/// the whole library when `ids` is unset).
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ExportSelection {
+    #[serde(default)]
+    pub ids: Option<Vec<i64>>,
+    /// Profiles carrying all of these tags.
+    #[serde(default)]
+    pub tag_ids: Option<Vec<i64>>,
+    #[serde(default)]
+    pub collection_id: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportOutcome {
+    Created,
+    Updated,
+    Skipped,
+    Rejected,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportRecordResult {
+    pub index: usize,
+    pub label: String,
+    pub outcome: ImportOutcome,
+    pub profile_id: Option<i64>,
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ImportReport {
+    pub created: usize,
+    pub updated: usize,
+    pub skipped: usize,
+    pub rejected: usize,
+    pub records: Vec<ImportRecordResult>,
+}
+
+fn record_label(record: &ProfileRecord) -> String {
+    match &record.name {
+        Some(name) if !name.is_empty() => format!("{} {} {}", record.brand, record.material, name),
+        _ => format!("{} {} {}", record.brand, record.material, record.color),
+    }
+}
+
+pub fn parse_document(json: &str) -> AppResult<ProfileDocument> {
+    let document: ProfileDocument = serde_json::from_str(json).map_err(|e| {
+        AppError::new(ErrorCode::Parse, format!("Invalid profile document: {}", e))
+    })?;
+
+    if document.format != EXCHANGE_FORMAT {
+        return Err(AppError::invalid(format!(
+            "Unsupported document format '{}', expected '{}'",
+            document.format, EXCHANGE_FORMAT
+        )));
+    }
+    if document.version == 0 || document.version > EXCHANGE_VERSION {
+        return Err(AppError::invalid(format!(
+            "Unsupported document version {} (this app reads up to {})",
+            document.version, EXCHANGE_VERSION
+        )));
+    }
+
+    Ok(document)
+}
+
+impl Database {
+    /// Builds an export document from the selected profiles, or from every
+    /// favorite and custom profile when nothing is selected. Selected ids
+    /// that are in the trash or gone are left out.
+    pub fn export_profiles(&self, selection: ExportSelection) -> AppResult<ProfileDocument> {
+        let mut profiles = match selection.ids {
+            Some(ids) => {
+                let mut stmt = self.conn.prepare_cached(&format!(
+                    "SELECT {} FROM filament_profiles WHERE id = ?1 AND deleted_at IS NULL",
+                    PROFILE_COLUMNS
+                ))?;
+                let mut profiles = Vec::new();
+                for id in ids {
+                    if let Some(profile) = stmt.query_row(params![id], row_to_profile).optional()? {
+                        profiles.push(profile);
+                    }
+                }
+                profiles
+            }
+            None => {
+                let mut profiles = self.get_favorites()?;
+                profiles.extend(self.get_custom_profiles()?.into_iter().filter(|p| !p.is_favorite));
+                profiles
+            }
+        };
+
+        if selection.tag_ids.is_some() || selection.collection_id.is_some() {
+            let matching =
+                self.filtered_profile_ids(selection.tag_ids.as_deref(), selection.collection_id)?;
+            profiles.retain(|profile| profile.id.is_some_and(|id| matching.contains(&id)));
+            profiles.sort_by_key(|profile| matching.iter().position(|id| Some(*id) == profile.id));
+        }
+
+        let exported_at = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+
+        Ok(ProfileDocument {
+            format: EXCHANGE_FORMAT.to_string(),
+            version: EXCHANGE_VERSION,
+            exported_at,
+            profiles: profiles.iter().map(ProfileRecord::from).collect(),
+        })
+    }
+
+    /// Finds the local profile an imported record corresponds to: the same
+    /// SpoolmanDB entry, or the same brand, material, name and color.
+    fn find_import_match(&self, record: &ProfileRecord) -> AppResult<Option<i64>> {
+        let kind_column = match record.kind {
+            ProfileKind::Favorite => "is_favorite",
+            ProfileKind::Custom => "is_custom",
+        };
+
+        if let Some(spoolman_id) = &record.spoolman_id {
+            let found = self
+                .conn
+                .query_row(
+                    &format!(
+                        "SELECT id FROM filament_profiles WHERE {} = 1 AND deleted_at IS NULL AND spoolman_id = ?1 ORDER BY id LIMIT 1",
+                        kind_column
+                    ),
+                    params![spoolman_id],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if found.is_some() {
+                return Ok(found);
+            }
+        }
+
+        Ok(self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT id FROM filament_profiles WHERE {} = 1 AND deleted_at IS NULL
+                     AND brand = ?1 COLLATE NOCASE AND material = ?2 COLLATE NOCASE
+                     AND COALESCE(name, '') = ?3 AND color = ?4 COLLATE NOCASE
+                     ORDER BY id LIMIT 1",
+                    kind_column
+                ),
+                params![
+                    record.brand,
+                    record.material,
+                    record.name.clone().unwrap_or_default(),
+                    record.color,
+                ],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Imports a document in one transaction, reporting the outcome of
+    /// every record. Rejected records never abort the rest of the import.
+    pub fn import_profiles(&self, document: &ProfileDocument) -> AppResult<ImportReport> {
+        let mut report = ImportReport::default();
+        let tx = self.conn.unchecked_transaction()?;
+
+        for (index, record) in document.profiles.iter().enumerate() {
+            let label = record_label(record);
+
+            // Favorites may lack temperatures SpoolmanDB never had.
+            let mut profile = FilamentProfile::from(record);
+            let checked = match record.kind {
+                ProfileKind::Custom => validation::validate_profile(&mut profile),
+                ProfileKind::Favorite => validation::validate_favorite(&mut profile),
+            };
+            if let Err(errors) = checked {
+                report.rejected += 1;
+                report.records.push(ImportRecordResult {
+                    index,
+                    label,
+                    outcome: ImportOutcome::Rejected,
+                    profile_id: None,
+                    reason: Some(errors.to_string()),
+                });
+                continue;
+            }
+            // Matched and compared as stored, with colors normalized.
+            let record = &ProfileRecord::from(&profile);
+
+            let (outcome, profile_id) = match self.find_import_match(record)? {
+                Some(id) => {
+                    let existing = self.get_profile(id)?;
+                    if ProfileRecord::from(&existing) == *record {
+                        (ImportOutcome::Skipped, id)
+                    } else {
+                        profile.id = Some(id);
+                        self.update_profile_fields(profile, Some("Imported"))?;
+                        (ImportOutcome::Updated, id)
+                    }
+                }
+                None => {
+                    let id = match record.kind {
+                        ProfileKind::Favorite => self.add_favorite(profile)?,
+                        ProfileKind::Custom => self.insert_profile(&profile, false, true)?,
+                    };
+                    (ImportOutcome::Created, id)
+                }
+            };
+
+            match outcome {
+                ImportOutcome::Created => report.created += 1,
+                ImportOutcome::Updated => report.updated += 1,
+                ImportOutcome::Skipped => report.skipped += 1,
+                ImportOutcome::Rejected => report.rejected += 1,
+            }
+            report.records.push(ImportRecordResult {
+                index,
+                label,
+                outcome,
+                profile_id: Some(profile_id),
+                reason: (outcome == ImportOutcome::Skipped).then(|| "duplicate".to_string()),
+            });
+        }
+
+        tx.commit()?;
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{open_temp, FilamentProfile};
+    use super::{parse_document, ExportSelection, ImportOutcome, ProfileKind};
+    use crate::error::ErrorCode;
+
+    fn profile(brand: &str, color: &str) -> FilamentProfile {
+        FilamentProfile {
+            brand: brand.into(),
+            material: "PETG".into(),
+            color: color.into(),
+            nozzle_temp: 240,
+            bed_temp: 70,
+            density: 1.27,
+            diameter: 1.75,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn export_round_trips_into_another_library() {
+        let (_dir, pool) = open_temp();
+        let db = pool.get().unwrap();
+        let custom = db.create_custom_profile(profile("Prusament", "#FF8000")).unwrap();
+        let favorite = db
+            .add_favorite(FilamentProfile {
+                spoolman_id: Some("sunlu_petg_black_1750_1000".into()),
+                nozzle_temp: 0,
+                bed_temp: 0,
+                ..profile("Sunlu", "#000000")
+            })
+            .unwrap();
+        let trashed = db.create_custom_profile(profile("Overture", "#FFFFFF")).unwrap();
+        db.delete_custom_profile(trashed).unwrap();
+
+        let document = db.export_profiles(ExportSelection::default()).unwrap();
+        assert_eq!(document.profiles.len(), 2);
+        let selected = db
+            .export_profiles(ExportSelection { ids: Some(vec![favorite, trashed, custom]), ..Default::default() })
+            .unwrap();
+        assert_eq!(selected.profiles, document.profiles);
+
+        let json = serde_json::to_string(&document).unwrap();
+        let (_other_dir, other_pool) = open_temp();
+        let other = other_pool.get().unwrap();
+        let report = other.import_profiles(&parse_document(&json).unwrap()).unwrap();
+        assert_eq!((report.created, report.updated, report.skipped, report.rejected), (2, 0, 0, 0));
+        assert_eq!(other.export_profiles(ExportSelection::default()).unwrap().profiles, document.profiles);
+
+        let report = other.import_profiles(&document).unwrap();
+        assert_eq!(report.skipped, 2);
+        assert!(report.records.iter().all(|r| r.reason.as_deref() == Some("duplicate")));
+    }
+
+    #[test]
+    fn import_updates_matches_and_rejects_invalid_records() {
+        let (_dir, pool) = open_temp();
+        let db = pool.get().unwrap();
+        let id = db.create_custom_profile(profile("Prusament", "#FF8000")).unwrap();
+        let mut document = db.export_profiles(ExportSelection::default()).unwrap();
+
+        let mut changed = document.profiles[0].clone();
+        changed.color = "ff8000".into();
+        changed.notes = Some("Dry before use".into());
+        let mut invalid = document.profiles[0].clone();
+        invalid.brand = "Fiberlogy".into();
+        invalid.density = 9.0;
+        let mut hot = document.profiles[0].clone();
+        hot.brand = "Extrudr".into();
+        hot.nozzle_temp = 0;
+        let mut favorite = hot.clone();
+        favorite.kind = ProfileKind::Favorite;
+        document.profiles = vec![changed, invalid, hot, favorite];
+
+        let report = db.import_profiles(&document).unwrap();
+        let outcomes: Vec<_> = report.records.iter().map(|r| r.outcome).collect();
+        assert_eq!(
+            outcomes,
+            [ImportOutcome::Updated, ImportOutcome::Rejected, ImportOutcome::Rejected, ImportOutcome::Created]
+        );
+        assert_eq!(report.records[0].profile_id, Some(id));
+        assert_eq!(db.get_profile(id).unwrap().notes.as_deref(), Some("Dry before use"));
+        assert!(report.records[1].reason.as_deref().unwrap().contains("density"));
+        assert!(report.records[2].reason.as_deref().unwrap().contains("nozzle_temp"));
+    }
+
+    #[test]
+    fn foreign_documents_are_refused() {
+        let document = |format: &str, version: u32| {
+            serde_json::json!({ "format": format, "version": version, "exported_at": 0, "profiles": [] }).to_string()
+        };
+
+        assert!(parse_document(&document("spoolsync-profiles", 1)).is_ok());
+        assert_eq!(parse_document(&document("orca-filaments", 1)).unwrap_err().code, ErrorCode::InvalidInput);
+        assert_eq!(parse_document(&document("spoolsync-profiles", 0)).unwrap_err().code, ErrorCode::InvalidInput);
+        assert_eq!(parse_document(&document("spoolsync-profiles", 2)).unwrap_err().code, ErrorCode::InvalidInput);
+        assert_eq!(parse_document("{ \"profiles\": ").unwrap_err().code, ErrorCode::Parse);
+    }
+}
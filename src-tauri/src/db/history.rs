@@ -0,0 +1,264 @@
+use super::{Database, FilamentProfile};
+use crate::error::AppResult;
+use rusqlite::{params, params_from_iter, types::Value, OptionalExtension, Result, Row};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum HistoryEvent {
+    AmsSync,
+    PresetCreate,
+    PresetUpdate,
+    PresetDelete,
+    ProfileCreate,
+    ProfileUpdate,
+    ProfileDelete,
+    ProfileRestore,
+    ProfilePurge,
+}
+
+impl HistoryEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HistoryEvent::AmsSync => "ams_sync",
+            HistoryEvent::PresetCreate => "preset_create",
+            HistoryEvent::PresetUpdate => "preset_update",
+            HistoryEvent::PresetDelete => "preset_delete",
+            HistoryEvent::ProfileCreate => "profile_create",
+            HistoryEvent::ProfileUpdate => "profile_update",
+            HistoryEvent::ProfileDelete => "profile_delete",
+            HistoryEvent::ProfileRestore => "profile_restore",
+            HistoryEvent::ProfilePurge => "profile_purge",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        [
+            HistoryEvent::AmsSync,
+            HistoryEvent::PresetCreate,
+            HistoryEvent::PresetUpdate,
+            HistoryEvent::PresetDelete,
+            HistoryEvent::ProfileCreate,
+            HistoryEvent::ProfileUpdate,
+            HistoryEvent::ProfileDelete,
+            HistoryEvent::ProfileRestore,
+            HistoryEvent::ProfilePurge,
+        ]
+        .into_iter()
+        .find(|event| event.as_str() == value)
+    }
+}
+
+/// One row of the append-only history. Printer and profile ids are kept
+/// as plain values so entries outlive the rows they refer to.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct HistoryEntry {
+    pub id: Option<i64>,
+    pub event: HistoryEvent,
+    pub printer_id: Option<i64>,
+    pub ams_id: Option<i32>,
+    pub tray_id: Option<i32>,
+    pub profile_id: Option<i64>,
+    /// Preset name or profile label, for display.
+    pub subject: Option<String>,
+    pub payload: Option<serde_json::Value>,
+    pub success: bool,
+    pub error: Option<String>,
+    /// UTC, `YYYY-MM-DD HH:MM:SS`; set by the database.
+    pub created_at: Option<String>,
+}
+
+impl HistoryEntry {
+    pub fn new(event: HistoryEvent) -> Self {
+        Self {
+            id: None,
+            event,
+            printer_id: None,
+            ams_id: None,
+            tray_id: None,
+            profile_id: None,
+            subject: None,
+            payload: None,
+            success: true,
+            error: None,
+            created_at: None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct HistoryFilter {
+    #[serde(default)]
+    pub events: Option<Vec<HistoryEvent>>,
+    #[serde(default)]
+    pub printer_id: Option<i64>,
+    #[serde(default)]
+    pub ams_id: Option<i32>,
+    #[serde(default)]
+    pub tray_id: Option<i32>,
+    #[serde(default)]
+    pub profile_id: Option<i64>,
+    #[serde(default)]
+    pub success: Option<bool>,
+    /// Inclusive bounds in the `created_at` format.
+    #[serde(default)]
+    pub since: Option<String>,
+    #[serde(default)]
+    pub until: Option<String>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub offset: Option<usize>,
+}
+
+const HISTORY_COLUMNS: &str =
+    "id, event, printer_id, ams_id, tray_id, profile_id, subject, payload, success, error, created_at";
+
+fn row_to_history(row: &Row) -> Result<HistoryEntry> {
+    let event: String = row.get(1)?;
+    let payload: Option<String> = row.get(7)?;
+    Ok(HistoryEntry {
+        id: row.get(0)?,
+        event: HistoryEvent::parse(&event).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(1, rusqlite::types::Type::Text, event.into())
+        })?,
+        printer_id: row.get(2)?,
+        ams_id: row.get(3)?,
+        tray_id: row.get(4)?,
+        profile_id: row.get(5)?,
+        subject: row.get(6)?,
+        payload: payload.and_then(|payload| serde_json::from_str(&payload).ok()),
+        success: row.get::<_, i32>(8)? == 1,
+        error: row.get(9)?,
+        created_at: row.get(10)?,
+    })
+}
+
+pub(super) fn profile_label(profile: &FilamentProfile) -> String {
+    match &profile.name {
+        Some(name) if !name.is_empty() => format!("{} {} {}", profile.brand, profile.material, name),
+        _ => format!("{} {} {}", profile.brand, profile.material, profile.color),
+    }
+}
+
+/// `{ field: { "from": old, "to": new } }` for every field that differs.
+pub(super) fn profile_changes(before: &FilamentProfile, after: &FilamentProfile) -> serde_json::Value {
+    let before = serde_json::to_value(before).unwrap_or_default();
+    let after = serde_json::to_value(after).unwrap_or_default();
+    let mut changes = serde_json::Map::new();
+
+    if let (Some(before), Some(after)) = (before.as_object(), after.as_object()) {
+        for (field, new_value) in after {
+            let old_value = before.get(field).cloned().unwrap_or(serde_json::Value::Null);
+            if old_value != *new_value {
+                changes.insert(
+                    field.clone(),
+                    serde_json::json!({ "from": old_value, "to": new_value }),
+                );
+            }
+        }
+    }
+
+    serde_json::Value::Object(changes)
+}
+
+impl Database {
+    pub fn record_history(&self, entry: &HistoryEntry) -> AppResult<i64> {
+        self.conn.execute(
+            "INSERT INTO history (event, printer_id, ams_id, tray_id, profile_id, subject, payload, success, error)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                entry.event.as_str(),
+                entry.printer_id,
+                entry.ams_id,
+                entry.tray_id,
+                entry.profile_id,
+                entry.subject,
+                entry.payload.as_ref().map(|payload| payload.to_string()),
+                entry.success as i32,
+                entry.error,
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Newest entries first.
+    pub fn get_history(&self, filter: HistoryFilter) -> AppResult<Vec<HistoryEntry>> {
+        let mut conditions: Vec<String> = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+
+        if let Some(events) = filter.events.filter(|events| !events.is_empty()) {
+            conditions.push(format!("event IN ({})", vec!["?"; events.len()].join(", ")));
+            values.extend(events.iter().map(|event| Value::Text(event.as_str().to_string())));
+        }
+        if let Some(printer_id) = filter.printer_id {
+            conditions.push("printer_id = ?".to_string());
+            values.push(Value::Integer(printer_id));
+        }
+        if let Some(ams_id) = filter.ams_id {
+            conditions.push("ams_id = ?".to_string());
+            values.push(Value::Integer(ams_id as i64));
+        }
+        if let Some(tray_id) = filter.tray_id {
+            conditions.push("tray_id = ?".to_string());
+            values.push(Value::Integer(tray_id as i64));
+        }
+        if let Some(profile_id) = filter.profile_id {
+            conditions.push("profile_id = ?".to_string());
+            values.push(Value::Integer(profile_id));
+        }
+        if let Some(success) = filter.success {
+            conditions.push("success = ?".to_string());
+            values.push(Value::Integer(success as i64));
+        }
+        if let Some(since) = filter.since {
+            conditions.push("created_at >= ?".to_string());
+            values.push(Value::Text(since));
+        }
+        if let Some(until) = filter.until {
+            conditions.push("created_at <= ?".to_string());
+            values.push(Value::Text(until));
+        }
+
+        let filter_sql = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+        values.push(Value::Integer(filter.limit.unwrap_or(100) as i64));
+        values.push(Value::Integer(filter.offset.unwrap_or(0) as i64));
+
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {} FROM history {} ORDER BY created_at DESC, id DESC LIMIT ? OFFSET ?",
+            HISTORY_COLUMNS, filter_sql
+        ))?;
+        let entries = stmt
+            .query_map(params_from_iter(values.iter()), row_to_history)?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(entries)
+    }
+
+    /// The last successful sync to a tray at or before `at`, i.e. what the
+    /// tray held at that moment as far as this app knows.
+    pub fn get_tray_history_at(
+        &self,
+        printer_id: i64,
+        ams_id: i32,
+        tray_id: i32,
+        at: &str,
+    ) -> AppResult<Option<HistoryEntry>> {
+        Ok(self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM history
+                     WHERE event = 'ams_sync' AND success = 1 AND printer_id = ?1 AND ams_id = ?2 AND tray_id = ?3 AND created_at <= ?4
+                     ORDER BY created_at DESC, id DESC LIMIT 1",
+                    HISTORY_COLUMNS
+                ),
+                params![printer_id, ams_id, tray_id, at],
+                row_to_history,
+            )
+            .optional()?)
+    }
+}
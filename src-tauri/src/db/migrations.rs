@@ -0,0 +1,507 @@
+use crate::error::{AppError, AppResult};
+use rusqlite::Connection;
+use std::path::Path;
+
+/// Ordered schema steps. Entry `n` upgrades a database from
+/// `user_version = n` to `n + 1`; never edit a step once it has shipped,
+/// append a new one instead.
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema (matches installs created before versioning)
+    "CREATE TABLE IF NOT EXISTS filament_profiles (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        brand TEXT NOT NULL,
+        material TEXT NOT NULL,
+        color TEXT NOT NULL,
+        nozzle_temp INTEGER NOT NULL,
+        bed_temp INTEGER NOT NULL,
+        density REAL NOT NULL,
+        diameter REAL NOT NULL,
+        is_favorite INTEGER NOT NULL DEFAULT 0,
+        is_custom INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE IF NOT EXISTS settings (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        printer_name TEXT,
+        printer_ip TEXT NOT NULL DEFAULT '',
+        printer_serial TEXT NOT NULL DEFAULT '',
+        printer_access_code TEXT NOT NULL DEFAULT '',
+        default_ams INTEGER NOT NULL DEFAULT 0,
+        default_tray INTEGER NOT NULL DEFAULT 0,
+        auto_sync INTEGER NOT NULL DEFAULT 0
+    );
+    INSERT OR IGNORE INTO settings (id, printer_ip, printer_serial, printer_access_code) VALUES (1, '', '', '');",
+    // 2: physical spool inventory and remaining-weight ledger
+    "CREATE TABLE spools (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        profile_id INTEGER REFERENCES filament_profiles(id) ON DELETE SET NULL,
+        spoolman_id TEXT,
+        initial_weight REAL NOT NULL,
+        remaining_weight REAL NOT NULL,
+        lot_number TEXT,
+        purchase_date TEXT,
+        opened_date TEXT,
+        location TEXT,
+        notes TEXT,
+        ams_id INTEGER,
+        tray_id INTEGER,
+        created_at TEXT NOT NULL DEFAULT (datetime('now'))
+    );
+    CREATE INDEX idx_spools_profile ON spools(profile_id);
+    CREATE TABLE spool_weight_ledger (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        spool_id INTEGER NOT NULL REFERENCES spools(id) ON DELETE CASCADE,
+        delta_grams REAL NOT NULL,
+        remaining_after REAL NOT NULL,
+        reason TEXT,
+        created_at TEXT NOT NULL DEFAULT (datetime('now'))
+    );
+    CREATE INDEX idx_spool_ledger_spool ON spool_weight_ledger(spool_id);",
+    // 3: printer registry; the single printer from settings becomes the default
+    "CREATE TABLE printers (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        model TEXT NOT NULL DEFAULT '',
+        ip_address TEXT NOT NULL,
+        serial_number TEXT NOT NULL,
+        access_code TEXT NOT NULL,
+        default_ams INTEGER NOT NULL DEFAULT 0,
+        default_tray INTEGER NOT NULL DEFAULT 0,
+        is_default INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE UNIQUE INDEX idx_printers_default ON printers(is_default) WHERE is_default = 1;
+    INSERT INTO printers (name, ip_address, serial_number, access_code, default_ams, default_tray, is_default)
+        SELECT COALESCE(NULLIF(printer_name, ''), 'Printer'), printer_ip, printer_serial, printer_access_code, default_ams, default_tray, 1
+        FROM settings WHERE id = 1 AND printer_ip != '';
+    ALTER TABLE spools ADD COLUMN printer_id INTEGER REFERENCES printers(id) ON DELETE SET NULL;
+    UPDATE spools SET printer_id = (SELECT id FROM printers WHERE is_default = 1) WHERE ams_id IS NOT NULL;",
+    // 4: keep every SpoolmanDB field on profiles (arrays stored as JSON)
+    "ALTER TABLE filament_profiles ADD COLUMN spoolman_id TEXT;
+    ALTER TABLE filament_profiles ADD COLUMN name TEXT;
+    ALTER TABLE filament_profiles ADD COLUMN color_hexes TEXT;
+    ALTER TABLE filament_profiles ADD COLUMN nozzle_temp_range TEXT;
+    ALTER TABLE filament_profiles ADD COLUMN bed_temp_range TEXT;
+    ALTER TABLE filament_profiles ADD COLUMN weight REAL;
+    ALTER TABLE filament_profiles ADD COLUMN spool_weight REAL;
+    ALTER TABLE filament_profiles ADD COLUMN spool_type TEXT;
+    ALTER TABLE filament_profiles ADD COLUMN finish TEXT;
+    ALTER TABLE filament_profiles ADD COLUMN multi_color_direction TEXT;
+    ALTER TABLE filament_profiles ADD COLUMN pattern TEXT;
+    ALTER TABLE filament_profiles ADD COLUMN translucent INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE filament_profiles ADD COLUMN glow INTEGER NOT NULL DEFAULT 0;",
+    // 5: upstream change tracking for favorites sourced from SpoolmanDB
+    "ALTER TABLE filament_profiles ADD COLUMN upstream_hash TEXT;
+    ALTER TABLE filament_profiles ADD COLUMN upstream_status TEXT;
+    ALTER TABLE filament_profiles ADD COLUMN upstream_pending TEXT;
+    CREATE INDEX idx_profiles_spoolman_id ON filament_profiles(spoolman_id);",
+    // 6: profile notes and the FTS5 search index. Index rowids are
+    // id * 2 for profiles and id * 2 + 1 for spools; triggers keep it current.
+    "ALTER TABLE filament_profiles ADD COLUMN notes TEXT;
+    CREATE VIRTUAL TABLE search_index USING fts5(
+        kind UNINDEXED,
+        ref_id UNINDEXED,
+        brand,
+        material,
+        name,
+        details,
+        notes,
+        tokenize = 'unicode61 remove_diacritics 2',
+        prefix = '2 3'
+    );
+    CREATE TRIGGER search_profiles_insert AFTER INSERT ON filament_profiles BEGIN
+        INSERT INTO search_index (rowid, kind, ref_id, brand, material, name, details, notes)
+        VALUES (new.id * 2, 'profile', new.id, new.brand, new.material, COALESCE(new.name, ''),
+                TRIM(new.color || ' ' || COALESCE(new.finish, '') || ' ' || COALESCE(new.pattern, '')), COALESCE(new.notes, ''));
+    END;
+    CREATE TRIGGER search_profiles_update AFTER UPDATE ON filament_profiles BEGIN
+        DELETE FROM search_index WHERE rowid = old.id * 2;
+        INSERT INTO search_index (rowid, kind, ref_id, brand, material, name, details, notes)
+        VALUES (new.id * 2, 'profile', new.id, new.brand, new.material, COALESCE(new.name, ''),
+                TRIM(new.color || ' ' || COALESCE(new.finish, '') || ' ' || COALESCE(new.pattern, '')), COALESCE(new.notes, ''));
+        DELETE FROM search_index WHERE rowid IN (SELECT id * 2 + 1 FROM spools WHERE profile_id = new.id);
+        INSERT INTO search_index (rowid, kind, ref_id, brand, material, name, details, notes)
+        SELECT s.id * 2 + 1, 'spool', s.id, new.brand, new.material, COALESCE(new.name, ''),
+               TRIM(COALESCE(s.lot_number, '') || ' ' || COALESCE(s.location, '')), COALESCE(s.notes, '')
+        FROM spools s WHERE s.profile_id = new.id;
+    END;
+    CREATE TRIGGER search_profiles_delete AFTER DELETE ON filament_profiles BEGIN
+        DELETE FROM search_index WHERE rowid = old.id * 2;
+    END;
+    CREATE TRIGGER search_spools_insert AFTER INSERT ON spools BEGIN
+        INSERT INTO search_index (rowid, kind, ref_id, brand, material, name, details, notes)
+        SELECT new.id * 2 + 1, 'spool', new.id, COALESCE(p.brand, ''), COALESCE(p.material, ''), COALESCE(p.name, ''),
+               TRIM(COALESCE(new.lot_number, '') || ' ' || COALESCE(new.location, '')), COALESCE(new.notes, '')
+        FROM (SELECT 1) LEFT JOIN filament_profiles p ON p.id = new.profile_id;
+    END;
+    CREATE TRIGGER search_spools_update AFTER UPDATE ON spools BEGIN
+        DELETE FROM search_index WHERE rowid = old.id * 2 + 1;
+        INSERT INTO search_index (rowid, kind, ref_id, brand, material, name, details, notes)
+        SELECT new.id * 2 + 1, 'spool', new.id, COALESCE(p.brand, ''), COALESCE(p.material, ''), COALESCE(p.name, ''),
+               TRIM(COALESCE(new.lot_number, '') || ' ' || COALESCE(new.location, '')), COALESCE(new.notes, '')
+        FROM (SELECT 1) LEFT JOIN filament_profiles p ON p.id = new.profile_id;
+    END;
+    CREATE TRIGGER search_spools_delete AFTER DELETE ON spools BEGIN
+        DELETE FROM search_index WHERE rowid = old.id * 2 + 1;
+    END;
+    INSERT INTO search_index (rowid, kind, ref_id, brand, material, name, details, notes)
+    SELECT id * 2, 'profile', id, brand, material, COALESCE(name, ''),
+           TRIM(color || ' ' || COALESCE(finish, '') || ' ' || COALESCE(pattern, '')), ''
+    FROM filament_profiles;
+    INSERT INTO search_index (rowid, kind, ref_id, brand, material, name, details, notes)
+    SELECT s.id * 2 + 1, 'spool', s.id, COALESCE(p.brand, ''), COALESCE(p.material, ''), COALESCE(p.name, ''),
+           TRIM(COALESCE(s.lot_number, '') || ' ' || COALESCE(s.location, '')), COALESCE(s.notes, '')
+    FROM spools s LEFT JOIN filament_profiles p ON p.id = s.profile_id;",
+    // 7: how many automatic backups to keep
+    "ALTER TABLE settings ADD COLUMN backup_retention INTEGER NOT NULL DEFAULT 10;",
+    // 8: append-only history of AMS syncs, preset writes and profile edits
+    "CREATE TABLE history (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        event TEXT NOT NULL,
+        printer_id INTEGER,
+        ams_id INTEGER,
+        tray_id INTEGER,
+        profile_id INTEGER,
+        subject TEXT,
+        payload TEXT,
+        success INTEGER NOT NULL DEFAULT 1,
+        error TEXT,
+        created_at TEXT NOT NULL DEFAULT (datetime('now'))
+    );
+    CREATE INDEX idx_history_created ON history(created_at);
+    CREATE INDEX idx_history_tray ON history(printer_id, ams_id, tray_id, created_at);
+    CREATE INDEX idx_history_profile ON history(profile_id);
+    CREATE TRIGGER history_no_update BEFORE UPDATE ON history BEGIN
+        SELECT RAISE(ABORT, 'history is append-only');
+    END;
+    CREATE TRIGGER history_no_delete BEFORE DELETE ON history BEGIN
+        SELECT RAISE(ABORT, 'history is append-only');
+    END;",
+    // 9: user-defined tags and ordered collections of profiles
+    "CREATE TABLE tags (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+        color TEXT
+    );
+    CREATE TABLE profile_tags (
+        profile_id INTEGER NOT NULL REFERENCES filament_profiles(id) ON DELETE CASCADE,
+        tag_id INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+        PRIMARY KEY (profile_id, tag_id)
+    );
+    CREATE INDEX idx_profile_tags_tag ON profile_tags(tag_id);
+    CREATE TABLE collections (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL UNIQUE COLLATE NOCASE,
+        description TEXT,
+        position INTEGER NOT NULL DEFAULT 0
+    );
+    CREATE TABLE collection_profiles (
+        collection_id INTEGER NOT NULL REFERENCES collections(id) ON DELETE CASCADE,
+        profile_id INTEGER NOT NULL REFERENCES filament_profiles(id) ON DELETE CASCADE,
+        position INTEGER NOT NULL DEFAULT 0,
+        PRIMARY KEY (collection_id, profile_id)
+    );
+    CREATE INDEX idx_collection_profiles_profile ON collection_profiles(profile_id);",
+    // 10: filament prices and per-job cost history. Usage rows keep the
+    // price they were costed at, so later price edits do not rewrite history.
+    "ALTER TABLE spools ADD COLUMN purchase_price REAL;
+    ALTER TABLE spools ADD COLUMN currency TEXT;
+    ALTER TABLE filament_profiles ADD COLUMN price_per_kg REAL;
+    ALTER TABLE filament_profiles ADD COLUMN currency TEXT;
+    CREATE TABLE print_jobs (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        name TEXT NOT NULL,
+        client TEXT,
+        printer_id INTEGER REFERENCES printers(id) ON DELETE SET NULL,
+        notes TEXT,
+        created_at TEXT NOT NULL DEFAULT (datetime('now'))
+    );
+    CREATE TABLE print_job_usage (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        job_id INTEGER NOT NULL REFERENCES print_jobs(id) ON DELETE CASCADE,
+        spool_id INTEGER REFERENCES spools(id) ON DELETE SET NULL,
+        profile_id INTEGER REFERENCES filament_profiles(id) ON DELETE SET NULL,
+        grams REAL NOT NULL,
+        price_per_kg REAL,
+        cost REAL,
+        currency TEXT,
+        source TEXT NOT NULL DEFAULT 'manual',
+        created_at TEXT NOT NULL DEFAULT (datetime('now'))
+    );
+    CREATE INDEX idx_print_job_usage_job ON print_job_usage(job_id);",
+    // 11: drying log per spool
+    "CREATE TABLE spool_drying (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        spool_id INTEGER NOT NULL REFERENCES spools(id) ON DELETE CASCADE,
+        dried_at TEXT NOT NULL DEFAULT (datetime('now')),
+        temperature REAL,
+        duration_minutes INTEGER,
+        method TEXT,
+        notes TEXT
+    );
+    CREATE INDEX idx_spool_drying_spool ON spool_drying(spool_id, dried_at);",
+    // 12: what was last synced to each AMS tray, checked against live reports.
+    // brand/material/color are a snapshot so the row survives profile edits.
+    "CREATE TABLE tray_assignments (
+        printer_id INTEGER NOT NULL REFERENCES printers(id) ON DELETE CASCADE,
+        ams_id INTEGER NOT NULL,
+        tray_id INTEGER NOT NULL,
+        profile_id INTEGER REFERENCES filament_profiles(id) ON DELETE SET NULL,
+        spool_id INTEGER REFERENCES spools(id) ON DELETE SET NULL,
+        brand TEXT NOT NULL,
+        material TEXT NOT NULL,
+        color TEXT NOT NULL,
+        assigned_at TEXT NOT NULL DEFAULT (datetime('now')),
+        expected_uuid TEXT,
+        live_material TEXT,
+        live_color TEXT,
+        live_uuid TEXT,
+        checked_at TEXT,
+        status TEXT NOT NULL DEFAULT 'unverified',
+        mismatch TEXT,
+        PRIMARY KEY (printer_id, ams_id, tray_id)
+    );",
+    // 13: soft delete; profiles with deleted_at set are in the trash
+    "ALTER TABLE filament_profiles ADD COLUMN deleted_at TEXT;
+    CREATE INDEX idx_profiles_deleted ON filament_profiles(deleted_at);",
+    // 14: field snapshots of profiles, one per update
+    "CREATE TABLE profile_revisions (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        profile_id INTEGER NOT NULL REFERENCES filament_profiles(id) ON DELETE CASCADE,
+        revision INTEGER NOT NULL,
+        data TEXT NOT NULL,
+        note TEXT,
+        created_at TEXT NOT NULL DEFAULT (datetime('now')),
+        UNIQUE (profile_id, revision)
+    );",
+    // 15: calibration results per profile, printer and nozzle. A NULL
+    // printer or nozzle type applies to any; a NULL value was not measured.
+    "CREATE TABLE calibration_results (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        profile_id INTEGER NOT NULL REFERENCES filament_profiles(id) ON DELETE CASCADE,
+        printer_id INTEGER REFERENCES printers(id) ON DELETE CASCADE,
+        nozzle_diameter REAL NOT NULL DEFAULT 0.4,
+        nozzle_type TEXT,
+        flow_ratio REAL,
+        pressure_advance REAL,
+        max_volumetric_speed REAL,
+        nozzle_temp INTEGER,
+        notes TEXT,
+        calibrated_at TEXT NOT NULL DEFAULT (datetime('now'))
+    );
+    CREATE INDEX idx_calibration_profile ON calibration_results(profile_id, printer_id, nozzle_diameter);",
+    // 16: SpoolmanDB source (base URL, filaments.json or checkout); NULL is SpoolmanDB itself
+    "ALTER TABLE settings ADD COLUMN spoolmandb_source TEXT;",
+    // 17: self-hosted Spoolman server, and usage of linked spools (spoolman_id
+    // is the Spoolman spool id) not yet reported to it, in grams
+    "ALTER TABLE settings ADD COLUMN spoolman_server_url TEXT;
+    ALTER TABLE spools ADD COLUMN spoolman_unreported REAL NOT NULL DEFAULT 0;
+    CREATE INDEX idx_spools_spoolman_id ON spools(spoolman_id);",
+    // 18: filament used according to the AMS, from the drop in a tray's
+    // remain % between reports; claimed_at is set once it is added to a job
+    "ALTER TABLE tray_assignments ADD COLUMN live_remain INTEGER;
+    CREATE TABLE ams_usage (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        printer_id INTEGER NOT NULL REFERENCES printers(id) ON DELETE CASCADE,
+        ams_id INTEGER NOT NULL,
+        tray_id INTEGER NOT NULL,
+        spool_id INTEGER REFERENCES spools(id) ON DELETE SET NULL,
+        profile_id INTEGER REFERENCES filament_profiles(id) ON DELETE SET NULL,
+        grams REAL NOT NULL,
+        recorded_at TEXT NOT NULL DEFAULT (datetime('now')),
+        claimed_at TEXT
+    );
+    CREATE INDEX idx_ams_usage_printer ON ams_usage(printer_id, claimed_at);",
+];
+
+pub fn latest_version() -> i64 {
+    MIGRATIONS.len() as i64
+}
+
+pub fn current_version(conn: &Connection) -> AppResult<i64> {
+    Ok(conn.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+}
+
+/// Brings the schema up to date. Any existing data is copied to
+/// `backup_dir` first, and all pending steps run in one transaction so a
+/// failing step leaves the database untouched.
+pub fn run(conn: &mut Connection, backup_dir: &Path) -> AppResult<()> {
+    let from = current_version(conn)?;
+    let to = latest_version();
+
+    if from > to {
+        return Err(AppError::invalid(format!(
+            "Database schema v{} is newer than this app supports (v{})",
+            from, to
+        )));
+    }
+
+    if from == to {
+        return Ok(());
+    }
+
+    if has_tables(conn)? {
+        let backup_path = super::backup::create(conn, backup_dir, &format!("pre-v{}", from))?;
+        println!("💾 Pre-migration backup: {:?}", backup_path);
+    }
+
+    println!("🔄 Migrating database schema v{} → v{}", from, to);
+
+    let tx = conn.transaction()?;
+    for (index, sql) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        let version = index as i64 + 1;
+        tx.execute_batch(sql)?;
+        tx.pragma_update(None, "user_version", version)?;
+        println!("   ✅ Applied migration v{}", version);
+    }
+    tx.commit()?;
+
+    Ok(())
+}
+
+fn has_tables(conn: &Connection) -> AppResult<bool> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(count > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn count(conn: &Connection, sql: &str) -> i64 {
+        conn.query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    fn has_table(conn: &Connection, name: &str) -> bool {
+        count(conn, &format!("SELECT COUNT(*) FROM sqlite_master WHERE name = '{}'", name)) > 0
+    }
+
+    /// An install from before versioning: the initial tables, the printer
+    /// kept in settings and `user_version` still 0.
+    fn unversioned() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0]).unwrap();
+        conn.execute_batch(
+            "UPDATE settings SET printer_name = '', printer_ip = '192.168.1.50', printer_serial = '01P00A',
+                printer_access_code = '12345678', default_ams = 1, default_tray = 2 WHERE id = 1;
+            INSERT INTO filament_profiles (brand, material, color, nozzle_temp, bed_temp, density, diameter)
+            VALUES ('Polymaker', 'PLA', 'Charcoal Black', 210, 60, 1.31, 1.75),
+                   ('Bambu Lab', 'PETG', 'Translucent', 250, 70, 1.27, 1.75);",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn migrates_an_empty_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let backups = dir.path().join("backups");
+        let mut conn = Connection::open_in_memory().unwrap();
+        run(&mut conn, &backups).unwrap();
+
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM printers"), 0);
+        assert_eq!(count(&conn, "SELECT backup_retention FROM settings"), 10);
+        // Nothing to lose, so nothing is backed up.
+        assert!(!backups.exists());
+        // Running again is a no-op.
+        run(&mut conn, &backups).unwrap();
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+    }
+
+    #[test]
+    fn migrates_a_v1_database_and_indexes_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut conn = unversioned();
+        conn.pragma_update(None, "user_version", 1).unwrap();
+        run(&mut conn, dir.path()).unwrap();
+
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM search_index WHERE kind = 'profile'"), 2);
+        assert_eq!(
+            count(&conn, "SELECT ref_id FROM search_index WHERE search_index MATCH 'charcoal'"),
+            1
+        );
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM filament_profiles WHERE deleted_at IS NULL"), 2);
+
+        // The triggers keep the backfilled index current.
+        conn.execute("INSERT INTO spools (profile_id, initial_weight, remaining_weight, location) VALUES (2, 1000, 800, 'Dry box')", [])
+            .unwrap();
+        assert_eq!(
+            count(&conn, "SELECT rowid FROM search_index WHERE search_index MATCH 'dry AND petg'"),
+            3
+        );
+    }
+
+    #[test]
+    fn moves_settings_printer_into_registry() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut conn = unversioned();
+        // Spools came in v2; one loaded in the AMS, one on the shelf.
+        conn.execute_batch(MIGRATIONS[1]).unwrap();
+        conn.execute_batch(
+            "INSERT INTO spools (profile_id, initial_weight, remaining_weight, ams_id, tray_id) VALUES (1, 1000, 640, 0, 3);
+            INSERT INTO spools (profile_id, initial_weight, remaining_weight, location) VALUES (2, 1000, 1000, 'Shelf');",
+        )
+        .unwrap();
+        conn.pragma_update(None, "user_version", 2).unwrap();
+        run(&mut conn, dir.path()).unwrap();
+
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+        let printer: (String, String, String, String, i64, i64, i64) = conn
+            .query_row(
+                "SELECT name, ip_address, serial_number, access_code, default_ams, default_tray, is_default FROM printers",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?, row.get(5)?, row.get(6)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            printer,
+            ("Printer".to_string(), "192.168.1.50".to_string(), "01P00A".to_string(), "12345678".to_string(), 1, 2, 1)
+        );
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM spools WHERE printer_id = 1 AND ams_id = 0"), 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM spools WHERE printer_id IS NULL"), 1);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM search_index WHERE kind = 'spool'"), 2);
+    }
+
+    #[test]
+    fn unversioned_database_keeps_its_data() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut conn = unversioned();
+        run(&mut conn, dir.path()).unwrap();
+
+        assert_eq!(current_version(&conn).unwrap(), latest_version());
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM filament_profiles"), 2);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM printers WHERE is_default = 1"), 1);
+    }
+
+    #[test]
+    fn failing_step_rolls_back_every_step() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut conn = unversioned();
+        // Step 8 creates `history`; a stray table makes it fail after 1–7 ran.
+        conn.execute_batch("CREATE TABLE history (id INTEGER PRIMARY KEY)").unwrap();
+
+        assert!(run(&mut conn, dir.path()).is_err());
+        assert_eq!(current_version(&conn).unwrap(), 0);
+        assert!(!has_table(&conn, "spools"));
+        assert!(!has_table(&conn, "printers"));
+        assert!(!has_table(&conn, "search_index"));
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM filament_profiles"), 2);
+        assert_eq!(count(&conn, "SELECT COUNT(*) FROM pragma_table_info('settings') WHERE name = 'backup_retention'"), 0);
+        // The pre-migration backup was still taken.
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn refuses_a_newer_schema() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1).unwrap();
+        assert!(run(&mut conn, dir.path()).is_err());
+        assert_eq!(current_version(&conn).unwrap(), latest_version() + 1);
+    }
+}
@@ -0,0 +1,243 @@
+use super::{backup, migrations, Database};
+use crate::error::{AppError, AppResult, ErrorCode};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::Connection;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+/// Connections kept open for commands and background writers.
+const POOL_SIZE: u32 = 8;
+/// Prepared statements cached per connection (`prepare_cached`).
+const STATEMENT_CACHE_CAPACITY: usize = 64;
+/// How long a writer waits on another writer before failing with `SQLITE_BUSY`.
+const BUSY_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long `get` waits for a free connection.
+const CHECKOUT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Shared handle to the database. Each `get` checks out its own SQLite
+/// connection, and the database runs in WAL mode, so readers never wait
+/// for each other or for a writer. Cloning is cheap.
+#[derive(Clone)]
+pub struct DbPool {
+    /// `None` while a restore has closed every connection, or when reopening
+    /// the database afterwards failed; `get` then tries again.
+    pool: Arc<RwLock<Option<Pool<SqliteConnectionManager>>>>,
+    db_path: PathBuf,
+    backup_dir: PathBuf,
+}
+
+fn configure(conn: &mut Connection) -> rusqlite::Result<()> {
+    conn.pragma_update(None, "foreign_keys", true)?;
+    conn.pragma_update(None, "synchronous", "NORMAL")?;
+    conn.busy_timeout(BUSY_TIMEOUT)?;
+    conn.set_prepared_statement_cache_capacity(STATEMENT_CACHE_CAPACITY);
+    Ok(())
+}
+
+impl DbPool {
+    pub fn open() -> AppResult<Self> {
+        let data_dir = dirs::data_local_dir()
+            .ok_or_else(|| AppError::new(ErrorCode::Io, "No data directory"))?;
+        Self::open_in(&data_dir.join("spoolsync-desktop"))
+    }
+
+    /// Opens (creating if needed) `spoolsync.db` and its backups in `app_dir`.
+    pub(super) fn open_in(app_dir: &Path) -> AppResult<Self> {
+        std::fs::create_dir_all(app_dir)
+            .map_err(|e| AppError { message: format!("Failed to create dir: {}", e), ..e.into() })?;
+
+        let db_path = app_dir.join("spoolsync.db");
+        println!("📂 Database path: {:?}", db_path);
+
+        let backup_dir = app_dir.join("backups");
+        prepare(&db_path, &backup_dir)?;
+
+        let db_pool = DbPool {
+            pool: Arc::new(RwLock::new(Some(build(&db_path)?))),
+            db_path,
+            backup_dir,
+        };
+        let db = db_pool.get()?;
+        backup::prune(&db.backup_dir, db.backup_retention()?)?;
+        match backup::redact_all(&db.backup_dir) {
+            Ok(0) => {}
+            Ok(count) => println!("🔑 Removed plaintext access codes from {} backup(s)", count),
+            Err(e) => println!("⚠️  Could not redact backups: {}", e),
+        }
+        Ok(db_pool)
+    }
+
+    /// Checks out a connection. It goes back to the pool when the returned
+    /// `Database` is dropped, so keep it only as long as needed.
+    pub fn get(&self) -> AppResult<Database> {
+        let conn = self
+            .pool()?
+            .get()
+            .map_err(|e| busy(&format!("No database connection available: {}", e)))?;
+        Ok(Database {
+            conn,
+            backup_dir: self.backup_dir.clone(),
+        })
+    }
+
+    /// The current pool. A restore holds the lock while the slot is empty,
+    /// so an empty slot seen here means the pool could not be reopened.
+    fn pool(&self) -> AppResult<Pool<SqliteConnectionManager>> {
+        if let Some(pool) = self.pool.read().unwrap().as_ref() {
+            return Ok(pool.clone());
+        }
+        let mut slot = self.pool.write().unwrap();
+        if slot.is_none() {
+            *slot = Some(build(&self.db_path)?);
+        }
+        Ok(slot.clone().unwrap())
+    }
+
+    /// Replaces the database with a backup; see `backup::restore`. New
+    /// checkouts wait while it runs, connections in use are waited for, and
+    /// every pooled connection is closed first so none keeps a view of the
+    /// old file. The pool is reopened afterwards, also when the restore fails.
+    pub fn restore_backup(&self, file_name: &str) -> AppResult<()> {
+        let mut slot = self.pool.write().unwrap();
+        if let Some(pool) = slot.as_ref() {
+            let started = Instant::now();
+            while pool.state().idle_connections < pool.state().connections {
+                if started.elapsed() > CHECKOUT_TIMEOUT {
+                    return Err(busy("The database is still in use; try the restore again"));
+                }
+                std::thread::sleep(Duration::from_millis(20));
+            }
+        }
+        *slot = None;
+
+        let restored = restore_file(&self.db_path, &self.backup_dir, file_name);
+        match build(&self.db_path) {
+            Ok(pool) => *slot = Some(pool),
+            // The slot stays empty and `get` retries. A failed restore is
+            // reported as such; otherwise the reopen error is.
+            Err(e) => {
+                println!("⚠️  Could not reopen the database after the restore: {}", e);
+                restored?;
+                return Err(e);
+            }
+        }
+        restored
+    }
+}
+
+fn busy(message: &str) -> AppError {
+    AppError::new(ErrorCode::DatabaseLocked, message)
+}
+
+fn build(db_path: &Path) -> AppResult<Pool<SqliteConnectionManager>> {
+    let manager = SqliteConnectionManager::file(db_path).with_init(configure);
+    Pool::builder()
+        .max_size(POOL_SIZE)
+        .min_idle(Some(1))
+        .connection_timeout(CHECKOUT_TIMEOUT)
+        .build(manager)
+        .map_err(|e| AppError::new(ErrorCode::Database, format!("Failed to open database: {}", e)))
+}
+
+/// Runs `backup::restore` on a private connection to `db_path`.
+fn restore_file(db_path: &Path, backup_dir: &Path, file_name: &str) -> AppResult<()> {
+    let mut conn = Connection::open(db_path)?;
+    configure(&mut conn)?;
+    backup::restore(&mut conn, backup_dir, file_name)
+}
+
+/// Switches the file to WAL and brings its schema up to date on a private
+/// connection, before the pool opens any others.
+fn prepare(db_path: &Path, backup_dir: &Path) -> AppResult<()> {
+    let mut conn = Connection::open(db_path)?;
+    configure(&mut conn)?;
+    conn.pragma_update_and_check(None, "journal_mode", "WAL", |_| Ok(()))?;
+
+    // A pending migration takes its own backup; otherwise snapshot on every start.
+    if migrations::current_version(&conn)? == migrations::latest_version() {
+        if let Err(e) = backup::create(&conn, backup_dir, "startup") {
+            println!("⚠️  Startup backup failed: {}", e);
+        }
+    }
+    migrations::run(&mut conn, backup_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{open_temp, FilamentProfile};
+    use std::time::Duration;
+
+    fn profile(brand: &str) -> FilamentProfile {
+        FilamentProfile {
+            brand: brand.to_string(),
+            material: "PLA".to_string(),
+            color: "#FF0000".to_string(),
+            nozzle_temp: 210,
+            bed_temp: 60,
+            density: 1.24,
+            diameter: 1.75,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn restore_reopens_every_connection() {
+        let (_dir, pool) = open_temp();
+        let backup = {
+            let db = pool.get().unwrap();
+            db.create_custom_profile(profile("Before")).unwrap();
+            db.create_backup("manual").unwrap()
+        };
+        {
+            // Several idle connections, each with a cached view of the file.
+            let (a, b) = (pool.get().unwrap(), pool.get().unwrap());
+            a.create_custom_profile(profile("After")).unwrap();
+            assert_eq!(b.get_custom_profiles().unwrap().len(), 2);
+        }
+
+        pool.restore_backup(&backup.file_name).unwrap();
+
+        for _ in 0..3 {
+            let db = pool.get().unwrap();
+            let brands: Vec<_> = db.get_custom_profiles().unwrap().into_iter().map(|p| p.brand).collect();
+            assert_eq!(brands, vec!["Before"]);
+        }
+        assert!(pool.get().unwrap().list_backups().unwrap().iter().any(|b| b.reason == "pre-restore"));
+    }
+
+    #[test]
+    fn restore_waits_for_connections_in_use() {
+        let (_dir, pool) = open_temp();
+        let backup = pool.get().unwrap().create_backup("manual").unwrap();
+
+        let held = pool.get().unwrap();
+        held.create_custom_profile(profile("Later")).unwrap();
+        let release = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            drop(held);
+        });
+
+        pool.restore_backup(&backup.file_name).unwrap();
+        release.join().unwrap();
+        assert!(pool.get().unwrap().get_custom_profiles().unwrap().is_empty());
+    }
+
+    #[test]
+    fn failed_restore_keeps_the_pool_open() {
+        let (_dir, pool) = open_temp();
+        assert!(pool.restore_backup("../spoolsync.db").is_err());
+        assert!(pool.get().unwrap().get_settings().is_ok());
+    }
+
+    #[test]
+    fn get_reopens_a_pool_that_failed_to_rebuild() {
+        let (_dir, pool) = open_temp();
+        pool.get().unwrap().create_custom_profile(profile("Kept")).unwrap();
+        *pool.pool.write().unwrap() = None;
+
+        assert_eq!(pool.get().unwrap().get_custom_profiles().unwrap().len(), 1);
+        assert!(pool.pool.read().unwrap().is_some());
+    }
+}
@@ -0,0 +1,162 @@
+use super::Database;
+use crate::error::{AppError, AppResult};
+use crate::mqtt::BambuPrinterConfig;
+use rusqlite::{params, OptionalExtension, Result, Row};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Printer {
+    pub id: Option<i64>,
+    #[serde(flatten)]
+    pub config: BambuPrinterConfig,
+    #[serde(default)]
+    pub is_default: bool,
+}
+
+const PRINTER_COLUMNS: &str = "id, name, model, ip_address, serial_number, access_code, default_ams, default_tray, is_default";
+
+fn row_to_printer(row: &Row) -> Result<Printer> {
+    Ok(Printer {
+        id: Some(row.get(0)?),
+        config: BambuPrinterConfig {
+            name: row.get(1)?,
+            model: row.get(2)?,
+            ip_address: row.get(3)?,
+            serial_number: row.get(4)?,
+            access_code: row.get(5)?,
+            default_ams: row.get(6)?,
+            default_tray: row.get(7)?,
+        },
+        is_default: row.get::<_, i32>(8)? == 1,
+    })
+}
+
+impl Database {
+    pub fn get_printers(&self) -> AppResult<Vec<Printer>> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {} FROM printers ORDER BY is_default DESC, name",
+            PRINTER_COLUMNS
+        ))?;
+
+        let printers = stmt
+            .query_map([], row_to_printer)?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(printers)
+    }
+
+    pub fn get_printer(&self, id: i64) -> AppResult<Printer> {
+        self.conn
+            .query_row(
+                &format!("SELECT {} FROM printers WHERE id = ?1", PRINTER_COLUMNS),
+                params![id],
+                row_to_printer,
+            )
+            .optional()?
+            .ok_or_else(|| AppError::not_found(format!("No printer with id {}", id)))
+    }
+
+    pub fn get_default_printer(&self) -> AppResult<Option<Printer>> {
+        Ok(self
+            .conn
+            .query_row(
+                &format!("SELECT {} FROM printers WHERE is_default = 1", PRINTER_COLUMNS),
+                [],
+                row_to_printer,
+            )
+            .optional()?)
+    }
+
+    /// Adds a printer. The first printer registered becomes the default.
+    pub fn create_printer(&self, printer: Printer) -> AppResult<i64> {
+        let tx = self.conn.unchecked_transaction()?;
+        let has_default: bool = tx.query_row(
+            "SELECT EXISTS(SELECT 1 FROM printers WHERE is_default = 1)",
+            [],
+            |row| row.get(0),
+        )?;
+        let is_default = printer.is_default || !has_default;
+        if is_default {
+            tx.execute("UPDATE printers SET is_default = 0", [])?;
+        }
+
+        tx.execute(
+            "INSERT INTO printers (name, model, ip_address, serial_number, access_code, default_ams, default_tray, is_default)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![
+                printer.config.name,
+                printer.config.model,
+                printer.config.ip_address,
+                printer.config.serial_number,
+                printer.config.access_code,
+                printer.config.default_ams,
+                printer.config.default_tray,
+                is_default as i32,
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+        tx.commit()?;
+        Ok(id)
+    }
+
+    pub fn update_printer(&self, printer: Printer) -> AppResult<()> {
+        self.conn.execute(
+            "UPDATE printers SET name = ?1, model = ?2, ip_address = ?3, serial_number = ?4, access_code = ?5, default_ams = ?6, default_tray = ?7
+             WHERE id = ?8",
+            params![
+                printer.config.name,
+                printer.config.model,
+                printer.config.ip_address,
+                printer.config.serial_number,
+                printer.config.access_code,
+                printer.config.default_ams,
+                printer.config.default_tray,
+                printer.id,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Removes a printer; if it was the default, the next one by name takes over.
+    pub fn delete_printer(&self, id: i64) -> AppResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM printers WHERE id = ?1", params![id])?;
+        tx.execute(
+            "UPDATE printers SET is_default = 1
+             WHERE id = (SELECT id FROM printers ORDER BY name LIMIT 1)
+               AND NOT EXISTS (SELECT 1 FROM printers WHERE is_default = 1)",
+            [],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn set_printer_access_code(&self, id: i64, access_code: &str) -> AppResult<()> {
+        self.conn.execute(
+            "UPDATE printers SET access_code = ?1 WHERE id = ?2",
+            params![access_code, id],
+        )?;
+        Ok(())
+    }
+
+    /// Whether any printer or the settings row still refers to this secret handle.
+    pub fn is_access_code_in_use(&self, access_code: &str) -> AppResult<bool> {
+        Ok(self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM printers WHERE access_code = ?1)
+                 OR EXISTS(SELECT 1 FROM settings WHERE printer_access_code = ?1)",
+            params![access_code],
+            |row| row.get(0),
+        )?)
+    }
+
+    pub fn set_default_printer(&self, id: i64) -> AppResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("UPDATE printers SET is_default = 0", [])?;
+        let updated = tx.execute("UPDATE printers SET is_default = 1 WHERE id = ?1", params![id])?;
+        if updated == 0 {
+            return Err(AppError::not_found(format!("No printer with id {}", id)));
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+use super::history;
+use super::{Database, FilamentProfile};
+use crate::error::AppResult;
+use rusqlite::{params, Result, Row};
+use serde::{Deserialize, Serialize};
+
+/// A snapshot of a profile's fields as saved by one update.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ProfileRevision {
+    pub id: i64,
+    pub profile_id: i64,
+    /// 1-based and increasing per profile.
+    pub revision: i64,
+    pub profile: FilamentProfile,
+    pub note: Option<String>,
+    /// UTC, `YYYY-MM-DD HH:MM:SS`.
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RevisionDiff {
+    pub profile_id: i64,
+    pub from_revision: i64,
+    pub to_revision: i64,
+    /// `{ field: { "from": old, "to": new } }` for every field that differs.
+    pub changes: serde_json::Value,
+}
+
+const REVISION_COLUMNS: &str = "id, profile_id, revision, data, note, created_at";
+
+fn row_to_revision(row: &Row) -> Result<ProfileRevision> {
+    let data: String = row.get(3)?;
+    Ok(ProfileRevision {
+        id: row.get(0)?,
+        profile_id: row.get(1)?,
+        revision: row.get(2)?,
+        profile: serde_json::from_str(&data).map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(3, rusqlite::types::Type::Text, Box::new(e))
+        })?,
+        note: row.get(4)?,
+        created_at: row.get(5)?,
+    })
+}
+
+impl Database {
+    /// Stores `after` as the next revision of a profile. Profiles edited
+    /// for the first time also get `before` stored as revision 1, so the
+    /// state prior to any tracked edit can be reverted to.
+    pub(super) fn record_revision(
+        &self,
+        before: &FilamentProfile,
+        after: &FilamentProfile,
+        note: Option<&str>,
+    ) -> AppResult<()> {
+        let profile_id = after.id.unwrap_or_default();
+        let latest: i64 = self.conn.query_row(
+            "SELECT COALESCE(MAX(revision), 0) FROM profile_revisions WHERE profile_id = ?1",
+            params![profile_id],
+            |row| row.get(0),
+        )?;
+
+        let mut next = latest + 1;
+        if latest == 0 {
+            self.insert_revision(profile_id, next, before, None)?;
+            next += 1;
+        }
+        self.insert_revision(profile_id, next, after, note)
+    }
+
+    fn insert_revision(
+        &self,
+        profile_id: i64,
+        revision: i64,
+        profile: &FilamentProfile,
+        note: Option<&str>,
+    ) -> AppResult<()> {
+        self.conn.execute(
+            "INSERT INTO profile_revisions (profile_id, revision, data, note) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                profile_id,
+                revision,
+                serde_json::to_string(profile).unwrap_or_default(),
+                note,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Newest revision first.
+    pub fn get_profile_revisions(&self, profile_id: i64) -> AppResult<Vec<ProfileRevision>> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {} FROM profile_revisions WHERE profile_id = ?1 ORDER BY revision DESC",
+            REVISION_COLUMNS
+        ))?;
+        let revisions = stmt
+            .query_map(params![profile_id], row_to_revision)?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(revisions)
+    }
+
+    pub fn get_profile_revision(&self, profile_id: i64, revision: i64) -> AppResult<ProfileRevision> {
+        Ok(self.conn.query_row(
+            &format!(
+                "SELECT {} FROM profile_revisions WHERE profile_id = ?1 AND revision = ?2",
+                REVISION_COLUMNS
+            ),
+            params![profile_id, revision],
+            row_to_revision,
+        )?)
+    }
+
+    pub fn diff_profile_revisions(&self, profile_id: i64, from: i64, to: i64) -> AppResult<RevisionDiff> {
+        let before = self.get_profile_revision(profile_id, from)?;
+        let after = self.get_profile_revision(profile_id, to)?;
+        Ok(RevisionDiff {
+            profile_id,
+            from_revision: from,
+            to_revision: to,
+            changes: history::profile_changes(&before.profile, &after.profile),
+        })
+    }
+
+    /// Restores a profile's fields from an earlier revision. The revert is
+    /// itself saved as a new revision, so it can be undone the same way.
+    pub fn revert_profile(&self, profile_id: i64, revision: i64) -> AppResult<()> {
+        let target = self.get_profile_revision(profile_id, revision)?;
+        let mut profile = target.profile;
+        profile.id = Some(profile_id);
+
+        let tx = self.conn.unchecked_transaction()?;
+        self.update_profile_fields(profile, Some(&format!("Reverted to revision {}", revision)))?;
+        tx.commit()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,376 @@
+use super::tags::profile_filter;
+use super::{Database, FilamentProfile, Spool};
+use crate::error::AppResult;
+use crate::spoolman::{SearchBoosts, RECENT_DAYS};
+use rusqlite::{params, params_from_iter, types::Value, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SearchQuery {
+    /// Words match as prefixes when they end in `*`; `"quoted text"` is a phrase.
+    #[serde(default)]
+    pub text: Option<String>,
+    /// `profile` or `spool`; both when unset.
+    #[serde(default)]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub material: Option<String>,
+    #[serde(default)]
+    pub brand: Option<String>,
+    #[serde(default)]
+    pub min_nozzle_temp: Option<i32>,
+    #[serde(default)]
+    pub max_nozzle_temp: Option<i32>,
+    #[serde(default)]
+    pub is_favorite: Option<bool>,
+    #[serde(default)]
+    pub is_custom: Option<bool>,
+    /// Profiles carrying all of these tags.
+    #[serde(default)]
+    pub tag_ids: Option<Vec<i64>>,
+    #[serde(default)]
+    pub collection_id: Option<i64>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub offset: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SearchHit {
+    pub kind: String,
+    pub id: i64,
+    pub rank: f64,
+    pub snippet: Option<String>,
+    pub profile: Option<FilamentProfile>,
+    pub spool: Option<Spool>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchResults {
+    pub items: Vec<SearchHit>,
+    pub total: usize,
+}
+
+/// Turns user input into an FTS5 expression. Every term is quoted so
+/// punctuation such as `PLA-CF` cannot break the query syntax.
+fn to_fts_query(text: &str) -> Option<String> {
+    let mut terms = Vec::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' {
+            chars.next();
+            let phrase: String = chars.by_ref().take_while(|&c| c != '"').collect();
+            if !phrase.trim().is_empty() {
+                terms.push(format!("\"{}\"", phrase.trim()));
+            }
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '"' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            let prefix = word.ends_with('*');
+            let word = word.trim_end_matches('*');
+            if !word.is_empty() {
+                terms.push(format!("\"{}\"{}", word, if prefix { "*" } else { "" }));
+            }
+        }
+    }
+
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+impl Database {
+    /// Favorites and the SpoolmanDB entries of profiles synced to an AMS or
+    /// used in a print within `RECENT_DAYS`, for ranking SpoolmanDB results.
+    pub fn get_search_boosts(&self) -> AppResult<SearchBoosts> {
+        let mut boosts = SearchBoosts::default();
+
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT spoolman_id FROM filament_profiles
+             WHERE is_favorite = 1 AND deleted_at IS NULL AND spoolman_id IS NOT NULL",
+        )?;
+        boosts.favorites = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<Result<_>>()?;
+
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT p.spoolman_id, MIN(julianday('now') - julianday(used.at))
+             FROM (
+                SELECT profile_id, created_at AS at FROM history WHERE event = 'ams_sync' AND success = 1
+                UNION ALL
+                SELECT profile_id, created_at FROM print_job_usage
+             ) used
+             JOIN filament_profiles p ON p.id = used.profile_id
+             WHERE p.spoolman_id IS NOT NULL AND used.at >= datetime('now', ?1)
+             GROUP BY p.spoolman_id",
+        )?;
+        boosts.recent = stmt
+            .query_map(params![format!("-{} days", RECENT_DAYS)], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?
+            .collect::<Result<_>>()?;
+
+        Ok(boosts)
+    }
+
+    /// Ranked search over local profiles and spools (including their notes).
+    pub fn search_local(&self, query: SearchQuery) -> AppResult<SearchResults> {
+        let fts = query.text.as_deref().and_then(to_fts_query);
+        let mut conditions: Vec<String> = Vec::new();
+        let mut values: Vec<Value> = Vec::new();
+
+        if let Some(fts) = &fts {
+            conditions.push("search_index MATCH ?".to_string());
+            values.push(Value::Text(fts.clone()));
+        }
+        if let Some(kind) = &query.kind {
+            conditions.push("search_index.kind = ?".to_string());
+            values.push(Value::Text(kind.clone()));
+        }
+        if let Some(material) = &query.material {
+            conditions.push("p.material = ? COLLATE NOCASE".to_string());
+            values.push(Value::Text(material.clone()));
+        }
+        if let Some(brand) = &query.brand {
+            conditions.push("p.brand = ? COLLATE NOCASE".to_string());
+            values.push(Value::Text(brand.clone()));
+        }
+        if let Some(min) = query.min_nozzle_temp {
+            conditions.push("p.nozzle_temp >= ?".to_string());
+            values.push(Value::Integer(min as i64));
+        }
+        if let Some(max) = query.max_nozzle_temp {
+            conditions.push("p.nozzle_temp <= ?".to_string());
+            values.push(Value::Integer(max as i64));
+        }
+        if let Some(favorite) = query.is_favorite {
+            conditions.push("p.is_favorite = ?".to_string());
+            values.push(Value::Integer(favorite as i64));
+        }
+        if let Some(custom) = query.is_custom {
+            conditions.push("p.is_custom = ?".to_string());
+            values.push(Value::Integer(custom as i64));
+        }
+        // `p` is the profile itself or the one a spool belongs to, so spools of
+        // trashed profiles are hidden too; spools without a profile leave it NULL.
+        conditions.push("p.deleted_at IS NULL".to_string());
+        let (tag_conditions, tag_values) =
+            profile_filter("p.id", query.tag_ids.as_deref(), query.collection_id);
+        conditions.extend(tag_conditions);
+        values.extend(tag_values);
+
+        let from = "FROM search_index
+            LEFT JOIN spools s ON search_index.kind = 'spool' AND s.id = search_index.ref_id
+            LEFT JOIN filament_profiles p ON p.id = CASE WHEN search_index.kind = 'profile' THEN search_index.ref_id ELSE s.profile_id END";
+        let filter = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+
+        let total: i64 = self.conn.query_row(
+            &format!("SELECT COUNT(*) {} {}", from, filter),
+            params_from_iter(values.iter()),
+            |row| row.get(0),
+        )?;
+
+        let (rank, snippet) = if fts.is_some() {
+            (
+                "bm25(search_index, 0.0, 0.0, 10.0, 5.0, 8.0, 2.0, 1.0)",
+                "snippet(search_index, -1, '[', ']', '…', 8)",
+            )
+        } else {
+            ("0.0", "NULL")
+        };
+
+        let limit = query.limit.unwrap_or(50);
+        let offset = query.offset.unwrap_or(0);
+        values.push(Value::Integer(limit as i64));
+        values.push(Value::Integer(offset as i64));
+
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT search_index.kind, search_index.ref_id, {} AS rank, {} {} {}
+             ORDER BY rank, search_index.rowid LIMIT ? OFFSET ?",
+            rank, snippet, from, filter
+        ))?;
+        let rows = stmt
+            .query_map(params_from_iter(values.iter()), |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, i64>(1)?,
+                    row.get::<_, f64>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        drop(stmt);
+
+        let mut items = Vec::with_capacity(rows.len());
+        for (kind, id, rank, snippet) in rows {
+            let (profile, spool) = if kind == "spool" {
+                let spool = self.get_spool(id)?;
+                let profile = spool.profile_id.map(|pid| self.get_profile(pid)).transpose()?;
+                (profile, Some(spool))
+            } else {
+                (Some(self.get_profile(id)?), None)
+            };
+            items.push(SearchHit {
+                kind,
+                id,
+                rank,
+                snippet,
+                profile,
+                spool,
+            });
+        }
+
+        Ok(SearchResults {
+            items,
+            total: total as usize,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{open_temp, Database, FilamentProfile, Spool};
+    use super::{to_fts_query, SearchQuery};
+
+    #[test]
+    fn user_input_becomes_quoted_fts_terms() {
+        assert_eq!(to_fts_query("pla"), Some(r#""pla""#.to_string()));
+        assert_eq!(to_fts_query("  poly*   matte "), Some(r#""poly"* "matte""#.to_string()));
+        assert_eq!(to_fts_query(r#"PLA-CF "galaxy black" sun*"#), Some(r#""PLA-CF" "galaxy black" "sun"*"#.to_string()));
+        assert_eq!(to_fts_query(r#""unterminated phrase"#), Some(r#""unterminated phrase""#.to_string()));
+        for empty in ["", "   ", "*", r#""  ""#] {
+            assert_eq!(to_fts_query(empty), None, "{:?}", empty);
+        }
+    }
+
+    fn profile(db: &Database, brand: &str, material: &str, name: &str, nozzle_temp: i32, notes: Option<&str>) -> i64 {
+        db.create_custom_profile(FilamentProfile {
+            brand: brand.into(),
+            material: material.into(),
+            name: Some(name.into()),
+            color: "#202020".into(),
+            nozzle_temp,
+            bed_temp: 60,
+            density: 1.24,
+            diameter: 1.75,
+            notes: notes.map(String::from),
+            ..Default::default()
+        })
+        .unwrap()
+    }
+
+    fn spool(db: &Database, profile_id: Option<i64>, location: &str) -> i64 {
+        db.create_spool(Spool {
+            id: None,
+            profile_id,
+            spoolman_id: None,
+            initial_weight: 1000.0,
+            remaining_weight: 1000.0,
+            lot_number: None,
+            purchase_date: None,
+            opened_date: None,
+            location: Some(location.into()),
+            notes: None,
+            purchase_price: None,
+            currency: None,
+            printer_id: None,
+            ams_id: None,
+            tray_id: None,
+            created_at: None,
+        })
+        .unwrap()
+    }
+
+    fn search(db: &Database, query: SearchQuery) -> Vec<(String, i64)> {
+        db.search_local(query).unwrap().items.into_iter().map(|hit| (hit.kind, hit.id)).collect()
+    }
+
+    fn text(text: &str) -> SearchQuery {
+        SearchQuery { text: Some(text.into()), ..Default::default() }
+    }
+
+    #[test]
+    fn search_matches_prefixes_phrases_and_filters() {
+        let (_dir, pool) = open_temp();
+        let db = pool.get().unwrap();
+        let galaxy = profile(&db, "Prusament", "PLA", "Galaxy Black", 215, None);
+        let carbon = profile(&db, "Polymaker", "PETG", "Carbon Black", 250, None);
+        let shelf = spool(&db, Some(carbon), "Drybox 2");
+
+        assert_eq!(search(&db, text("galax*")), [("profile".to_string(), galaxy)]);
+        assert!(search(&db, text("galax")).is_empty());
+        assert_eq!(search(&db, text("\"carbon black\"")).len(), 2);
+        assert!(search(&db, text("\"black carbon\"")).is_empty());
+        assert_eq!(search(&db, text("drybox")), [("spool".to_string(), shelf)]);
+
+        let black = |query: SearchQuery| search(&db, SearchQuery { text: Some("black".into()), ..query });
+        assert_eq!(black(SearchQuery { kind: Some("profile".into()), ..Default::default() }).len(), 2);
+        assert_eq!(black(SearchQuery { material: Some("petg".into()), ..Default::default() }).len(), 2);
+        assert_eq!(
+            black(SearchQuery { brand: Some("prusament".into()), ..Default::default() }),
+            [("profile".to_string(), galaxy)]
+        );
+        assert_eq!(
+            black(SearchQuery { min_nozzle_temp: Some(240), kind: Some("profile".into()), ..Default::default() }),
+            [("profile".to_string(), carbon)]
+        );
+        assert_eq!(
+            black(SearchQuery { max_nozzle_temp: Some(220), ..Default::default() }),
+            [("profile".to_string(), galaxy)]
+        );
+    }
+
+    #[test]
+    fn trashed_profiles_and_their_spools_are_hidden() {
+        let (_dir, pool) = open_temp();
+        let db = pool.get().unwrap();
+        let trashed = profile(&db, "Sunlu", "PLA", "Silk Gold", 210, None);
+        spool(&db, Some(trashed), "Shelf");
+        let loose = spool(&db, None, "Shelf");
+        db.delete_custom_profile(trashed).unwrap();
+
+        assert!(search(&db, text("sunlu")).is_empty());
+        assert_eq!(search(&db, text("shelf")), [("spool".to_string(), loose)]);
+        assert_eq!(db.search_local(SearchQuery::default()).unwrap().total, 1);
+    }
+
+    #[test]
+    fn brand_matches_outrank_notes_and_pages_are_stable() {
+        let (_dir, pool) = open_temp();
+        let db = pool.get().unwrap();
+        let noted = profile(&db, "Elegoo", "PLA", "Red", 210, Some("Prints like Esun PLA+"));
+        let brand = profile(&db, "eSUN", "PLA", "Red", 210, None);
+
+        let ranked = db.search_local(text("esun")).unwrap();
+        assert_eq!(ranked.items[0].id, brand);
+        assert_eq!(ranked.items[1].id, noted);
+        assert!(ranked.items[0].rank < ranked.items[1].rank);
+        assert!(ranked.items[1].snippet.as_deref().unwrap().contains("[Esun]"));
+
+        for n in 0..5 {
+            profile(&db, "Generic", "PLA", &format!("Page {}", n), 210, None);
+        }
+        let query = |offset: usize| SearchQuery { limit: Some(2), offset: Some(offset), ..text("generic") };
+        let pages: Vec<_> = [0, 2, 4].into_iter().map(|offset| db.search_local(query(offset)).unwrap()).collect();
+        assert!(pages.iter().all(|page| page.total == 5));
+        let ids: Vec<i64> = pages.iter().flat_map(|page| page.items.iter().map(|hit| hit.id)).collect();
+        assert_eq!(ids.len(), 5);
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+}
@@ -0,0 +1,217 @@
+use super::{Database, FilamentProfile, Spool};
+use crate::error::AppResult;
+use crate::spoolman_server::{ServerFilament, ServerSpool};
+use rusqlite::{params, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+
+/// Outcome of importing spools from a Spoolman server.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct SpoolmanImportReport {
+    /// Local spool ids, in the order the server listed them.
+    pub spool_ids: Vec<i64>,
+    pub created: usize,
+    pub updated: usize,
+    /// Favorites created for Spoolman filaments no local profile matched.
+    pub profiles_created: usize,
+}
+
+/// Filament used from a linked spool that Spoolman has not been told about.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UnreportedUsage {
+    pub spool_id: i64,
+    /// Spoolman spool id.
+    pub spoolman_id: i64,
+    pub grams: f64,
+}
+
+impl From<&ServerFilament> for FilamentProfile {
+    fn from(filament: &ServerFilament) -> Self {
+        let hexes: Vec<String> = filament
+            .multi_color_hexes
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(|hex| hex.trim().trim_start_matches('#').to_string())
+            .filter(|hex| !hex.is_empty())
+            .collect();
+        Self {
+            brand: filament.vendor.as_ref().map(|v| v.name.clone()).unwrap_or_default(),
+            material: filament.material.clone().unwrap_or_default(),
+            color: filament
+                .color_hex
+                .as_ref()
+                .or(hexes.first())
+                .map(|hex| format!("#{}", hex.trim_start_matches('#')))
+                .unwrap_or_default(),
+            nozzle_temp: filament.settings_extruder_temp.unwrap_or(0),
+            bed_temp: filament.settings_bed_temp.unwrap_or(0),
+            density: filament.density,
+            diameter: filament.diameter,
+            spoolman_id: filament.external_id.clone(),
+            name: filament.name.clone(),
+            color_hexes: (!hexes.is_empty()).then_some(hexes),
+            weight: filament.weight,
+            spool_weight: filament.spool_weight,
+            multi_color_direction: filament.multi_color_direction.clone(),
+            notes: filament.comment.clone(),
+            price_per_kg: match (filament.price, filament.weight) {
+                (Some(price), Some(weight)) if weight > 0.0 => Some(price / weight * 1000.0),
+                _ => None,
+            },
+            ..Default::default()
+        }
+    }
+}
+
+/// `YYYY-MM-DD` of a Spoolman timestamp.
+fn date_part(timestamp: &Option<String>) -> Option<String> {
+    timestamp.as_ref().and_then(|t| t.get(..10)).map(str::to_string)
+}
+
+impl Database {
+    pub fn get_spool_by_spoolman_id(&self, spoolman_id: i64) -> AppResult<Option<i64>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT id FROM spools WHERE spoolman_id = ?1 ORDER BY id LIMIT 1",
+                params![spoolman_id.to_string()],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// The profile for a Spoolman filament: the favorite saved from the same
+    /// SpoolmanDB entry, else a profile with the same brand, material and
+    /// color, else a new favorite built from the filament.
+    fn profile_for_server_filament(&self, filament: &ServerFilament, report: &mut SpoolmanImportReport) -> AppResult<i64> {
+        if let Some(external_id) = &filament.external_id {
+            if let Some(id) = self.find_favorite_by_spoolman_id(external_id)? {
+                return Ok(id);
+            }
+        }
+
+        let profile = FilamentProfile::from(filament);
+        let existing = self
+            .conn
+            .query_row(
+                "SELECT id FROM filament_profiles
+                 WHERE deleted_at IS NULL AND lower(brand) = lower(?1) AND lower(material) = lower(?2) AND lower(color) = lower(?3)
+                 ORDER BY is_custom DESC, id LIMIT 1",
+                params![profile.brand, profile.material, profile.color],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if let Some(id) = existing {
+            return Ok(id);
+        }
+
+        report.profiles_created += 1;
+        self.add_favorite(profile)
+    }
+
+    /// Creates the local spool linked to `remote`, or refreshes its weight,
+    /// location and lot from Spoolman, which is authoritative for them.
+    /// Local notes, purchase details and tray assignments are kept.
+    pub fn import_server_spool(&self, remote: &ServerSpool, report: &mut SpoolmanImportReport) -> AppResult<i64> {
+        let initial_weight = remote
+            .initial_weight
+            .or(remote.filament.weight)
+            .unwrap_or_else(|| remote.remaining_weight.unwrap_or(0.0) + remote.used_weight);
+        let remaining_weight = remote
+            .remaining_weight
+            .unwrap_or((initial_weight - remote.used_weight).max(0.0));
+
+        let id = match self.get_spool_by_spoolman_id(remote.id)? {
+            Some(id) => {
+                let mut spool = self.get_spool(id)?;
+                spool.initial_weight = initial_weight;
+                spool.location = remote.location.clone().or(spool.location);
+                spool.lot_number = remote.lot_nr.clone().or(spool.lot_number);
+                spool.opened_date = spool.opened_date.or_else(|| date_part(&remote.first_used));
+                self.update_spool(spool.clone())?;
+                // Usage not reported yet is already subtracted locally but
+                // not on the server.
+                let pending: f64 = self.conn.query_row(
+                    "SELECT spoolman_unreported FROM spools WHERE id = ?1",
+                    params![id],
+                    |row| row.get(0),
+                )?;
+                let remaining_weight = (remaining_weight - pending).max(0.0);
+                if (spool.remaining_weight - remaining_weight).abs() >= 0.05 {
+                    self.set_spool_weight(id, remaining_weight, Some("spoolman".to_string()))?;
+                }
+                report.updated += 1;
+                id
+            }
+            None => {
+                let profile_id = self.profile_for_server_filament(&remote.filament, report)?;
+                let id = self.create_spool(Spool {
+                    id: None,
+                    profile_id: Some(profile_id),
+                    spoolman_id: Some(remote.id.to_string()),
+                    initial_weight,
+                    remaining_weight,
+                    lot_number: remote.lot_nr.clone(),
+                    purchase_date: date_part(&remote.registered),
+                    opened_date: date_part(&remote.first_used),
+                    location: remote.location.clone(),
+                    notes: remote.comment.clone(),
+                    purchase_price: remote.price,
+                    currency: None,
+                    printer_id: None,
+                    ams_id: None,
+                    tray_id: None,
+                    created_at: None,
+                })?;
+                report.created += 1;
+                id
+            }
+        };
+        report.spool_ids.push(id);
+        Ok(id)
+    }
+
+    pub fn import_server_spools(&self, remotes: &[ServerSpool]) -> AppResult<SpoolmanImportReport> {
+        let mut report = SpoolmanImportReport::default();
+        for remote in remotes {
+            self.import_server_spool(remote, &mut report)?;
+        }
+        Ok(report)
+    }
+
+    /// Linked spools with usage Spoolman has not been told about yet.
+    pub fn get_unreported_usage(&self) -> AppResult<Vec<UnreportedUsage>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT id, CAST(spoolman_id AS INTEGER), spoolman_unreported FROM spools
+             WHERE CAST(spoolman_id AS INTEGER) > 0 AND spoolman_unreported > 0 ORDER BY id",
+        )?;
+        let usage = stmt
+            .query_map([], |row| {
+                Ok(UnreportedUsage {
+                    spool_id: row.get(0)?,
+                    spoolman_id: row.get(1)?,
+                    grams: row.get(2)?,
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(usage)
+    }
+
+    /// Takes usage off the queue before it is sent, keeping usage recorded
+    /// meanwhile; `requeue_usage` puts it back if the report fails.
+    pub fn mark_usage_reported(&self, spool_id: i64, grams: f64) -> AppResult<()> {
+        self.conn.execute(
+            "UPDATE spools SET spoolman_unreported = MAX(spoolman_unreported - ?1, 0) WHERE id = ?2",
+            params![grams, spool_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn requeue_usage(&self, spool_id: i64, grams: f64) -> AppResult<()> {
+        self.conn.execute(
+            "UPDATE spools SET spoolman_unreported = spoolman_unreported + ?1 WHERE id = ?2",
+            params![grams, spool_id],
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,295 @@
+use super::Database;
+use crate::error::{AppError, AppResult};
+use rusqlite::{params, OptionalExtension, Result, Row};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Spool {
+    pub id: Option<i64>,
+    pub profile_id: Option<i64>,
+    pub spoolman_id: Option<String>,
+    pub initial_weight: f64,
+    pub remaining_weight: f64,
+    pub lot_number: Option<String>,
+    pub purchase_date: Option<String>,
+    pub opened_date: Option<String>,
+    pub location: Option<String>,
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub purchase_price: Option<f64>,
+    #[serde(default)]
+    pub currency: Option<String>,
+    #[serde(default)]
+    pub printer_id: Option<i64>,
+    #[serde(default)]
+    pub ams_id: Option<i32>,
+    #[serde(default)]
+    pub tray_id: Option<i32>,
+    #[serde(default)]
+    pub created_at: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SpoolWeightEntry {
+    pub id: i64,
+    pub spool_id: i64,
+    pub delta_grams: f64,
+    pub remaining_after: f64,
+    pub reason: Option<String>,
+    pub created_at: String,
+}
+
+const SPOOL_COLUMNS: &str = "id, profile_id, spoolman_id, initial_weight, remaining_weight, lot_number, purchase_date, opened_date, location, notes, printer_id, ams_id, tray_id, created_at, \
+    purchase_price, currency";
+
+fn row_to_spool(row: &Row) -> Result<Spool> {
+    Ok(Spool {
+        id: Some(row.get(0)?),
+        profile_id: row.get(1)?,
+        spoolman_id: row.get(2)?,
+        initial_weight: row.get(3)?,
+        remaining_weight: row.get(4)?,
+        lot_number: row.get(5)?,
+        purchase_date: row.get(6)?,
+        opened_date: row.get(7)?,
+        location: row.get(8)?,
+        notes: row.get(9)?,
+        printer_id: row.get(10)?,
+        ams_id: row.get(11)?,
+        tray_id: row.get(12)?,
+        created_at: row.get(13)?,
+        purchase_price: row.get(14)?,
+        currency: row.get(15)?,
+    })
+}
+
+/// Usage is always consumption; a negative figure would add weight back.
+pub(super) fn check_usage_grams(grams: f64) -> AppResult<()> {
+    if grams.is_finite() && grams > 0.0 {
+        Ok(())
+    } else {
+        Err(AppError::invalid(format!("Usage must be more than 0 g, got {}", grams)))
+    }
+}
+
+impl Database {
+    pub fn get_spools(&self, profile_id: Option<i64>) -> AppResult<Vec<Spool>> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {} FROM spools WHERE ?1 IS NULL OR profile_id = ?1 ORDER BY id",
+            SPOOL_COLUMNS
+        ))?;
+
+        let spools = stmt
+            .query_map(params![profile_id], row_to_spool)?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(spools)
+    }
+
+    pub fn get_spool(&self, id: i64) -> AppResult<Spool> {
+        self.conn
+            .query_row(
+                &format!("SELECT {} FROM spools WHERE id = ?1", SPOOL_COLUMNS),
+                params![id],
+                row_to_spool,
+            )
+            .optional()?
+            .ok_or_else(|| AppError::not_found(format!("No spool with id {}", id)))
+    }
+
+    pub fn create_spool(&self, spool: Spool) -> AppResult<i64> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "INSERT INTO spools (profile_id, spoolman_id, initial_weight, remaining_weight, lot_number, purchase_date, opened_date, location, notes,
+                purchase_price, currency)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                spool.profile_id,
+                spool.spoolman_id,
+                spool.initial_weight,
+                spool.remaining_weight,
+                spool.lot_number,
+                spool.purchase_date,
+                spool.opened_date,
+                spool.location,
+                spool.notes,
+                spool.purchase_price,
+                spool.currency,
+            ],
+        )?;
+        let id = tx.last_insert_rowid();
+        tx.execute(
+            "INSERT INTO spool_weight_ledger (spool_id, delta_grams, remaining_after, reason) VALUES (?1, 0, ?2, 'created')",
+            params![id, spool.remaining_weight],
+        )?;
+        tx.commit()?;
+        Ok(id)
+    }
+
+    /// Updates the descriptive fields. Weight changes go through
+    /// `record_spool_usage` / `set_spool_weight` so the ledger stays complete.
+    pub fn update_spool(&self, spool: Spool) -> AppResult<()> {
+        self.conn.execute(
+            "UPDATE spools SET profile_id = ?1, spoolman_id = ?2, initial_weight = ?3, lot_number = ?4, purchase_date = ?5, opened_date = ?6, location = ?7, notes = ?8,
+                purchase_price = ?9, currency = ?10
+             WHERE id = ?11",
+            params![
+                spool.profile_id,
+                spool.spoolman_id,
+                spool.initial_weight,
+                spool.lot_number,
+                spool.purchase_date,
+                spool.opened_date,
+                spool.location,
+                spool.notes,
+                spool.purchase_price,
+                spool.currency,
+                spool.id,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_spool(&self, id: i64) -> AppResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM spool_weight_ledger WHERE spool_id = ?1", params![id])?;
+        tx.execute("DELETE FROM spools WHERE id = ?1", params![id])?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Subtracts consumed filament from a spool and returns the new remaining
+    /// weight. Usage of a spool linked to Spoolman is queued for reporting;
+    /// see `get_unreported_usage`.
+    pub fn record_spool_usage(&self, spool_id: i64, grams: f64, reason: Option<String>) -> AppResult<f64> {
+        check_usage_grams(grams)?;
+        self.apply_spool_delta(spool_id, -grams, reason.or_else(|| Some("usage".to_string())), grams)
+    }
+
+    /// Sets the remaining weight from a scale reading, logging the difference.
+    pub fn set_spool_weight(&self, spool_id: i64, remaining_weight: f64, reason: Option<String>) -> AppResult<f64> {
+        let current = self.get_spool(spool_id)?.remaining_weight;
+        self.apply_spool_delta(
+            spool_id,
+            remaining_weight - current,
+            reason.or_else(|| Some("weighed".to_string())),
+            0.0,
+        )
+    }
+
+    fn apply_spool_delta(&self, spool_id: i64, delta: f64, reason: Option<String>, unreported: f64) -> AppResult<f64> {
+        let tx = self.conn.unchecked_transaction()?;
+        let current: f64 = tx.query_row(
+            "SELECT remaining_weight FROM spools WHERE id = ?1",
+            params![spool_id],
+            |row| row.get(0),
+        )?;
+        let remaining = (current + delta).max(0.0);
+
+        tx.execute(
+            "UPDATE spools SET remaining_weight = ?1,
+                spoolman_unreported = spoolman_unreported + CASE WHEN spoolman_id IS NULL THEN 0 ELSE ?2 END
+             WHERE id = ?3",
+            params![remaining, unreported, spool_id],
+        )?;
+        tx.execute(
+            "INSERT INTO spool_weight_ledger (spool_id, delta_grams, remaining_after, reason) VALUES (?1, ?2, ?3, ?4)",
+            params![spool_id, remaining - current, remaining, reason],
+        )?;
+        tx.commit()?;
+        Ok(remaining)
+    }
+
+    pub fn get_spool_ledger(&self, spool_id: i64) -> AppResult<Vec<SpoolWeightEntry>> {
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT id, spool_id, delta_grams, remaining_after, reason, created_at
+             FROM spool_weight_ledger WHERE spool_id = ?1 ORDER BY id",
+        )?;
+
+        let entries = stmt
+            .query_map(params![spool_id], |row| {
+                Ok(SpoolWeightEntry {
+                    id: row.get(0)?,
+                    spool_id: row.get(1)?,
+                    delta_grams: row.get(2)?,
+                    remaining_after: row.get(3)?,
+                    reason: row.get(4)?,
+                    created_at: row.get(5)?,
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(entries)
+    }
+
+    /// Marks a spool as loaded in an AMS tray, unloading whichever spool
+    /// previously occupied that tray.
+    pub fn assign_spool_to_tray(&self, spool_id: i64, printer_id: i64, ams_id: i32, tray_id: i32) -> AppResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "UPDATE spools SET printer_id = NULL, ams_id = NULL, tray_id = NULL
+             WHERE printer_id = ?1 AND ams_id = ?2 AND tray_id = ?3",
+            params![printer_id, ams_id, tray_id],
+        )?;
+        tx.execute(
+            "UPDATE spools SET printer_id = ?1, ams_id = ?2, tray_id = ?3 WHERE id = ?4",
+            params![printer_id, ams_id, tray_id, spool_id],
+        )?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn get_spool_in_tray(&self, printer_id: i64, ams_id: i32, tray_id: i32) -> AppResult<Option<Spool>> {
+        Ok(self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM spools WHERE printer_id = ?1 AND ams_id = ?2 AND tray_id = ?3",
+                    SPOOL_COLUMNS
+                ),
+                params![printer_id, ams_id, tray_id],
+                row_to_spool,
+            )
+            .optional()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::open_temp;
+    use super::Spool;
+    use crate::error::ErrorCode;
+
+    #[test]
+    fn usage_must_be_positive() {
+        let (_dir, pool) = open_temp();
+        let db = pool.get().unwrap();
+        let id = db
+            .create_spool(Spool {
+                id: None,
+                profile_id: None,
+                spoolman_id: None,
+                initial_weight: 1000.0,
+                remaining_weight: 1000.0,
+                lot_number: None,
+                purchase_date: None,
+                opened_date: None,
+                location: None,
+                notes: None,
+                purchase_price: None,
+                currency: None,
+                printer_id: None,
+                ams_id: None,
+                tray_id: None,
+                created_at: None,
+            })
+            .unwrap();
+
+        for grams in [0.0, -25.0, f64::NAN] {
+            let error = db.record_spool_usage(id, grams, None).unwrap_err();
+            assert_eq!(error.code, ErrorCode::InvalidInput);
+        }
+        assert_eq!(db.get_spool(id).unwrap().remaining_weight, 1000.0);
+        assert_eq!(db.record_spool_usage(id, 25.0, None).unwrap(), 975.0);
+    }
+}
@@ -0,0 +1,268 @@
+use super::{row_to_profile, Database, FilamentProfile, PROFILE_COLUMNS};
+use crate::error::AppResult;
+use rusqlite::{params, params_from_iter, types::Value, OptionalExtension, Result, Row};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Tag {
+    pub id: Option<i64>,
+    pub name: String,
+    #[serde(default)]
+    pub color: Option<String>,
+    #[serde(default)]
+    pub profile_count: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Collection {
+    pub id: Option<i64>,
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub position: i64,
+    #[serde(default)]
+    pub profile_count: i64,
+}
+
+const TAG_COLUMNS: &str = "t.id, t.name, t.color, (SELECT COUNT(*) FROM profile_tags pt JOIN filament_profiles p ON p.id = pt.profile_id
+    WHERE pt.tag_id = t.id AND p.deleted_at IS NULL)";
+const COLLECTION_COLUMNS: &str = "c.id, c.name, c.description, c.position, (SELECT COUNT(*) FROM collection_profiles cp JOIN filament_profiles p ON p.id = cp.profile_id
+    WHERE cp.collection_id = c.id AND p.deleted_at IS NULL)";
+
+fn row_to_tag(row: &Row) -> Result<Tag> {
+    Ok(Tag {
+        id: Some(row.get(0)?),
+        name: row.get(1)?,
+        color: row.get(2)?,
+        profile_count: row.get(3)?,
+    })
+}
+
+fn row_to_collection(row: &Row) -> Result<Collection> {
+    Ok(Collection {
+        id: Some(row.get(0)?),
+        name: row.get(1)?,
+        description: row.get(2)?,
+        position: row.get(3)?,
+        profile_count: row.get(4)?,
+    })
+}
+
+/// SQL condition (on `column`) and parameters selecting profiles that carry
+/// every tag in `tag_ids` and/or belong to `collection_id`. Shared by search
+/// and export so both filter the same way.
+pub(super) fn profile_filter(
+    column: &str,
+    tag_ids: Option<&[i64]>,
+    collection_id: Option<i64>,
+) -> (Vec<String>, Vec<Value>) {
+    let mut conditions = Vec::new();
+    let mut values = Vec::new();
+
+    if let Some(tag_ids) = tag_ids.filter(|ids| !ids.is_empty()) {
+        conditions.push(format!(
+            "{} IN (SELECT profile_id FROM profile_tags WHERE tag_id IN ({}) GROUP BY profile_id HAVING COUNT(*) = ?)",
+            column,
+            vec!["?"; tag_ids.len()].join(", ")
+        ));
+        values.extend(tag_ids.iter().map(|id| Value::Integer(*id)));
+        values.push(Value::Integer(tag_ids.len() as i64));
+    }
+    if let Some(collection_id) = collection_id {
+        conditions.push(format!(
+            "{} IN (SELECT profile_id FROM collection_profiles WHERE collection_id = ?)",
+            column
+        ));
+        values.push(Value::Integer(collection_id));
+    }
+
+    (conditions, values)
+}
+
+impl Database {
+    pub fn get_tags(&self) -> AppResult<Vec<Tag>> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(&format!("SELECT {} FROM tags t ORDER BY t.name COLLATE NOCASE", TAG_COLUMNS))?;
+        let tags = stmt.query_map([], row_to_tag)?.collect::<Result<Vec<_>>>()?;
+        Ok(tags)
+    }
+
+    /// Creates a tag, or returns the id of the existing tag with that name
+    /// (names are case-insensitive).
+    pub fn create_tag(&self, tag: Tag) -> AppResult<i64> {
+        let name = tag.name.trim();
+        if let Some(id) = self
+            .conn
+            .query_row("SELECT id FROM tags WHERE name = ?1", params![name], |row| row.get(0))
+            .optional()?
+        {
+            return Ok(id);
+        }
+
+        self.conn.execute(
+            "INSERT INTO tags (name, color) VALUES (?1, ?2)",
+            params![name, tag.color],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn update_tag(&self, tag: Tag) -> AppResult<()> {
+        self.conn.execute(
+            "UPDATE tags SET name = ?1, color = ?2 WHERE id = ?3",
+            params![tag.name.trim(), tag.color, tag.id],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_tag(&self, id: i64) -> AppResult<()> {
+        self.conn.execute("DELETE FROM tags WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    pub fn get_profile_tags(&self, profile_id: i64) -> AppResult<Vec<Tag>> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {} FROM tags t JOIN profile_tags l ON l.tag_id = t.id
+             WHERE l.profile_id = ?1 ORDER BY t.name COLLATE NOCASE",
+            TAG_COLUMNS
+        ))?;
+        let tags = stmt
+            .query_map(params![profile_id], row_to_tag)?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(tags)
+    }
+
+    /// Replaces the tags on a profile.
+    pub fn set_profile_tags(&self, profile_id: i64, tag_ids: &[i64]) -> AppResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute("DELETE FROM profile_tags WHERE profile_id = ?1", params![profile_id])?;
+        for tag_id in tag_ids {
+            tx.execute(
+                "INSERT OR IGNORE INTO profile_tags (profile_id, tag_id) VALUES (?1, ?2)",
+                params![profile_id, tag_id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn get_collections(&self) -> AppResult<Vec<Collection>> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {} FROM collections c ORDER BY c.position, c.id",
+            COLLECTION_COLUMNS
+        ))?;
+        let collections = stmt
+            .query_map([], row_to_collection)?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(collections)
+    }
+
+    /// Adds a collection after the existing ones.
+    pub fn create_collection(&self, collection: Collection) -> AppResult<i64> {
+        self.conn.execute(
+            "INSERT INTO collections (name, description, position)
+             VALUES (?1, ?2, (SELECT COALESCE(MAX(position) + 1, 0) FROM collections))",
+            params![collection.name.trim(), collection.description],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn update_collection(&self, collection: Collection) -> AppResult<()> {
+        self.conn.execute(
+            "UPDATE collections SET name = ?1, description = ?2 WHERE id = ?3",
+            params![collection.name.trim(), collection.description, collection.id],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_collection(&self, id: i64) -> AppResult<()> {
+        self.conn.execute("DELETE FROM collections WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    /// Sets the display order of collections to the order of `ids`.
+    pub fn reorder_collections(&self, ids: &[i64]) -> AppResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        for (position, id) in ids.iter().enumerate() {
+            tx.execute(
+                "UPDATE collections SET position = ?1 WHERE id = ?2",
+                params![position as i64, id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Profiles in a collection, in the collection's order.
+    pub fn get_collection_profiles(&self, collection_id: i64) -> AppResult<Vec<FilamentProfile>> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {} FROM filament_profiles JOIN collection_profiles cp ON cp.profile_id = filament_profiles.id
+             WHERE cp.collection_id = ?1 AND filament_profiles.deleted_at IS NULL ORDER BY cp.position, filament_profiles.id",
+            PROFILE_COLUMNS
+        ))?;
+        let profiles = stmt
+            .query_map(params![collection_id], row_to_profile)?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(profiles)
+    }
+
+    /// Appends a profile to a collection; adding it twice keeps its place.
+    pub fn add_profile_to_collection(&self, collection_id: i64, profile_id: i64) -> AppResult<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO collection_profiles (collection_id, profile_id, position)
+             VALUES (?1, ?2, (SELECT COALESCE(MAX(position) + 1, 0) FROM collection_profiles WHERE collection_id = ?1))",
+            params![collection_id, profile_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_profile_from_collection(&self, collection_id: i64, profile_id: i64) -> AppResult<()> {
+        self.conn.execute(
+            "DELETE FROM collection_profiles WHERE collection_id = ?1 AND profile_id = ?2",
+            params![collection_id, profile_id],
+        )?;
+        Ok(())
+    }
+
+    /// Sets the order of profiles in a collection to the order of `profile_ids`.
+    pub fn reorder_collection(&self, collection_id: i64, profile_ids: &[i64]) -> AppResult<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        for (position, profile_id) in profile_ids.iter().enumerate() {
+            tx.execute(
+                "UPDATE collection_profiles SET position = ?1 WHERE collection_id = ?2 AND profile_id = ?3",
+                params![position as i64, collection_id, profile_id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Ids of profiles matching the tag/collection filters, in collection
+    /// order when a collection is given.
+    pub(super) fn filtered_profile_ids(
+        &self,
+        tag_ids: Option<&[i64]>,
+        collection_id: Option<i64>,
+    ) -> AppResult<Vec<i64>> {
+        let (mut conditions, mut values) = profile_filter("p.id", tag_ids, collection_id);
+        conditions.insert(0, "p.deleted_at IS NULL".to_string());
+        let filter = format!("WHERE {}", conditions.join(" AND "));
+        let order = match collection_id {
+            Some(collection_id) => {
+                values.push(Value::Integer(collection_id));
+                "(SELECT position FROM collection_profiles WHERE collection_id = ? AND profile_id = p.id), p.id"
+            }
+            None => "p.id",
+        };
+
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT p.id FROM filament_profiles p {} ORDER BY {}",
+            filter, order
+        ))?;
+        let ids = stmt
+            .query_map(params_from_iter(values.iter()), |row| row.get(0))?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(ids)
+    }
+}
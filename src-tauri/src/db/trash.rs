@@ -0,0 +1,228 @@
+use super::exchange::ProfileKind;
+use super::history::{self, HistoryEntry, HistoryEvent};
+use super::{row_to_profile, Database, FilamentProfile, PROFILE_COLUMNS, PROFILE_COLUMN_COUNT};
+use crate::error::{AppError, AppResult};
+use rusqlite::{params, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TrashedProfile {
+    #[serde(flatten)]
+    pub profile: FilamentProfile,
+    /// UTC, `YYYY-MM-DD HH:MM:SS`.
+    pub deleted_at: String,
+}
+
+impl ProfileKind {
+    fn column(&self) -> &'static str {
+        match self {
+            ProfileKind::Favorite => "is_favorite",
+            ProfileKind::Custom => "is_custom",
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            ProfileKind::Favorite => "favorite",
+            ProfileKind::Custom => "custom profile",
+        }
+    }
+}
+
+impl Database {
+    fn log_profile_event(&self, event: HistoryEvent, profile: &FilamentProfile) -> AppResult<()> {
+        let mut entry = HistoryEntry::new(event);
+        entry.profile_id = profile.id;
+        entry.subject = Some(history::profile_label(profile));
+        entry.payload = serde_json::to_value(profile).ok();
+        self.record_history(&entry)?;
+        Ok(())
+    }
+
+    /// Moves a profile to the trash. Only a live profile of the given kind
+    /// is affected, so removing a favorite never touches a custom profile.
+    pub(super) fn trash_profile(&self, id: i64, kind: ProfileKind) -> AppResult<()> {
+        let profile = self
+            .conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM filament_profiles WHERE id = ?1 AND {} = 1 AND deleted_at IS NULL",
+                    PROFILE_COLUMNS,
+                    kind.column()
+                ),
+                params![id],
+                row_to_profile,
+            )
+            .optional()?
+            .ok_or_else(|| AppError::not_found(format!("No {} with id {}", kind.label(), id)))?;
+
+        self.conn.execute(
+            "UPDATE filament_profiles SET deleted_at = datetime('now') WHERE id = ?1",
+            params![id],
+        )?;
+        self.log_profile_event(HistoryEvent::ProfileDelete, &profile)
+    }
+
+    /// Trashed profiles, most recently deleted first.
+    pub fn get_trash(&self) -> AppResult<Vec<TrashedProfile>> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {}, deleted_at FROM filament_profiles WHERE deleted_at IS NOT NULL ORDER BY deleted_at DESC, id DESC",
+            PROFILE_COLUMNS
+        ))?;
+        let profiles = stmt
+            .query_map([], |row| {
+                Ok(TrashedProfile {
+                    profile: row_to_profile(row)?,
+                    deleted_at: row.get(PROFILE_COLUMN_COUNT)?,
+                })
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(profiles)
+    }
+
+    fn get_trashed_profile(&self, id: i64) -> AppResult<FilamentProfile> {
+        self.conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM filament_profiles WHERE id = ?1 AND deleted_at IS NOT NULL",
+                    PROFILE_COLUMNS
+                ),
+                params![id],
+                row_to_profile,
+            )
+            .optional()?
+            .ok_or_else(|| AppError::not_found(format!("Profile {} is not in the trash", id)))
+    }
+
+    pub fn restore_profile(&self, id: i64) -> AppResult<()> {
+        let profile = self.get_trashed_profile(id)?;
+        self.conn.execute(
+            "UPDATE filament_profiles SET deleted_at = NULL WHERE id = ?1",
+            params![id],
+        )?;
+        self.log_profile_event(HistoryEvent::ProfileRestore, &profile)
+    }
+
+    /// Deletes a trashed profile for good. Spools keep their data but lose
+    /// the link; tags and collection entries go with the profile.
+    pub fn purge_profile(&self, id: i64) -> AppResult<()> {
+        let profile = self.get_trashed_profile(id)?;
+        let tx = self.conn.unchecked_transaction()?;
+        self.delete_profile_rows(id)?;
+        self.log_profile_event(HistoryEvent::ProfilePurge, &profile)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Purges everything in the trash and returns how many profiles went.
+    pub fn empty_trash(&self) -> AppResult<usize> {
+        let tx = self.conn.unchecked_transaction()?;
+        let trash = self.get_trash()?;
+        for trashed in &trash {
+            if let Some(id) = trashed.profile.id {
+                self.delete_profile_rows(id)?;
+            }
+            self.log_profile_event(HistoryEvent::ProfilePurge, &trashed.profile)?;
+        }
+        tx.commit()?;
+        Ok(trash.len())
+    }
+
+    /// Deletes a profile and the rows that point at it. Done by hand so it
+    /// does not depend on `PRAGMA foreign_keys`; callers hold a transaction.
+    fn delete_profile_rows(&self, id: i64) -> AppResult<()> {
+        for sql in [
+            "DELETE FROM profile_tags WHERE profile_id = ?1",
+            "DELETE FROM collection_profiles WHERE profile_id = ?1",
+            "DELETE FROM profile_revisions WHERE profile_id = ?1",
+            "DELETE FROM calibration_results WHERE profile_id = ?1",
+            "UPDATE spools SET profile_id = NULL WHERE profile_id = ?1",
+            "UPDATE print_job_usage SET profile_id = NULL WHERE profile_id = ?1",
+            "UPDATE tray_assignments SET profile_id = NULL WHERE profile_id = ?1",
+            "UPDATE ams_usage SET profile_id = NULL WHERE profile_id = ?1",
+            "DELETE FROM filament_profiles WHERE id = ?1",
+        ] {
+            self.conn.execute(sql, params![id])?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{open_temp, FilamentProfile, Spool, Tag};
+    use crate::error::ErrorCode;
+
+    fn count(db: &super::Database, sql: &str) -> i64 {
+        db.conn.query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn purge_removes_dependent_rows_without_foreign_keys() {
+        let (_dir, pool) = open_temp();
+        let db = pool.get().unwrap();
+        db.conn.pragma_update(None, "foreign_keys", false).unwrap();
+
+        let profile_id = db
+            .add_favorite(FilamentProfile {
+                brand: "Polymaker".into(),
+                material: "PLA".into(),
+                color: "#FFFFFF".into(),
+                nozzle_temp: 210,
+                bed_temp: 60,
+                density: 1.24,
+                diameter: 1.75,
+                ..Default::default()
+            })
+            .unwrap();
+        let tag_id = db
+            .create_tag(Tag { id: None, name: "matte".into(), color: None, profile_count: 0 })
+            .unwrap();
+        db.set_profile_tags(profile_id, &[tag_id]).unwrap();
+        db.conn
+            .execute("INSERT INTO collections (name) VALUES ('Shelf')", [])
+            .unwrap();
+        db.add_profile_to_collection(db.conn.last_insert_rowid(), profile_id).unwrap();
+        let spool_id = db
+            .create_spool(Spool {
+                id: None,
+                profile_id: Some(profile_id),
+                spoolman_id: None,
+                initial_weight: 1000.0,
+                remaining_weight: 1000.0,
+                lot_number: None,
+                purchase_date: None,
+                opened_date: None,
+                location: None,
+                notes: None,
+                purchase_price: None,
+                currency: None,
+                printer_id: None,
+                ams_id: None,
+                tray_id: None,
+                created_at: None,
+            })
+            .unwrap();
+
+        db.remove_favorite(profile_id).unwrap();
+        assert_eq!(db.empty_trash().unwrap(), 1);
+
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM filament_profiles"), 0);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM profile_tags"), 0);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM collection_profiles"), 0);
+        assert_eq!(db.get_spool(spool_id).unwrap().profile_id, None);
+        assert_eq!(count(&db, "SELECT COUNT(*) FROM pragma_foreign_key_check"), 0);
+    }
+
+    #[test]
+    fn missing_profiles_are_not_found() {
+        let (_dir, pool) = open_temp();
+        let db = pool.get().unwrap();
+
+        assert_eq!(db.remove_favorite(42).unwrap_err().code, ErrorCode::NotFound);
+        assert_eq!(db.restore_profile(42).unwrap_err().code, ErrorCode::NotFound);
+        assert_eq!(db.purge_profile(42).unwrap_err().code, ErrorCode::NotFound);
+        assert_eq!(db.get_profile(42).unwrap_err().code, ErrorCode::NotFound);
+        assert_eq!(db.get_spool(42).unwrap_err().code, ErrorCode::NotFound);
+    }
+}
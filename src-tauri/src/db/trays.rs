@@ -0,0 +1,239 @@
+use super::Database;
+use crate::error::AppResult;
+use crate::mqtt::{AMSStatus, FilamentSyncCommand};
+use rusqlite::{params, Result, Row};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TrayStatus {
+    /// Synced, but no report has been seen since.
+    Unverified,
+    Match,
+    Mismatch,
+    /// The printer reports nothing loaded in the tray.
+    Empty,
+}
+
+impl TrayStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            TrayStatus::Unverified => "unverified",
+            TrayStatus::Match => "match",
+            TrayStatus::Mismatch => "mismatch",
+            TrayStatus::Empty => "empty",
+        }
+    }
+
+    fn parse(value: &str) -> Self {
+        match value {
+            "match" => TrayStatus::Match,
+            "mismatch" => TrayStatus::Mismatch,
+            "empty" => TrayStatus::Empty,
+            _ => TrayStatus::Unverified,
+        }
+    }
+}
+
+/// What SpoolSync last put in a tray, and how that compares to what the
+/// printer reported the last time it was asked.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TrayAssignment {
+    pub printer_id: i64,
+    pub ams_id: i32,
+    pub tray_id: i32,
+    pub profile_id: Option<i64>,
+    pub spool_id: Option<i64>,
+    pub brand: String,
+    pub material: String,
+    pub color: String,
+    pub assigned_at: String,
+    pub live_material: Option<String>,
+    pub live_color: Option<String>,
+    pub live_uuid: Option<String>,
+    pub checked_at: Option<String>,
+    pub status: TrayStatus,
+    /// Why the live tray does not match, e.g. "material is PETG".
+    pub mismatch: Option<String>,
+    /// Filament left in percent at the last report, if the AMS tracks it.
+    #[serde(default)]
+    pub live_remain: Option<i32>,
+}
+
+const TRAY_COLUMNS: &str = "printer_id, ams_id, tray_id, profile_id, spool_id, brand, material, color, assigned_at, \
+    live_material, live_color, live_uuid, checked_at, status, mismatch, live_remain, expected_uuid";
+
+fn row_to_tray(row: &Row) -> Result<TrayAssignment> {
+    Ok(TrayAssignment {
+        printer_id: row.get(0)?,
+        ams_id: row.get(1)?,
+        tray_id: row.get(2)?,
+        profile_id: row.get(3)?,
+        spool_id: row.get(4)?,
+        brand: row.get(5)?,
+        material: row.get(6)?,
+        color: row.get(7)?,
+        assigned_at: row.get(8)?,
+        live_material: row.get(9)?,
+        live_color: row.get(10)?,
+        live_uuid: row.get(11)?,
+        checked_at: row.get(12)?,
+        status: TrayStatus::parse(&row.get::<_, String>(13)?),
+        mismatch: row.get(14)?,
+        live_remain: row.get(15)?,
+    })
+}
+
+/// `RRGGBB`, uppercase, without `#` or alpha.
+fn normalize_color(color: &str) -> String {
+    color.trim_start_matches('#').chars().take(6).collect::<String>().to_uppercase()
+}
+
+impl Database {
+    /// Records a successful sync. The RFID baseline is reset and taken from
+    /// the next report.
+    pub fn assign_tray(&self, printer_id: i64, command: &FilamentSyncCommand) -> AppResult<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO tray_assignments (printer_id, ams_id, tray_id, profile_id, spool_id, brand, material, color)
+             VALUES (?1, ?2, ?3, COALESCE(?4, (SELECT profile_id FROM spools WHERE id = ?5)), ?5, ?6, ?7, ?8)",
+            params![
+                printer_id,
+                command.ams_id as i32,
+                command.slot_id as i32,
+                command.profile_id,
+                command.spool_id,
+                command.brand,
+                command.material,
+                normalize_color(&command.color),
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_tray_assignments(&self, printer_id: i64) -> AppResult<Vec<TrayAssignment>> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {} FROM tray_assignments WHERE printer_id = ?1 ORDER BY ams_id, tray_id",
+            TRAY_COLUMNS
+        ))?;
+        let trays = stmt
+            .query_map(params![printer_id], row_to_tray)?
+            .collect::<Result<Vec<_>>>()?;
+        Ok(trays)
+    }
+
+    pub fn clear_tray_assignment(&self, printer_id: i64, ams_id: i32, tray_id: i32) -> AppResult<()> {
+        self.conn.execute(
+            "DELETE FROM tray_assignments WHERE printer_id = ?1 AND ams_id = ?2 AND tray_id = ?3",
+            params![printer_id, ams_id, tray_id],
+        )?;
+        Ok(())
+    }
+
+    /// Compares every assignment of a printer with a live AMS report and
+    /// stores the outcome. An empty report (no AMS data received) changes
+    /// nothing. When a tray's remain % dropped between two matching
+    /// reports, the difference is logged as AMS usage; see `claim_ams_usage`.
+    pub fn reconcile_trays(&self, printer_id: i64, live: &[AMSStatus]) -> AppResult<Vec<TrayAssignment>> {
+        if live.is_empty() {
+            return self.get_tray_assignments(printer_id);
+        }
+
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {} FROM tray_assignments WHERE printer_id = ?1",
+            TRAY_COLUMNS
+        ))?;
+        let assignments = stmt
+            .query_map(params![printer_id], |row| Ok((row_to_tray(row)?, row.get::<_, Option<String>>(16)?)))?
+            .collect::<Result<Vec<_>>>()?;
+        drop(stmt);
+
+        let tx = self.conn.unchecked_transaction()?;
+        for (assignment, expected_uuid) in assignments {
+            let tray = live
+                .iter()
+                .filter(|unit| unit.ams_id as i32 == assignment.ams_id)
+                .flat_map(|unit| unit.trays.iter())
+                .find(|tray| tray.tray_id as i32 == assignment.tray_id);
+
+            let Some(tray) = tray else {
+                tx.execute(
+                    "UPDATE tray_assignments SET live_material = NULL, live_color = NULL, live_uuid = NULL, live_remain = NULL,
+                        checked_at = datetime('now'), status = ?1, mismatch = 'tray is empty'
+                     WHERE printer_id = ?2 AND ams_id = ?3 AND tray_id = ?4",
+                    params![TrayStatus::Empty.as_str(), printer_id, assignment.ams_id, assignment.tray_id],
+                )?;
+                continue;
+            };
+
+            let live_color = normalize_color(&tray.tray_color);
+            let mut problems = Vec::new();
+            if !tray.tray_type.eq_ignore_ascii_case(&assignment.material) {
+                problems.push(format!("material is {}", tray.tray_type));
+            }
+            if live_color != assignment.color {
+                problems.push(format!("color is #{}", live_color));
+            }
+            if let (Some(expected), Some(uuid)) = (&expected_uuid, &tray.tray_uuid) {
+                if expected != uuid {
+                    problems.push("RFID spool changed".to_string());
+                }
+            }
+
+            let (status, mismatch) = if problems.is_empty() {
+                (TrayStatus::Match, None)
+            } else {
+                println!(
+                    "⚠️  Printer {} AMS {} tray {}: expected {} {} #{}, but {}",
+                    printer_id,
+                    assignment.ams_id,
+                    assignment.tray_id,
+                    assignment.brand,
+                    assignment.material,
+                    assignment.color,
+                    problems.join(", ")
+                );
+                (TrayStatus::Mismatch, Some(problems.join(", ")))
+            };
+
+            let remain = tray.remain.map(i32::from);
+            let matched = status == TrayStatus::Match && assignment.status == TrayStatus::Match;
+            if let (true, Some(before), Some(after)) = (matched, assignment.live_remain, remain) {
+                let spool = assignment.spool_id.map(|id| self.get_spool(id)).transpose()?;
+                let weight = tray.tray_weight.or(spool.map(|spool| spool.initial_weight)).filter(|w| *w > 0.0);
+                if let Some(weight) = weight.filter(|_| after < before) {
+                    tx.execute(
+                        "INSERT INTO ams_usage (printer_id, ams_id, tray_id, spool_id, profile_id, grams) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                        params![
+                            printer_id,
+                            assignment.ams_id,
+                            assignment.tray_id,
+                            assignment.spool_id,
+                            assignment.profile_id,
+                            weight * f64::from(before - after) / 100.0,
+                        ],
+                    )?;
+                }
+            }
+
+            tx.execute(
+                "UPDATE tray_assignments SET live_material = ?1, live_color = ?2, live_uuid = ?3,
+                    expected_uuid = COALESCE(expected_uuid, ?3), checked_at = datetime('now'), status = ?4, mismatch = ?5, live_remain = ?9
+                 WHERE printer_id = ?6 AND ams_id = ?7 AND tray_id = ?8",
+                params![
+                    tray.tray_type,
+                    live_color,
+                    tray.tray_uuid,
+                    status.as_str(),
+                    mismatch,
+                    printer_id,
+                    assignment.ams_id,
+                    assignment.tray_id,
+                    remain,
+                ],
+            )?;
+        }
+        tx.commit()?;
+
+        self.get_tray_assignments(printer_id)
+    }
+}
@@ -0,0 +1,308 @@
+use super::{row_to_profile, Database, FilamentProfile, PROFILE_COLUMNS, PROFILE_COLUMN_COUNT};
+use crate::error::{AppError, AppResult};
+use crate::spoolman::{content_hash, SpoolmanFilament};
+use rusqlite::{params, OptionalExtension, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum UpstreamStatus {
+    Changed,
+    Removed,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FieldChange {
+    pub field: String,
+    pub local: serde_json::Value,
+    pub upstream: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct FavoriteUpdate {
+    pub profile_id: i64,
+    pub spoolman_id: String,
+    pub status: UpstreamStatus,
+    pub changes: Vec<FieldChange>,
+}
+
+/// Fields compared between a favorite and its upstream entry; `id` is the
+/// link itself and never differs.
+fn diff_fields(local: &SpoolmanFilament, upstream: &SpoolmanFilament) -> Vec<FieldChange> {
+    let local = serde_json::to_value(local).unwrap_or_default();
+    let upstream = serde_json::to_value(upstream).unwrap_or_default();
+    let (Some(local), Some(upstream)) = (local.as_object(), upstream.as_object()) else {
+        return Vec::new();
+    };
+
+    upstream
+        .iter()
+        .filter(|(field, _)| field.as_str() != "id")
+        .filter_map(|(field, upstream_value)| {
+            let local_value = local.get(field).cloned().unwrap_or(serde_json::Value::Null);
+            (local_value != *upstream_value).then(|| FieldChange {
+                field: field.clone(),
+                local: local_value,
+                upstream: upstream_value.clone(),
+            })
+        })
+        .collect()
+}
+
+impl Database {
+    pub(super) fn find_favorite_by_spoolman_id(&self, spoolman_id: &str) -> AppResult<Option<i64>> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT id FROM filament_profiles WHERE is_favorite = 1 AND deleted_at IS NULL AND spoolman_id = ?1 ORDER BY id LIMIT 1",
+                params![spoolman_id],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    /// Remembers which upstream version a favorite was saved from. The
+    /// entry itself is hashed, as `check_favorite_updates` does; a profile
+    /// does not keep every SpoolmanDB field.
+    fn record_upstream_hash(&self, id: i64, upstream: &SpoolmanFilament) -> AppResult<()> {
+        self.conn.execute(
+            "UPDATE filament_profiles SET upstream_hash = ?1, upstream_status = NULL, upstream_pending = NULL WHERE id = ?2",
+            params![content_hash(upstream), id],
+        )?;
+        Ok(())
+    }
+
+    /// Saves a SpoolmanDB entry as a favorite, tracking it from this version.
+    pub fn add_favorite_from_spoolman(&self, filament: &SpoolmanFilament) -> AppResult<i64> {
+        if let Some(id) = self.find_favorite_by_spoolman_id(&filament.id)? {
+            return Ok(id);
+        }
+        let tx = self.conn.unchecked_transaction()?;
+        let id = self.add_favorite(FilamentProfile::from(filament))?;
+        self.record_upstream_hash(id, filament)?;
+        tx.commit()?;
+        Ok(id)
+    }
+
+    /// Compares SpoolmanDB-sourced favorites against a fresh copy of the
+    /// database and flags those whose entry changed or disappeared. A
+    /// favorite saved without its upstream entry (from a file or a Spoolman
+    /// server) is tracked from the version seen on its first check.
+    pub fn check_favorite_updates(&self, upstream: &[SpoolmanFilament]) -> AppResult<Vec<FavoriteUpdate>> {
+        let by_id: HashMap<&str, &SpoolmanFilament> =
+            upstream.iter().map(|f| (f.id.as_str(), f)).collect();
+
+        let mut stmt = self.conn.prepare_cached(
+            "SELECT id, spoolman_id, upstream_hash, upstream_status FROM filament_profiles
+             WHERE is_favorite = 1 AND deleted_at IS NULL AND spoolman_id IS NOT NULL",
+        )?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i64>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+        drop(stmt);
+
+        let tx = self.conn.unchecked_transaction()?;
+        for (id, spoolman_id, hash, status) in rows {
+            if status.as_deref() == Some("ignored") {
+                continue;
+            }
+
+            match by_id.get(spoolman_id.as_str()) {
+                None => {
+                    tx.execute(
+                        "UPDATE filament_profiles SET upstream_status = 'removed', upstream_pending = NULL WHERE id = ?1",
+                        params![id],
+                    )?;
+                }
+                Some(filament) => {
+                    let upstream_hash = content_hash(filament);
+                    if hash.is_none() {
+                        tx.execute(
+                            "UPDATE filament_profiles SET upstream_hash = ?1, upstream_status = NULL, upstream_pending = NULL WHERE id = ?2",
+                            params![upstream_hash, id],
+                        )?;
+                    } else if hash.as_deref() == Some(upstream_hash.as_str()) {
+                        tx.execute(
+                            "UPDATE filament_profiles SET upstream_status = NULL, upstream_pending = NULL WHERE id = ?1",
+                            params![id],
+                        )?;
+                    } else {
+                        tx.execute(
+                            "UPDATE filament_profiles SET upstream_status = 'changed', upstream_pending = ?1 WHERE id = ?2",
+                            params![serde_json::to_string(filament).unwrap_or_default(), id],
+                        )?;
+                    }
+                }
+            }
+        }
+        tx.commit()?;
+
+        self.get_favorite_updates()
+    }
+
+    pub fn get_favorite_updates(&self) -> AppResult<Vec<FavoriteUpdate>> {
+        let mut stmt = self.conn.prepare_cached(&format!(
+            "SELECT {}, upstream_status, upstream_pending FROM filament_profiles
+             WHERE is_favorite = 1 AND deleted_at IS NULL AND upstream_status IN ('changed', 'removed') ORDER BY id",
+            PROFILE_COLUMNS
+        ))?;
+
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((row_to_profile(row)?, row.get::<_, String>(PROFILE_COLUMN_COUNT)?, row.get::<_, Option<String>>(PROFILE_COLUMN_COUNT + 1)?))
+            })?
+            .collect::<Result<Vec<_>>>()?;
+
+        let updates = rows
+            .into_iter()
+            .map(|(profile, status, pending)| {
+                let local = SpoolmanFilament::from(&profile);
+                let upstream = pending.and_then(|p| serde_json::from_str::<SpoolmanFilament>(&p).ok());
+                FavoriteUpdate {
+                    profile_id: profile.id.unwrap_or_default(),
+                    spoolman_id: local.id.clone(),
+                    status: if status == "removed" { UpstreamStatus::Removed } else { UpstreamStatus::Changed },
+                    changes: upstream.map(|u| diff_fields(&local, &u)).unwrap_or_default(),
+                }
+            })
+            .collect();
+
+        Ok(updates)
+    }
+
+    /// Applies the pending upstream version to a favorite. For a removed
+    /// entry the favorite is unlinked and kept as a purely local profile.
+    pub fn accept_favorite_update(&self, id: i64) -> AppResult<()> {
+        let (status, pending): (Option<String>, Option<String>) = self.conn.query_row(
+            "SELECT upstream_status, upstream_pending FROM filament_profiles WHERE id = ?1",
+            params![id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        match (status.as_deref(), pending) {
+            (Some("changed"), Some(pending)) => {
+                let upstream: SpoolmanFilament = serde_json::from_str(&pending)?;
+                let current = self.get_profile(id)?;
+                let mut profile = FilamentProfile::from(&upstream);
+                profile.id = current.id;
+                profile.notes = current.notes;
+                profile.price_per_kg = current.price_per_kg;
+                profile.currency = current.currency;
+
+                let tx = self.conn.unchecked_transaction()?;
+                self.update_profile_fields(profile.clone(), Some("SpoolmanDB update"))?;
+                self.record_upstream_hash(id, &upstream)?;
+                tx.commit()?;
+                Ok(())
+            }
+            (Some("removed"), _) => {
+                self.conn.execute(
+                    "UPDATE filament_profiles SET spoolman_id = NULL, upstream_hash = NULL, upstream_status = NULL, upstream_pending = NULL
+                     WHERE id = ?1",
+                    params![id],
+                )?;
+                Ok(())
+            }
+            _ => Err(AppError::not_found(format!("Favorite {} has no upstream change", id))),
+        }
+    }
+
+    /// Keeps the local favorite as is. A rejected change is remembered so
+    /// the same upstream version is not flagged again; a rejected removal
+    /// stops tracking the entry.
+    pub fn reject_favorite_update(&self, id: i64) -> AppResult<()> {
+        let (status, pending): (Option<String>, Option<String>) = self.conn.query_row(
+            "SELECT upstream_status, upstream_pending FROM filament_profiles WHERE id = ?1",
+            params![id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        match (status.as_deref(), pending) {
+            (Some("changed"), Some(pending)) => {
+                let upstream: SpoolmanFilament = serde_json::from_str(&pending)?;
+                self.conn.execute(
+                    "UPDATE filament_profiles SET upstream_hash = ?1, upstream_status = NULL, upstream_pending = NULL WHERE id = ?2",
+                    params![content_hash(&upstream), id],
+                )?;
+                Ok(())
+            }
+            (Some("removed"), _) => {
+                self.conn.execute(
+                    "UPDATE filament_profiles SET upstream_status = 'ignored', upstream_pending = NULL WHERE id = ?1",
+                    params![id],
+                )?;
+                Ok(())
+            }
+            _ => Err(AppError::not_found(format!("Favorite {} has no upstream change", id))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::open_temp;
+    use super::{SpoolmanFilament, UpstreamStatus};
+
+    fn filament(extruder_temp: i32) -> SpoolmanFilament {
+        serde_json::from_value(serde_json::json!({
+            "id": "polymaker_pla_polyterramatte_sakuragradient_1750_1000",
+            "manufacturer": "Polymaker",
+            "name": "PolyTerra Sakura Gradient",
+            "material": "PLA",
+            "density": 1.31,
+            "diameter": 1.75,
+            "color_hexes": ["F4B6C2", "FFFFFF"],
+            "weight": 1000.0,
+            "spool_type": "cardboard",
+            "extruder_temp": extruder_temp,
+            "extruder_temp_range": [190, 230],
+            "bed_temp": 60,
+            "finish": "matte",
+            "multi_color_direction": "longitudinal"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn unchanged_entry_is_not_flagged() {
+        let (_dir, pool) = open_temp();
+        let db = pool.get().unwrap();
+        db.add_favorite_from_spoolman(&filament(210)).unwrap();
+
+        assert!(db.check_favorite_updates(&[filament(210)]).unwrap().is_empty());
+        assert!(db.check_favorite_updates(&[filament(210)]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn accepted_update_is_not_flagged_again() {
+        let (_dir, pool) = open_temp();
+        let db = pool.get().unwrap();
+        let id = db.add_favorite_from_spoolman(&filament(210)).unwrap();
+
+        let updates = db.check_favorite_updates(&[filament(215)]).unwrap();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].status, UpstreamStatus::Changed);
+
+        db.accept_favorite_update(id).unwrap();
+        assert_eq!(db.get_profile(id).unwrap().nozzle_temp, 215);
+        assert!(db.check_favorite_updates(&[filament(215)]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn favorite_without_baseline_is_tracked_from_first_check() {
+        let (_dir, pool) = open_temp();
+        let db = pool.get().unwrap();
+        db.add_favorite((&filament(210)).into()).unwrap();
+
+        assert!(db.check_favorite_updates(&[filament(210)]).unwrap().is_empty());
+        assert_eq!(db.check_favorite_updates(&[filament(215)]).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,309 @@
+use super::FilamentProfile;
+use crate::error::AppError;
+use crate::materials;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Filament diameters a profile may declare, in mm.
+const DIAMETERS: &[f64] = &[1.75, 2.85];
+/// Inclusive; compared with a little slack since `1.80 - 1.75 > 0.05` in f64.
+const DIAMETER_TOLERANCE: f64 = 0.05;
+/// g/cm³; TPU sits near the bottom, metal-filled filaments near the top.
+const DENSITY_RANGE: (f64, f64) = (0.5, 4.5);
+
+/// A rejected profile field. `field` is the JSON name of the field so the
+/// form can show the message next to the input.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ValidationErrors(pub Vec<FieldError>);
+
+impl ValidationErrors {
+    fn add(&mut self, field: &str, message: impl Into<String>) {
+        self.0.push(FieldError {
+            field: field.to_string(),
+            message: message.into(),
+        });
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let messages: Vec<String> = self
+            .0
+            .iter()
+            .map(|error| format!("{}: {}", error.field, error.message))
+            .collect();
+        write!(f, "Invalid profile: {}", messages.join("; "))
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+/// Field errors are kept in `details.fields` as `[{ field, message }]`.
+impl From<ValidationErrors> for AppError {
+    fn from(errors: ValidationErrors) -> Self {
+        AppError::invalid(errors.to_string()).with_details(serde_json::json!({ "fields": errors.0 }))
+    }
+}
+
+/// Canonical form of a hex color: `#RRGGBB`, or `#RRGGBBAA` when an alpha
+/// channel is given. Accepts an optional `#` and the `RGB` shorthand.
+pub fn normalize_hex(value: &str) -> Option<String> {
+    let hex = value.trim().trim_start_matches('#');
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    match hex.len() {
+        3 => Some(format!(
+            "#{}",
+            hex.chars().flat_map(|c| [c, c]).collect::<String>().to_uppercase()
+        )),
+        6 | 8 => Some(format!("#{}", hex.to_uppercase())),
+        _ => None,
+    }
+}
+
+fn check_temperature(
+    errors: &mut ValidationErrors,
+    field: &str,
+    value: i32,
+    (min, max): (i32, i32),
+    material: &str,
+) {
+    if !(min..=max).contains(&value) {
+        errors.add(
+            field,
+            format!("{} °C is outside {}–{} °C for {}", value, min, max, material),
+        );
+    }
+}
+
+fn check_range(
+    errors: &mut ValidationErrors,
+    field: &str,
+    range: &Option<Vec<i32>>,
+    limits: (i32, i32),
+    material: &str,
+) {
+    let Some(range) = range else {
+        return;
+    };
+    if range.len() != 2 || range[0] > range[1] {
+        errors.add(field, "must be [min, max]");
+        return;
+    }
+    for &value in range {
+        check_temperature(errors, field, value, limits, material);
+    }
+}
+
+/// Checks a profile before it is written and normalizes it in place:
+/// names are trimmed and colors are rewritten as canonical hex.
+pub fn validate_profile(profile: &mut FilamentProfile) -> Result<(), ValidationErrors> {
+    validate(profile, false)
+}
+
+/// `validate_profile` for favorites, which may keep a nozzle or bed
+/// temperature of 0 where their SpoolmanDB entry has none.
+pub fn validate_favorite(profile: &mut FilamentProfile) -> Result<(), ValidationErrors> {
+    validate(profile, true)
+}
+
+fn validate(profile: &mut FilamentProfile, allow_unset_temperatures: bool) -> Result<(), ValidationErrors> {
+    let mut errors = ValidationErrors::default();
+
+    profile.brand = profile.brand.trim().to_string();
+    profile.material = profile.material.trim().to_string();
+    if profile.brand.is_empty() {
+        errors.add("brand", "is required");
+    }
+    if profile.material.is_empty() {
+        errors.add("material", "is required");
+    }
+
+    if !profile.color.trim().is_empty() {
+        match normalize_hex(&profile.color) {
+            Some(color) => profile.color = color,
+            None => errors.add("color", format!("'{}' is not a hex color", profile.color)),
+        }
+    }
+    // Stored without `#`, as SpoolmanDB does.
+    if let Some(hexes) = &mut profile.color_hexes {
+        for hex in hexes.iter_mut() {
+            match normalize_hex(hex) {
+                Some(normalized) => *hex = normalized.trim_start_matches('#').to_string(),
+                None => errors.add("color_hexes", format!("'{}' is not a hex color", hex)),
+            }
+        }
+    }
+
+    let limits = materials::temperature_limits(&profile.material);
+    let material = limits.family.as_deref().unwrap_or("this material");
+    let nozzle = (limits.nozzle_min, limits.nozzle_max);
+    let bed = (limits.bed_min, limits.bed_max);
+    if !(allow_unset_temperatures && profile.nozzle_temp == 0) {
+        check_temperature(&mut errors, "nozzle_temp", profile.nozzle_temp, nozzle, material);
+    }
+    if !(allow_unset_temperatures && profile.bed_temp == 0) {
+        check_temperature(&mut errors, "bed_temp", profile.bed_temp, bed, material);
+    }
+    check_range(&mut errors, "nozzle_temp_range", &profile.nozzle_temp_range, nozzle, material);
+    check_range(&mut errors, "bed_temp_range", &profile.bed_temp_range, bed, material);
+
+    let (density_min, density_max) = DENSITY_RANGE;
+    if !(density_min..=density_max).contains(&profile.density) {
+        errors.add(
+            "density",
+            format!("{} g/cm³ is outside {}–{} g/cm³", profile.density, density_min, density_max),
+        );
+    }
+    if !DIAMETERS
+        .iter()
+        .any(|diameter| (profile.diameter - diameter).abs() <= DIAMETER_TOLERANCE + 1e-9)
+    {
+        errors.add("diameter", format!("{} mm is not 1.75 or 2.85 mm", profile.diameter));
+    }
+
+    if profile.weight.is_some_and(|weight| weight <= 0.0) {
+        errors.add("weight", "must be positive");
+    }
+    if profile.spool_weight.is_some_and(|weight| weight < 0.0) {
+        errors.add("spool_weight", "must not be negative");
+    }
+    if profile.price_per_kg.is_some_and(|price| price < 0.0) {
+        errors.add("price_per_kg", "must not be negative");
+    }
+
+    if errors.0.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_hex, validate_favorite, validate_profile, FilamentProfile};
+    use crate::error::{AppError, ErrorCode};
+
+    fn profile(material: &str, nozzle_temp: i32, bed_temp: i32) -> FilamentProfile {
+        FilamentProfile {
+            brand: "Prusament".into(),
+            material: material.into(),
+            color: "#FF8000".into(),
+            nozzle_temp,
+            bed_temp,
+            density: 1.24,
+            diameter: 1.75,
+            ..Default::default()
+        }
+    }
+
+    fn fields(mut profile: FilamentProfile) -> Vec<String> {
+        match validate_profile(&mut profile) {
+            Ok(()) => Vec::new(),
+            Err(errors) => errors.0.into_iter().map(|error| error.field).collect(),
+        }
+    }
+
+    #[test]
+    fn density_bounds_are_inclusive() {
+        for (density, valid) in [(0.49, false), (0.5, true), (4.5, true), (4.51, false)] {
+            let mut p = profile("PLA", 210, 60);
+            p.density = density;
+            assert_eq!(fields(p).is_empty(), valid, "density {}", density);
+        }
+    }
+
+    #[test]
+    fn diameter_must_be_a_standard_size_within_tolerance() {
+        for (diameter, valid) in [
+            (1.75, true),
+            (1.70, true),
+            (1.80, true),
+            (1.69, false),
+            (1.81, false),
+            (2.85, true),
+            (2.80, true),
+            (2.90, true),
+            (2.91, false),
+            (3.0, false),
+        ] {
+            let mut p = profile("PLA", 210, 60);
+            p.diameter = diameter;
+            assert_eq!(fields(p).is_empty(), valid, "diameter {}", diameter);
+        }
+    }
+
+    #[test]
+    fn temperatures_are_checked_against_the_material_family() {
+        assert!(fields(profile("PLA", 170, 0)).is_empty());
+        assert!(fields(profile("PLA Matte", 250, 80)).is_empty());
+        assert_eq!(fields(profile("PLA", 169, 60)), ["nozzle_temp"]);
+        assert_eq!(fields(profile("PLA", 251, 81)), ["nozzle_temp", "bed_temp"]);
+
+        assert_eq!(fields(profile("PETG", 240, 49)), ["bed_temp"]);
+        assert!(fields(profile("Nylon CF", 300, 100)).is_empty());
+        assert_eq!(fields(profile("PA6-CF", 321, 100)), ["nozzle_temp"]);
+        // Not in the table: generic limits.
+        assert!(fields(profile("PEEK", 420, 150)).is_empty());
+        assert_eq!(fields(profile("PEEK", 451, 150)), ["nozzle_temp"]);
+
+        assert_eq!(fields(profile("PETG", 0, 0)), ["nozzle_temp", "bed_temp"]);
+        assert!(validate_favorite(&mut profile("PETG", 0, 0)).is_ok());
+        assert!(validate_favorite(&mut profile("PETG", 150, 0)).is_err());
+
+        let mut p = profile("PLA", 210, 60);
+        p.nozzle_temp_range = Some(vec![190, 260]);
+        p.bed_temp_range = Some(vec![60, 50]);
+        assert_eq!(fields(p), ["nozzle_temp_range", "bed_temp_range"]);
+    }
+
+    #[test]
+    fn colors_are_normalized() {
+        assert_eq!(normalize_hex("fff").as_deref(), Some("#FFFFFF"));
+        assert_eq!(normalize_hex("#FFFFFF").as_deref(), Some("#FFFFFF"));
+        assert_eq!(normalize_hex(" ffffff80 ").as_deref(), Some("#FFFFFF80"));
+        for garbage in ["", "#", "white", "#ffff", "12345g", "#ffffff8"] {
+            assert_eq!(normalize_hex(garbage), None, "{:?}", garbage);
+        }
+
+        let mut p = profile("PLA", 210, 60);
+        p.color = "abc".into();
+        p.color_hexes = Some(vec!["#ff0000".into(), "0f0".into()]);
+        validate_profile(&mut p).unwrap();
+        assert_eq!(p.color, "#AABBCC");
+        assert_eq!(p.color_hexes, Some(vec!["FF0000".to_string(), "00FF00".to_string()]));
+
+        let mut p = profile("PLA", 210, 60);
+        p.color = "white".into();
+        p.color_hexes = Some(vec!["nope".into()]);
+        assert_eq!(fields(p), ["color", "color_hexes"]);
+    }
+
+    #[test]
+    fn errors_carry_a_field_map_for_the_form() {
+        let mut p = profile("PLA", 300, 60);
+        p.brand = "  ".into();
+        p.density = 9.0;
+        let error = AppError::from(validate_profile(&mut p).unwrap_err());
+
+        assert_eq!(error.code, ErrorCode::InvalidInput);
+        assert!(error.message.starts_with("Invalid profile: brand: is required; nozzle_temp: "));
+        assert_eq!(
+            error.details.unwrap(),
+            serde_json::json!({
+                "fields": [
+                    { "field": "brand", "message": "is required" },
+                    { "field": "nozzle_temp", "message": "300 °C is outside 170–250 °C for PLA" },
+                    { "field": "density", "message": "9 g/cm³ is outside 0.5–4.5 g/cm³" },
+                ]
+            })
+        );
+    }
+}
@@ -0,0 +1,147 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Stable machine-readable error codes. The frontend switches on these,
+/// so existing values must never be renamed.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// Unexpected SQLite failure.
+    Database,
+    /// Another connection holds the database; retrying usually helps.
+    DatabaseLocked,
+    NotFound,
+    /// Rejected input, e.g. an out-of-range value or a wrong-kind id.
+    InvalidInput,
+    /// The printer is printing or paused; details carry its state.
+    PrinterBusy,
+    /// The printer refused the access code.
+    PrinterAuth,
+    PrinterUnreachable,
+    PrinterTimeout,
+    /// The target nozzle or extruder cannot run the profile.
+    NozzleMismatch,
+    /// HTTP request to SpoolmanDB, FilamentColors or Spoolman failed.
+    Network,
+    /// A file or response could not be parsed.
+    Parse,
+    Io,
+    /// Encrypted secret storage needs the passphrase first.
+    SecretsLocked,
+    Secrets,
+    /// No usable Bambu Studio installation or user profile directory.
+    BambuStudioUnavailable,
+    Internal,
+}
+
+/// Error returned by every command: `{ code, message, details? }`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AppError {
+    pub code: ErrorCode,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<serde_json::Value>,
+}
+
+pub type AppResult<T> = Result<T, AppError>;
+
+impl AppError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            details: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::NotFound, message)
+    }
+
+    pub fn invalid(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::InvalidInput, message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::Internal, message)
+    }
+}
+
+impl fmt::Display for AppError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for AppError {}
+
+/// The db layer returns rule violations and missing records as `AppError`
+/// itself; only a lookup that found no row is still recognized here.
+impl From<rusqlite::Error> for AppError {
+    fn from(error: rusqlite::Error) -> Self {
+        match &error {
+            rusqlite::Error::QueryReturnedNoRows => AppError::not_found("Record not found"),
+            rusqlite::Error::SqliteFailure(failure, _)
+                if matches!(
+                    failure.code,
+                    rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked
+                ) =>
+            {
+                AppError::new(ErrorCode::DatabaseLocked, error.to_string())
+            }
+            _ => AppError::new(ErrorCode::Database, error.to_string()),
+        }
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(error: std::io::Error) -> Self {
+        match error.kind() {
+            std::io::ErrorKind::NotFound => AppError::not_found(error.to_string()),
+            _ => AppError::new(ErrorCode::Io, error.to_string()),
+        }
+    }
+}
+
+impl From<serde_json::Error> for AppError {
+    fn from(error: serde_json::Error) -> Self {
+        AppError::new(ErrorCode::Parse, error.to_string())
+    }
+}
+
+impl From<reqwest::Error> for AppError {
+    fn from(error: reqwest::Error) -> Self {
+        let code = if error.is_decode() { ErrorCode::Parse } else { ErrorCode::Network };
+        let mut app_error = AppError::new(code, error.to_string());
+        if let Some(status) = error.status() {
+            app_error = app_error.with_details(serde_json::json!({ "status": status.as_u16() }));
+        }
+        app_error
+    }
+}
+
+impl From<rumqttc::ConnectionError> for AppError {
+    fn from(error: rumqttc::ConnectionError) -> Self {
+        use rumqttc::{ConnectReturnCode, ConnectionError};
+        match &error {
+            ConnectionError::ConnectionRefused(
+                ConnectReturnCode::BadUserNamePassword | ConnectReturnCode::NotAuthorized,
+            ) => AppError::new(ErrorCode::PrinterAuth, "Printer rejected the access code"),
+            ConnectionError::NetworkTimeout | ConnectionError::FlushTimeout => {
+                AppError::new(ErrorCode::PrinterTimeout, format!("Connection error: {}", error))
+            }
+            _ => AppError::new(ErrorCode::PrinterUnreachable, format!("Connection error: {}", error)),
+        }
+    }
+}
+
+impl From<rumqttc::ClientError> for AppError {
+    fn from(error: rumqttc::ClientError) -> Self {
+        AppError::new(ErrorCode::PrinterUnreachable, format!("MQTT request failed: {}", error))
+    }
+}
@@ -1,3 +1,4 @@
+use crate::error::{AppError, AppResult, ErrorCode};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -57,7 +58,7 @@ impl FilamentColorsClient {
         material_type: Option<String>,
         limit: Option<i32>,
         offset: Option<i32>,
-    ) -> Result<FilamentColorsResponse, String> {
+    ) -> AppResult<FilamentColorsResponse> {
         let mut url = format!("{}/api/swatch/", self.base_url);
         let mut params = vec![];
 
@@ -87,16 +88,21 @@ impl FilamentColorsClient {
             .header("User-Agent", "SpoolSync/1.0")
             .send()
             .await
-            .map_err(|e| format!("Failed to fetch: {}", e))?;
+            .map_err(|e| AppError::new(ErrorCode::Network, format!("Failed to fetch: {}", e)))?;
 
         if !response.status().is_success() {
-            return Err(format!("API error: {} - {}", response.status(), response.text().await.unwrap_or_default()));
+            let status = response.status().as_u16();
+            return Err(AppError::new(
+                ErrorCode::Network,
+                format!("API error: {} - {}", response.status(), response.text().await.unwrap_or_default()),
+            )
+            .with_details(serde_json::json!({ "status": status })));
         }
 
         let data: FilamentColorsResponse = response
             .json()
             .await
-            .map_err(|e| format!("Failed to parse JSON: {}", e))?;
+            .map_err(|e| AppError::new(ErrorCode::Parse, format!("Failed to parse JSON: {}", e)))?;
 
         println!("✅ Loaded {} swatches (total: {})", data.results.len(), data.count);
 
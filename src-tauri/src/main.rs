@@ -3,23 +3,154 @@
 mod db;
 mod mqtt;
 mod spoolman;
+mod spoolman_server;
 mod filamentcolors;
 mod bambu_studio;
+mod error;
+mod materials;
+mod secrets;
+mod threemf;
 
-use db::{Database, FilamentProfile, Settings};
-use mqtt::{BambuMqttClient, BambuPrinterConfig, FilamentSyncCommand, AMSStatus};
-use spoolman::{SpoolmanClient, SpoolmanFilament, SpoolmanResponse};
+use db::{
+    AmsUsage, BackupInfo, CalibrationResult, Collection, CostEstimate, Database, DbPool,
+    DryingEntry, EffectiveCalibration, ExportSelection, FavoriteUpdate, FilamentProfile,
+    HistoryEntry, HistoryEvent, HistoryFilter, ImportReport, JobUsageInput, PrintJob, Printer,
+    ProfileDocument, ProfileRevision, RevisionDiff, SearchQuery, SearchResults, Settings, Spool,
+    SpoolDryingStatus, SpoolWeightEntry, SpoolmanImportReport, Tag, TrashedProfile,
+    TrayAssignment, DEFAULT_NOZZLE_DIAMETER,
+};
+use mqtt::{BambuMqttClient, BambuPrinterConfig, FilamentSyncCommand, AMSStatus, NozzleInfo};
+use spoolman::{
+    SearchBoosts, SpoolmanCacheStatus, SpoolmanClient, SpoolmanDbSource, SpoolmanFilament,
+    SpoolmanResponse,
+};
+use spoolman_server::{ServerFilament, ServerInfo, ServerSpool, ServerVendor, SpoolmanServerClient};
 use filamentcolors::{FilamentColorsClient, FilamentColorsResponse};
-use bambu_studio::{BambuStudioManager, BambuFilamentProfile};
+use error::{AppError, AppResult, ErrorCode};
+use materials::{DryingRecommendation, TemperatureLimits};
+use bambu_studio::{BambuStudioManager, BambuFilamentProfile, DEFAULT_PRINTER_MODEL};
+use secrets::{SecretStatus, SecretStore};
+use threemf::ThreeMfFilament;
 use std::sync::{Arc, Mutex};
 use tauri::State;
 
 struct AppState {
-    db: Mutex<Database>,
+    db: DbPool,
     mqtt: Mutex<BambuMqttClient>,
     spoolman: Arc<SpoolmanClient>,
+    spoolman_server: Arc<SpoolmanServerClient>,
     filament_colors: Arc<FilamentColorsClient>,
     bambu_studio: Mutex<Option<BambuStudioManager>>,
+    secrets: Arc<SecretStore>,
+}
+
+/// Replaces plaintext access codes left in the database by installs from
+/// before secret storage with handles. Nothing moves while the encrypted
+/// file is locked; `unlock_secrets` runs this again once it is not.
+fn move_plaintext_secrets(db: &Database, secrets: &SecretStore) -> AppResult<()> {
+    if !secrets.is_unlocked() {
+        return Ok(());
+    }
+
+    let settings = db.get_settings()?;
+    let mut moved: Vec<(String, String)> = Vec::new();
+
+    if !settings.printer_access_code.is_empty() && !secrets::is_handle(&settings.printer_access_code) {
+        let handle = secrets.store(&settings.printer_access_code)?;
+        db.set_settings_access_code(&handle)?;
+        moved.push((settings.printer_access_code, handle));
+    }
+
+    for printer in db.get_printers()? {
+        let code = &printer.config.access_code;
+        if code.is_empty() || secrets::is_handle(code) {
+            continue;
+        }
+        let handle = match moved.iter().find(|(plain, _)| plain == code) {
+            Some((_, handle)) => handle.clone(),
+            None => {
+                let handle = secrets.store(code)?;
+                moved.push((code.clone(), handle.clone()));
+                handle
+            }
+        };
+        db.set_printer_access_code(printer.id.unwrap_or_default(), &handle)?;
+    }
+
+    if !moved.is_empty() {
+        println!("🔑 Moved {} access code(s) into secret storage", moved.len());
+    }
+    Ok(())
+}
+
+/// Deletes a secret once nothing in the database refers to it anymore.
+async fn drop_unused_secret(state: &State<'_, AppState>, handle: String) -> AppResult<()> {
+    if !secrets::is_handle(&handle) {
+        return Ok(());
+    }
+    let secrets = Arc::clone(&state.secrets);
+    with_db(state, move |db| {
+        if db.is_access_code_in_use(&handle)? {
+            return Ok(());
+        }
+        secrets.delete(&handle)
+    })
+    .await
+}
+
+/// History is best effort: a failed write is reported but never fails
+/// the action being recorded. It is written in the background on its own
+/// connection, so the command returns without waiting for it.
+fn log_history(state: &State<AppState>, entry: &HistoryEntry) {
+    let pool = state.db.clone();
+    let entry = entry.clone();
+    tauri::async_runtime::spawn_blocking(move || {
+        if let Err(e) = pool.get().and_then(|db| db.record_history(&entry)) {
+            println!("⚠️  Failed to record history: {}", e);
+        }
+    });
+}
+
+/// Usage is saved locally before this runs, so a command never waits for
+/// or fails on Spoolman; anything not reported is retried next time.
+fn report_spoolman_usage_in_background(state: &State<AppState>) {
+    let pool = state.db.clone();
+    let server = Arc::clone(&state.spoolman_server);
+    tauri::async_runtime::spawn(async move {
+        if let Err(e) = server.report_usage(&pool).await {
+            println!("⚠️  Could not report usage to Spoolman, will retry: {}", e);
+        }
+    });
+}
+
+/// Runs a query on its own pooled connection on the blocking thread pool.
+/// Every command that touches the database goes through here, so a slow
+/// query holds up neither the main thread nor other commands.
+async fn with_db<T, F>(state: &State<'_, AppState>, query: F) -> AppResult<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&Database) -> AppResult<T> + Send + 'static,
+{
+    let pool = state.db.clone();
+    tauri::async_runtime::spawn_blocking(move || query(&pool.get()?))
+        .await
+        .map_err(|e| AppError::internal(format!("Database task failed: {}", e)))?
+}
+
+fn preset_history<T>(
+    event: HistoryEvent,
+    name: &str,
+    payload: Option<serde_json::Value>,
+    result: &AppResult<T>,
+) -> HistoryEntry {
+    let mut entry = HistoryEntry::new(event);
+    entry.subject = Some(name.to_string());
+    entry.payload = payload;
+    if let Err(e) = result {
+        entry.success = false;
+        entry.error = Some(e.message.clone());
+    }
+    entry
 }
 
 #[tauri::command]
@@ -28,103 +159,743 @@ fn greet(name: &str) -> String {
 }
 
 #[tauri::command]
-fn get_favorites(state: State<AppState>) -> Result<Vec<FilamentProfile>, String> {
-    let db = state.db.lock().unwrap();
-    db.get_favorites().map_err(|e| e.to_string())
+async fn get_favorites(state: State<'_, AppState>) -> AppResult<Vec<FilamentProfile>> {
+    with_db(&state, |db| db.get_favorites()).await
+}
+
+#[tauri::command]
+async fn get_custom_profiles(state: State<'_, AppState>) -> AppResult<Vec<FilamentProfile>> {
+    with_db(&state, |db| db.get_custom_profiles()).await
 }
 
 #[tauri::command]
-fn get_custom_profiles(state: State<AppState>) -> Result<Vec<FilamentProfile>, String> {
-    let db = state.db.lock().unwrap();
-    db.get_custom_profiles().map_err(|e| e.to_string())
+async fn get_brands(state: State<'_, AppState>) -> AppResult<Vec<String>> {
+    with_db(&state, |db| db.get_brands()).await
 }
 
 #[tauri::command]
-fn get_brands(state: State<AppState>) -> Result<Vec<String>, String> {
-    let db = state.db.lock().unwrap();
-    db.get_brands().map_err(|e| e.to_string())
+async fn get_materials(state: State<'_, AppState>) -> AppResult<Vec<String>> {
+    with_db(&state, |db| db.get_materials()).await
 }
 
 #[tauri::command]
-fn get_materials(state: State<AppState>) -> Result<Vec<String>, String> {
-    let db = state.db.lock().unwrap();
-    db.get_materials().map_err(|e| e.to_string())
+async fn search_local(state: State<'_, AppState>, query: SearchQuery) -> AppResult<SearchResults> {
+    with_db(&state, move |db| db.search_local(query)).await
 }
 
 #[tauri::command]
-async fn get_spoolman_materials(state: State<'_, AppState>) -> Result<Vec<String>, String> {
+async fn get_spoolman_materials(state: State<'_, AppState>) -> AppResult<Vec<String>> {
     let spoolman = Arc::clone(&state.spoolman);
     spoolman.get_materials().await
 }
 
 #[tauri::command]
-fn add_favorite(state: State<AppState>, profile: FilamentProfile) -> Result<i64, String> {
-    let db = state.db.lock().unwrap();
-    db.add_favorite(profile).map_err(|e| e.to_string())
+async fn add_favorite(state: State<'_, AppState>, profile: FilamentProfile) -> AppResult<i64> {
+    with_db(&state, move |db| db.add_favorite(profile)).await
 }
 
 #[tauri::command]
-fn remove_favorite(state: State<AppState>, id: i64) -> Result<(), String> {
-    let db = state.db.lock().unwrap();
-    db.remove_favorite(id).map_err(|e| e.to_string())
+async fn add_favorite_from_spoolman(state: State<'_, AppState>, filament: SpoolmanFilament) -> AppResult<i64> {
+    with_db(&state, move |db| db.add_favorite_from_spoolman(&filament)).await
 }
 
 #[tauri::command]
-fn create_custom_profile(state: State<AppState>, profile: FilamentProfile) -> Result<i64, String> {
-    let db = state.db.lock().unwrap();
-    db.create_custom_profile(profile).map_err(|e| e.to_string())
+async fn get_profile(state: State<'_, AppState>, id: i64) -> AppResult<FilamentProfile> {
+    with_db(&state, move |db| db.get_profile(id)).await
 }
 
 #[tauri::command]
-fn update_custom_profile(state: State<AppState>, profile: FilamentProfile) -> Result<(), String> {
-    let db = state.db.lock().unwrap();
-    db.update_custom_profile(profile).map_err(|e| e.to_string())
+async fn get_profile_as_spoolman(state: State<'_, AppState>, id: i64) -> AppResult<SpoolmanFilament> {
+    let profile = with_db(&state, move |db| db.get_profile(id)).await?;
+    Ok(SpoolmanFilament::from(&profile))
 }
 
 #[tauri::command]
-fn delete_custom_profile(state: State<AppState>, id: i64) -> Result<(), String> {
-    let db = state.db.lock().unwrap();
-    db.delete_custom_profile(id).map_err(|e| e.to_string())
+async fn export_profiles(
+    state: State<'_, AppState>,
+    selection: Option<ExportSelection>,
+) -> AppResult<ProfileDocument> {
+    with_db(&state, move |db| db.export_profiles(selection.unwrap_or_default())).await
 }
 
 #[tauri::command]
-fn get_settings(state: State<AppState>) -> Result<Settings, String> {
-    let db = state.db.lock().unwrap();
-    db.get_settings().map_err(|e| e.to_string())
+async fn export_profiles_to_file(
+    state: State<'_, AppState>,
+    selection: Option<ExportSelection>,
+    path: String,
+) -> AppResult<usize> {
+    let document = with_db(&state, move |db| db.export_profiles(selection.unwrap_or_default())).await?;
+    let json = serde_json::to_string_pretty(&document)
+        .map_err(|e| AppError::internal(format!("Failed to serialize profiles: {}", e)))?;
+    std::fs::write(&path, json).map_err(|e| AppError {
+        message: format!("Failed to write {}: {}", path, e),
+        ..e.into()
+    })?;
+    println!("📤 Exported {} profile(s) to {}", document.profiles.len(), path);
+    Ok(document.profiles.len())
 }
 
 #[tauri::command]
-fn save_settings(state: State<AppState>, settings: Settings) -> Result<(), String> {
-    let db = state.db.lock().unwrap();
-    db.save_settings(settings).map_err(|e| e.to_string())
+async fn import_profiles(state: State<'_, AppState>, json: String) -> AppResult<ImportReport> {
+    let document = db::parse_document(&json)?;
+    let report = with_db(&state, move |db| {
+        db.create_backup("pre-import")?;
+        db.import_profiles(&document)
+    })
+    .await?;
+    println!(
+        "📥 Imported profiles: {} created, {} updated, {} skipped, {} rejected",
+        report.created, report.updated, report.skipped, report.rejected
+    );
+    Ok(report)
 }
 
 #[tauri::command]
-fn test_printer_connection(
-    state: State<AppState>,
-    config: BambuPrinterConfig,
-) -> Result<String, String> {
+async fn import_profiles_from_file(state: State<'_, AppState>, path: String) -> AppResult<ImportReport> {
+    let json = std::fs::read_to_string(&path).map_err(|e| AppError {
+        message: format!("Failed to read {}: {}", path, e),
+        ..e.into()
+    })?;
+    import_profiles(state, json).await
+}
+
+#[tauri::command]
+async fn remove_favorite(state: State<'_, AppState>, id: i64) -> AppResult<()> {
+    with_db(&state, move |db| db.remove_favorite(id)).await
+}
+
+#[tauri::command]
+async fn create_custom_profile(state: State<'_, AppState>, profile: FilamentProfile) -> AppResult<i64> {
+    with_db(&state, move |db| db.create_custom_profile(profile)).await
+}
+
+#[tauri::command]
+async fn update_custom_profile(
+    state: State<'_, AppState>,
+    profile: FilamentProfile,
+    note: Option<String>,
+) -> AppResult<()> {
+    with_db(&state, move |db| db.update_custom_profile(profile, note.as_deref())).await
+}
+
+#[tauri::command]
+async fn get_profile_revisions(
+    state: State<'_, AppState>,
+    profile_id: i64,
+) -> AppResult<Vec<ProfileRevision>> {
+    with_db(&state, move |db| db.get_profile_revisions(profile_id)).await
+}
+
+#[tauri::command]
+async fn diff_profile_revisions(
+    state: State<'_, AppState>,
+    profile_id: i64,
+    from: i64,
+    to: i64,
+) -> AppResult<RevisionDiff> {
+    with_db(&state, move |db| db.diff_profile_revisions(profile_id, from, to)).await
+}
+
+#[tauri::command]
+async fn revert_profile(state: State<'_, AppState>, profile_id: i64, revision: i64) -> AppResult<()> {
+    with_db(&state, move |db| db.revert_profile(profile_id, revision)).await
+}
+
+#[tauri::command]
+async fn delete_custom_profile(state: State<'_, AppState>, id: i64) -> AppResult<()> {
+    with_db(&state, move |db| db.delete_custom_profile(id)).await
+}
+
+#[tauri::command]
+async fn get_trash(state: State<'_, AppState>) -> AppResult<Vec<TrashedProfile>> {
+    with_db(&state, |db| db.get_trash()).await
+}
+
+#[tauri::command]
+async fn restore_profile(state: State<'_, AppState>, id: i64) -> AppResult<()> {
+    with_db(&state, move |db| db.restore_profile(id)).await
+}
+
+#[tauri::command]
+async fn purge_profile(state: State<'_, AppState>, id: i64) -> AppResult<()> {
+    with_db(&state, move |db| db.purge_profile(id)).await
+}
+
+#[tauri::command]
+async fn empty_trash(state: State<'_, AppState>) -> AppResult<usize> {
+    with_db(&state, |db| {
+        db.create_backup("pre-empty-trash")?;
+        db.empty_trash()
+    })
+    .await
+}
+
+#[tauri::command]
+async fn get_tags(state: State<'_, AppState>) -> AppResult<Vec<Tag>> {
+    with_db(&state, |db| db.get_tags()).await
+}
+
+#[tauri::command]
+async fn create_tag(state: State<'_, AppState>, tag: Tag) -> AppResult<i64> {
+    with_db(&state, move |db| db.create_tag(tag)).await
+}
+
+#[tauri::command]
+async fn update_tag(state: State<'_, AppState>, tag: Tag) -> AppResult<()> {
+    with_db(&state, move |db| db.update_tag(tag)).await
+}
+
+#[tauri::command]
+async fn delete_tag(state: State<'_, AppState>, id: i64) -> AppResult<()> {
+    with_db(&state, move |db| db.delete_tag(id)).await
+}
+
+#[tauri::command]
+async fn get_profile_tags(state: State<'_, AppState>, profile_id: i64) -> AppResult<Vec<Tag>> {
+    with_db(&state, move |db| db.get_profile_tags(profile_id)).await
+}
+
+#[tauri::command]
+async fn set_profile_tags(
+    state: State<'_, AppState>,
+    profile_id: i64,
+    tag_ids: Vec<i64>,
+) -> AppResult<()> {
+    with_db(&state, move |db| db.set_profile_tags(profile_id, &tag_ids)).await
+}
+
+#[tauri::command]
+async fn get_collections(state: State<'_, AppState>) -> AppResult<Vec<Collection>> {
+    with_db(&state, |db| db.get_collections()).await
+}
+
+#[tauri::command]
+async fn create_collection(state: State<'_, AppState>, collection: Collection) -> AppResult<i64> {
+    with_db(&state, move |db| db.create_collection(collection)).await
+}
+
+#[tauri::command]
+async fn update_collection(state: State<'_, AppState>, collection: Collection) -> AppResult<()> {
+    with_db(&state, move |db| db.update_collection(collection)).await
+}
+
+#[tauri::command]
+async fn delete_collection(state: State<'_, AppState>, id: i64) -> AppResult<()> {
+    with_db(&state, move |db| db.delete_collection(id)).await
+}
+
+#[tauri::command]
+async fn reorder_collections(state: State<'_, AppState>, ids: Vec<i64>) -> AppResult<()> {
+    with_db(&state, move |db| db.reorder_collections(&ids)).await
+}
+
+#[tauri::command]
+async fn get_collection_profiles(
+    state: State<'_, AppState>,
+    collection_id: i64,
+) -> AppResult<Vec<FilamentProfile>> {
+    with_db(&state, move |db| db.get_collection_profiles(collection_id)).await
+}
+
+#[tauri::command]
+async fn add_profile_to_collection(
+    state: State<'_, AppState>,
+    collection_id: i64,
+    profile_id: i64,
+) -> AppResult<()> {
+    with_db(&state, move |db| db.add_profile_to_collection(collection_id, profile_id)).await
+}
+
+#[tauri::command]
+async fn remove_profile_from_collection(
+    state: State<'_, AppState>,
+    collection_id: i64,
+    profile_id: i64,
+) -> AppResult<()> {
+    with_db(&state, move |db| db.remove_profile_from_collection(collection_id, profile_id)).await
+}
+
+#[tauri::command]
+async fn reorder_collection(
+    state: State<'_, AppState>,
+    collection_id: i64,
+    profile_ids: Vec<i64>,
+) -> AppResult<()> {
+    with_db(&state, move |db| db.reorder_collection(collection_id, &profile_ids)).await
+}
+
+#[tauri::command]
+async fn get_spools(state: State<'_, AppState>, profile_id: Option<i64>) -> AppResult<Vec<Spool>> {
+    with_db(&state, move |db| db.get_spools(profile_id)).await
+}
+
+#[tauri::command]
+async fn get_spool(state: State<'_, AppState>, id: i64) -> AppResult<Spool> {
+    with_db(&state, move |db| db.get_spool(id)).await
+}
+
+#[tauri::command]
+async fn create_spool(state: State<'_, AppState>, spool: Spool) -> AppResult<i64> {
+    with_db(&state, move |db| db.create_spool(spool)).await
+}
+
+#[tauri::command]
+async fn update_spool(state: State<'_, AppState>, spool: Spool) -> AppResult<()> {
+    with_db(&state, move |db| db.update_spool(spool)).await
+}
+
+#[tauri::command]
+async fn delete_spool(state: State<'_, AppState>, id: i64) -> AppResult<()> {
+    with_db(&state, move |db| db.delete_spool(id)).await
+}
+
+#[tauri::command]
+async fn record_spool_usage(
+    state: State<'_, AppState>,
+    spool_id: i64,
+    grams: f64,
+    reason: Option<String>,
+) -> AppResult<f64> {
+    let remaining = with_db(&state, move |db| db.record_spool_usage(spool_id, grams, reason)).await?;
+    report_spoolman_usage_in_background(&state);
+    Ok(remaining)
+}
+
+#[tauri::command]
+async fn set_spool_weight(
+    state: State<'_, AppState>,
+    spool_id: i64,
+    remaining_weight: f64,
+    reason: Option<String>,
+) -> AppResult<f64> {
+    with_db(&state, move |db| db.set_spool_weight(spool_id, remaining_weight, reason)).await
+}
+
+#[tauri::command]
+async fn get_spool_ledger(state: State<'_, AppState>, spool_id: i64) -> AppResult<Vec<SpoolWeightEntry>> {
+    with_db(&state, move |db| db.get_spool_ledger(spool_id)).await
+}
+
+#[tauri::command]
+async fn get_spool_in_tray(
+    state: State<'_, AppState>,
+    printer_id: i64,
+    ams_id: i32,
+    tray_id: i32,
+) -> AppResult<Option<Spool>> {
+    with_db(&state, move |db| db.get_spool_in_tray(printer_id, ams_id, tray_id)).await
+}
+
+#[tauri::command]
+async fn add_drying_entry(state: State<'_, AppState>, entry: DryingEntry) -> AppResult<i64> {
+    with_db(&state, move |db| db.add_drying_entry(entry)).await
+}
+
+#[tauri::command]
+async fn get_drying_log(state: State<'_, AppState>, spool_id: i64) -> AppResult<Vec<DryingEntry>> {
+    with_db(&state, move |db| db.get_drying_log(spool_id)).await
+}
+
+#[tauri::command]
+async fn delete_drying_entry(state: State<'_, AppState>, id: i64) -> AppResult<()> {
+    with_db(&state, move |db| db.delete_drying_entry(id)).await
+}
+
+#[tauri::command]
+async fn get_spool_drying_status(state: State<'_, AppState>, spool_id: i64) -> AppResult<SpoolDryingStatus> {
+    with_db(&state, move |db| db.get_spool_drying_status(spool_id)).await
+}
+
+#[tauri::command]
+async fn get_overdue_spools(state: State<'_, AppState>) -> AppResult<Vec<SpoolDryingStatus>> {
+    with_db(&state, |db| db.get_overdue_spools()).await
+}
+
+#[tauri::command]
+fn get_drying_recommendation(material: String) -> Option<DryingRecommendation> {
+    materials::drying_recommendation(&material)
+}
+
+#[tauri::command]
+fn get_temperature_limits(material: String) -> TemperatureLimits {
+    materials::temperature_limits(&material)
+}
+
+#[tauri::command]
+async fn add_calibration_result(state: State<'_, AppState>, result: CalibrationResult) -> AppResult<i64> {
+    with_db(&state, move |db| db.add_calibration_result(result)).await
+}
+
+#[tauri::command]
+async fn update_calibration_result(state: State<'_, AppState>, result: CalibrationResult) -> AppResult<()> {
+    with_db(&state, move |db| db.update_calibration_result(result)).await
+}
+
+#[tauri::command]
+async fn delete_calibration_result(state: State<'_, AppState>, id: i64) -> AppResult<()> {
+    with_db(&state, move |db| db.delete_calibration_result(id)).await
+}
+
+#[tauri::command]
+async fn get_calibration_results(
+    state: State<'_, AppState>,
+    profile_id: i64,
+) -> AppResult<Vec<CalibrationResult>> {
+    with_db(&state, move |db| db.get_calibration_results(profile_id)).await
+}
+
+/// Values that profile generation and AMS sync will use; the nozzle
+/// defaults to 0.4 mm of any type.
+#[tauri::command]
+async fn get_effective_calibration(
+    state: State<'_, AppState>,
+    profile_id: i64,
+    printer_id: Option<i64>,
+    nozzle_diameter: Option<f64>,
+    nozzle_type: Option<String>,
+) -> AppResult<EffectiveCalibration> {
+    with_db(&state, move |db| {
+        db.get_effective_calibration(
+            profile_id,
+            printer_id,
+            nozzle_diameter.unwrap_or(DEFAULT_NOZZLE_DIAMETER),
+            nozzle_type.as_deref(),
+        )
+    })
+    .await
+}
+
+#[tauri::command]
+async fn estimate_cost(
+    state: State<'_, AppState>,
+    grams: f64,
+    spool_id: Option<i64>,
+    profile_id: Option<i64>,
+) -> AppResult<CostEstimate> {
+    with_db(&state, move |db| db.estimate_cost(grams, spool_id, profile_id)).await
+}
+
+#[tauri::command]
+fn read_3mf_usage(path: String) -> AppResult<Vec<ThreeMfFilament>> {
+    threemf::read_filament_usage(&path)
+}
+
+#[tauri::command]
+async fn get_print_jobs(state: State<'_, AppState>) -> AppResult<Vec<PrintJob>> {
+    with_db(&state, |db| db.get_print_jobs()).await
+}
+
+#[tauri::command]
+async fn get_print_job(state: State<'_, AppState>, id: i64) -> AppResult<PrintJob> {
+    with_db(&state, move |db| db.get_print_job(id)).await
+}
+
+#[tauri::command]
+async fn create_print_job(state: State<'_, AppState>, job: PrintJob) -> AppResult<i64> {
+    with_db(&state, move |db| db.create_print_job(job)).await
+}
+
+#[tauri::command]
+async fn update_print_job(state: State<'_, AppState>, job: PrintJob) -> AppResult<()> {
+    with_db(&state, move |db| db.update_print_job(job)).await
+}
+
+#[tauri::command]
+async fn delete_print_job(state: State<'_, AppState>, id: i64) -> AppResult<()> {
+    with_db(&state, move |db| db.delete_print_job(id)).await
+}
+
+#[tauri::command]
+async fn add_job_usage(state: State<'_, AppState>, job_id: i64, usage: JobUsageInput) -> AppResult<i64> {
+    let id = with_db(&state, move |db| db.add_job_usage(job_id, usage)).await?;
+    report_spoolman_usage_in_background(&state);
+    Ok(id)
+}
+
+#[tauri::command]
+async fn get_ams_usage(state: State<'_, AppState>, printer_id: Option<i64>) -> AppResult<Vec<AmsUsage>> {
+    let printer_id = resolve_printer(&state, printer_id).await?.id.unwrap_or_default();
+    with_db(&state, move |db| db.get_ams_usage(printer_id)).await
+}
+
+#[tauri::command]
+async fn claim_ams_usage(state: State<'_, AppState>, job_id: i64) -> AppResult<Vec<i64>> {
+    let ids = with_db(&state, move |db| db.claim_ams_usage(job_id)).await?;
+    report_spoolman_usage_in_background(&state);
+    Ok(ids)
+}
+
+#[tauri::command]
+async fn get_settings(state: State<'_, AppState>) -> AppResult<Settings> {
+    with_db(&state, |db| db.get_settings()).await
+}
+
+#[tauri::command]
+async fn save_settings(state: State<'_, AppState>, mut settings: Settings) -> AppResult<()> {
+    let spoolmandb_source = SpoolmanDbSource::from_setting(settings.spoolmandb_source.as_deref());
+    spoolmandb_source.check()?;
+    let spoolman_server_url = settings.spoolman_server_url.clone();
+    settings.printer_access_code = state.secrets.store_if_plaintext(&settings.printer_access_code)?;
+    let previous = with_db(&state, move |db| {
+        let mut previous = vec![db.get_settings()?.printer_access_code];
+        if let Some(printer) = db.get_default_printer()? {
+            previous.push(printer.config.access_code);
+        }
+        db.save_settings(settings)?;
+        Ok(previous)
+    })
+    .await?;
+    state.spoolman.set_source(spoolmandb_source);
+    state.spoolman_server.set_base_url(spoolman_server_url.as_deref());
+    for handle in previous {
+        drop_unused_secret(&state, handle).await?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+async fn list_backups(state: State<'_, AppState>) -> AppResult<Vec<BackupInfo>> {
+    with_db(&state, |db| db.list_backups()).await
+}
+
+#[tauri::command]
+async fn create_backup(state: State<'_, AppState>) -> AppResult<BackupInfo> {
+    with_db(&state, |db| db.create_backup("manual")).await
+}
+
+/// Runs on the blocking pool like `with_db`, but on the pool itself: the
+/// restore closes every connection first.
+#[tauri::command]
+async fn restore_backup(state: State<'_, AppState>, file_name: String) -> AppResult<()> {
+    let pool = state.db.clone();
+    tauri::async_runtime::spawn_blocking(move || pool.restore_backup(&file_name))
+        .await
+        .map_err(|e| AppError::internal(format!("Database task failed: {}", e)))?
+}
+
+#[tauri::command]
+fn get_secret_status(state: State<AppState>) -> SecretStatus {
+    state.secrets.status()
+}
+
+#[tauri::command]
+async fn unlock_secrets(state: State<'_, AppState>, passphrase: String) -> AppResult<()> {
+    state.secrets.unlock(&passphrase)?;
+    let secrets = Arc::clone(&state.secrets);
+    with_db(&state, move |db| move_plaintext_secrets(db, &secrets)).await
+}
+
+/// Looks up a registered printer, falling back to the default one, with
+/// its access code resolved from secret storage.
+async fn resolve_printer(state: &State<'_, AppState>, printer_id: Option<i64>) -> AppResult<Printer> {
+    let mut printer = with_db(state, move |db| match printer_id {
+        Some(id) => db.get_printer(id),
+        None => db
+            .get_default_printer()?
+            .ok_or_else(|| AppError::not_found("No default printer configured")),
+    })
+    .await?;
+    printer.config.access_code = state.secrets.resolve(&printer.config.access_code)?;
+    Ok(printer)
+}
+
+#[tauri::command]
+async fn get_printers(state: State<'_, AppState>) -> AppResult<Vec<Printer>> {
+    with_db(&state, |db| db.get_printers()).await
+}
+
+#[tauri::command]
+async fn get_printer(state: State<'_, AppState>, id: i64) -> AppResult<Printer> {
+    with_db(&state, move |db| db.get_printer(id)).await
+}
+
+#[tauri::command]
+async fn create_printer(state: State<'_, AppState>, mut printer: Printer) -> AppResult<i64> {
+    printer.config.access_code = state.secrets.store_if_plaintext(&printer.config.access_code)?;
+    with_db(&state, move |db| db.create_printer(printer)).await
+}
+
+#[tauri::command]
+async fn update_printer(state: State<'_, AppState>, mut printer: Printer) -> AppResult<()> {
+    printer.config.access_code = state.secrets.store_if_plaintext(&printer.config.access_code)?;
+    let previous = with_db(&state, move |db| {
+        let previous = db
+            .get_printer(printer.id.unwrap_or_default())?
+            .config
+            .access_code;
+        db.update_printer(printer)?;
+        Ok(previous)
+    })
+    .await?;
+    drop_unused_secret(&state, previous).await
+}
+
+#[tauri::command]
+async fn delete_printer(state: State<'_, AppState>, id: i64) -> AppResult<()> {
+    let previous = with_db(&state, move |db| {
+        let previous = db.get_printer(id)?.config.access_code;
+        db.delete_printer(id)?;
+        Ok(previous)
+    })
+    .await?;
+    drop_unused_secret(&state, previous).await
+}
+
+#[tauri::command]
+async fn set_default_printer(state: State<'_, AppState>, id: i64) -> AppResult<()> {
+    with_db(&state, move |db| db.set_default_printer(id)).await
+}
+
+/// Tests a registered printer by id, or an unsaved `config` from the add-printer form.
+/// A stored access code is only ever sent to its own printer, so a `config`
+/// must carry the code itself rather than a `secret:` handle.
+#[tauri::command]
+async fn test_printer_connection(
+    state: State<'_, AppState>,
+    printer_id: Option<i64>,
+    config: Option<BambuPrinterConfig>,
+) -> AppResult<String> {
+    let config = match config {
+        Some(config) if secrets::is_handle(&config.access_code) => {
+            return Err(AppError::invalid(
+                "A stored access code can only be tested with its saved printer; enter the code to test new details",
+            ));
+        }
+        Some(config) => config,
+        None => resolve_printer(&state, printer_id).await?.config,
+    };
     let mqtt = state.mqtt.lock().unwrap();
     mqtt.test_connection(config)
 }
 
 #[tauri::command]
-fn get_ams_status(
-    state: State<AppState>,
-    config: BambuPrinterConfig,
-) -> Result<Vec<AMSStatus>, String> {
+async fn get_ams_status(
+    state: State<'_, AppState>,
+    printer_id: Option<i64>,
+) -> AppResult<Vec<AMSStatus>> {
+    let printer = resolve_printer(&state, printer_id).await?;
+    let statuses = {
+        let mqtt = state.mqtt.lock().unwrap();
+        mqtt.get_ams_status(printer.config)?
+    };
+
+    let printer_id = printer.id.unwrap_or_default();
+    let live = statuses.clone();
+    if let Err(e) = with_db(&state, move |db| db.reconcile_trays(printer_id, &live)).await {
+        println!("⚠️  Failed to reconcile tray assignments: {}", e);
+    }
+    Ok(statuses)
+}
+
+/// Nozzles the printer reports, so presets and calibration lookups can match
+/// what is actually installed.
+#[tauri::command]
+async fn get_printer_nozzles(
+    state: State<'_, AppState>,
+    printer_id: Option<i64>,
+) -> AppResult<Vec<NozzleInfo>> {
+    let printer = resolve_printer(&state, printer_id).await?;
     let mqtt = state.mqtt.lock().unwrap();
-    mqtt.get_ams_status(config)
+    mqtt.get_nozzles(printer.config)
 }
 
 #[tauri::command]
-fn sync_to_ams(
-    state: State<AppState>,
-    config: BambuPrinterConfig,
+async fn get_tray_assignments(
+    state: State<'_, AppState>,
+    printer_id: Option<i64>,
+) -> AppResult<Vec<TrayAssignment>> {
+    let printer_id = resolve_printer(&state, printer_id).await?.id.unwrap_or_default();
+    with_db(&state, move |db| db.get_tray_assignments(printer_id)).await
+}
+
+#[tauri::command]
+async fn clear_tray_assignment(
+    state: State<'_, AppState>,
+    printer_id: Option<i64>,
+    ams_id: i32,
+    tray_id: i32,
+) -> AppResult<()> {
+    let printer_id = resolve_printer(&state, printer_id).await?.id.unwrap_or_default();
+    with_db(&state, move |db| db.clear_tray_assignment(printer_id, ams_id, tray_id)).await
+}
+
+#[tauri::command]
+async fn sync_to_ams(
+    state: State<'_, AppState>,
+    printer_id: Option<i64>,
     command: FilamentSyncCommand,
-) -> Result<String, String> {
-    let mqtt = state.mqtt.lock().unwrap();
-    mqtt.sync_filament(config, command)
+) -> AppResult<String> {
+    let printer = resolve_printer(&state, printer_id).await?;
+    let printer_id = printer.id.unwrap_or_default();
+    let spool_id = command.spool_id;
+    let ams_id = command.ams_id as i32;
+    let tray_id = command.slot_id as i32;
+
+    // Checked before the tray changes; the AMS page asks the user first
+    // (`get_spool_drying_status`), so here it only warns.
+    let drying_warning = match spool_id {
+        Some(spool_id) => match with_db(&state, move |db| db.get_spool_drying_status(spool_id)).await {
+            Ok(status) => status.reason.filter(|_| status.overdue),
+            Err(e) => {
+                println!("⚠️  Could not check drying status of spool {}: {}", spool_id, e);
+                None
+            }
+        },
+        None => None,
+    };
+    if let Some(reason) = &drying_warning {
+        println!("⚠️  Spool {} is overdue for drying: {}", spool_id.unwrap_or_default(), reason);
+    }
+
+    let mut entry = HistoryEntry::new(HistoryEvent::AmsSync);
+    entry.printer_id = Some(printer_id);
+    entry.ams_id = Some(ams_id);
+    entry.tray_id = Some(tray_id);
+    entry.profile_id = command.profile_id;
+    entry.subject = Some(format!("{} {} {}", command.brand, command.material, command.color));
+    entry.payload = serde_json::to_value(&command).ok();
+
+    // Calibration depends on the nozzle, which only the printer knows, so
+    // it is looked up in the middle of the (blocking) sync.
+    let profile_id = command.profile_id;
+    let pool = state.db.clone();
+    let k_value_for = |nozzle: &NozzleInfo| -> AppResult<Option<f32>> {
+        let Some(profile_id) = profile_id else {
+            return Ok(None);
+        };
+        let nozzle_type = Some(nozzle.nozzle_type.as_str()).filter(|t| !t.is_empty());
+        let calibration = pool.get()?.get_effective_calibration(
+            profile_id,
+            Some(printer_id),
+            f64::from(nozzle.diameter),
+            nozzle_type,
+        )?;
+        Ok(calibration.pressure_advance.map(|k| k as f32))
+    };
+    let result = {
+        let mqtt = state.mqtt.lock().unwrap();
+        mqtt.sync_filament(printer.config, command.clone(), k_value_for)
+    };
+    if let Err(e) = &result {
+        entry.success = false;
+        entry.error = Some(e.message.clone());
+    }
+    log_history(&state, &entry);
+    let mut message = result?;
+
+    with_db(&state, move |db| {
+        db.assign_tray(printer_id, &command)?;
+        if let Some(spool_id) = spool_id {
+            db.assign_spool_to_tray(spool_id, printer_id, ams_id, tray_id)?;
+        }
+        Ok(())
+    })
+    .await?;
+    if let Some(reason) = drying_warning {
+        message = format!("{}\n⚠️ Spool is overdue for drying: {}", message, reason);
+    }
+
+    Ok(message)
 }
 
 #[tauri::command]
@@ -135,23 +906,133 @@ async fn search_spoolman(
     material: Option<String>,
     limit: usize,
     offset: usize,
-) -> Result<SpoolmanResponse, String> {
+) -> AppResult<SpoolmanResponse> {
+    // Ranking without boosts beats failing the search.
+    let boosts = with_db(&state, |db| db.get_search_boosts())
+        .await
+        .unwrap_or_else(|e| {
+            println!("⚠️  Could not load search boosts: {}", e);
+            SearchBoosts::default()
+        });
     let spoolman = Arc::clone(&state.spoolman);
     spoolman
-        .search_filaments(query, vendor, material, limit, offset)
+        .search_filaments(query, vendor, material, &boosts, limit, offset)
         .await
 }
 
 #[tauri::command]
-async fn get_spoolman_brands(state: State<'_, AppState>) -> Result<Vec<String>, String> {
+async fn get_spoolman_brands(state: State<'_, AppState>) -> AppResult<Vec<String>> {
     let spoolman = Arc::clone(&state.spoolman);
     spoolman.get_brands().await
 }
 
+/// Where the SpoolmanDB catalog came from and when it was last synced.
+#[tauri::command]
+async fn get_spoolman_status(state: State<'_, AppState>) -> AppResult<SpoolmanCacheStatus> {
+    let spoolman = Arc::clone(&state.spoolman);
+    spoolman.status().await
+}
+
+/// Revalidates SpoolmanDB, downloading it if it changed, and returns the
+/// favorites whose upstream entry changed or was removed.
 #[tauri::command]
-async fn sync_spoolman_db(state: State<'_, AppState>) -> Result<(), String> {
+async fn sync_spoolman_db(state: State<'_, AppState>) -> AppResult<Vec<FavoriteUpdate>> {
     let spoolman = Arc::clone(&state.spoolman);
-    spoolman.sync_database().await
+    spoolman.sync_database().await?;
+    let filaments = spoolman.get_all().await?;
+    with_db(&state, move |db| db.check_favorite_updates(&filaments)).await
+}
+
+/// Version of the configured Spoolman server; fails when it is unreachable.
+#[tauri::command]
+async fn get_spoolman_server_info(state: State<'_, AppState>) -> AppResult<ServerInfo> {
+    let server = Arc::clone(&state.spoolman_server);
+    server.info().await
+}
+
+#[tauri::command]
+async fn get_spoolman_server_vendors(state: State<'_, AppState>) -> AppResult<Vec<ServerVendor>> {
+    let server = Arc::clone(&state.spoolman_server);
+    server.get_vendors().await
+}
+
+#[tauri::command]
+async fn get_spoolman_server_filaments(
+    state: State<'_, AppState>,
+) -> AppResult<Vec<ServerFilament>> {
+    let server = Arc::clone(&state.spoolman_server);
+    server.get_filaments().await
+}
+
+#[tauri::command]
+async fn get_spoolman_server_spools(
+    state: State<'_, AppState>,
+    allow_archived: Option<bool>,
+) -> AppResult<Vec<ServerSpool>> {
+    let server = Arc::clone(&state.spoolman_server);
+    server.get_spools(allow_archived.unwrap_or(false)).await
+}
+
+#[tauri::command]
+async fn get_spoolman_server_locations(state: State<'_, AppState>) -> AppResult<Vec<String>> {
+    let server = Arc::clone(&state.spoolman_server);
+    server.get_locations().await
+}
+
+/// Imports every active Spoolman spool, linking new local spools to a
+/// matching profile or a new favorite, and refreshing ones imported before.
+#[tauri::command]
+async fn import_spoolman_server_spools(
+    state: State<'_, AppState>,
+) -> AppResult<SpoolmanImportReport> {
+    let server = Arc::clone(&state.spoolman_server);
+    let spools = server.get_spools(false).await?;
+    let report = with_db(&state, move |db| {
+        db.create_backup("pre-spoolman-import")?;
+        db.import_server_spools(&spools)
+    })
+    .await?;
+    println!(
+        "✅ Imported Spoolman spools: {} new, {} updated, {} new profiles",
+        report.created, report.updated, report.profiles_created
+    );
+    Ok(report)
+}
+
+/// Imports one Spoolman spool and returns the local spool, e.g. to load it
+/// into an AMS tray with `sync_to_ams`.
+#[tauri::command]
+async fn import_spoolman_server_spool(state: State<'_, AppState>, id: i64) -> AppResult<Spool> {
+    let server = Arc::clone(&state.spoolman_server);
+    let spool = server.get_spool(id).await?;
+    with_db(&state, move |db| {
+        let id = db.import_server_spool(&spool, &mut SpoolmanImportReport::default())?;
+        db.get_spool(id)
+    })
+    .await
+}
+
+/// Reports queued usage now and returns how many spools were reported.
+#[tauri::command]
+async fn report_spoolman_usage(state: State<'_, AppState>) -> AppResult<usize> {
+    let pool = state.db.clone();
+    let server = Arc::clone(&state.spoolman_server);
+    server.report_usage(&pool).await
+}
+
+#[tauri::command]
+async fn get_favorite_updates(state: State<'_, AppState>) -> AppResult<Vec<FavoriteUpdate>> {
+    with_db(&state, |db| db.get_favorite_updates()).await
+}
+
+#[tauri::command]
+async fn accept_favorite_update(state: State<'_, AppState>, id: i64) -> AppResult<()> {
+    with_db(&state, move |db| db.accept_favorite_update(id)).await
+}
+
+#[tauri::command]
+async fn reject_favorite_update(state: State<'_, AppState>, id: i64) -> AppResult<()> {
+    with_db(&state, move |db| db.reject_favorite_update(id)).await
 }
 
 #[tauri::command]
@@ -161,7 +1042,7 @@ async fn get_filament_swatches(
     material_type: Option<String>,
     limit: Option<i32>,
     offset: Option<i32>,
-) -> Result<FilamentColorsResponse, String> {
+) -> AppResult<FilamentColorsResponse> {
     let client = Arc::clone(&state.filament_colors);
     client.get_swatches(manufacturer, material_type, limit, offset).await
 }
@@ -189,11 +1070,35 @@ fn debug_filament(filament: SpoolmanFilament) {
 }
 
 #[tauri::command]
-fn list_bambu_profiles(state: State<AppState>) -> Result<Vec<String>, String> {
+async fn get_history(
+    state: State<'_, AppState>,
+    filter: HistoryFilter,
+) -> AppResult<Vec<HistoryEntry>> {
+    with_db(&state, move |db| db.get_history(filter)).await
+}
+
+/// What the app last synced into a tray as of `at` (UTC, `YYYY-MM-DD HH:MM:SS`).
+#[tauri::command]
+async fn get_tray_history_at(
+    state: State<'_, AppState>,
+    printer_id: i64,
+    ams_id: i32,
+    tray_id: i32,
+    at: String,
+) -> AppResult<Option<HistoryEntry>> {
+    with_db(&state, move |db| db.get_tray_history_at(printer_id, ams_id, tray_id, &at)).await
+}
+
+fn bambu_studio_not_configured() -> AppError {
+    AppError::new(ErrorCode::BambuStudioUnavailable, "Bambu Studio not configured")
+}
+
+#[tauri::command]
+fn list_bambu_profiles(state: State<AppState>) -> AppResult<Vec<String>> {
     let manager_opt = state.bambu_studio.lock().unwrap();
     match manager_opt.as_ref() {
         Some(manager) => manager.list_profiles(),
-        None => Err("Bambu Studio not configured".to_string()),
+        None => Err(bambu_studio_not_configured()),
     }
 }
 
@@ -201,11 +1106,11 @@ fn list_bambu_profiles(state: State<AppState>) -> Result<Vec<String>, String> {
 fn read_bambu_profile(
     state: State<AppState>,
     name: String,
-) -> Result<BambuFilamentProfile, String> {
+) -> AppResult<BambuFilamentProfile> {
     let manager_opt = state.bambu_studio.lock().unwrap();
     match manager_opt.as_ref() {
         Some(manager) => manager.read_profile(&name),
-        None => Err("Bambu Studio not configured".to_string()),
+        None => Err(bambu_studio_not_configured()),
     }
 }
 
@@ -213,12 +1118,18 @@ fn read_bambu_profile(
 fn create_bambu_profile(
     state: State<AppState>,
     profile: BambuFilamentProfile,
-) -> Result<String, String> {
-    let manager_opt = state.bambu_studio.lock().unwrap();
-    match manager_opt.as_ref() {
-        Some(manager) => manager.create_profile(&profile),
-        None => Err("Bambu Studio not configured".to_string()),
-    }
+) -> AppResult<String> {
+    let result = {
+        let manager_opt = state.bambu_studio.lock().unwrap();
+        match manager_opt.as_ref() {
+            Some(manager) => manager.create_profile(&profile),
+            None => Err(bambu_studio_not_configured()),
+        }
+    };
+    let payload = serde_json::to_value(&profile).ok();
+    let entry = preset_history(HistoryEvent::PresetCreate, &profile.name, payload, &result);
+    log_history(&state, &entry);
+    result
 }
 
 #[tauri::command]
@@ -226,53 +1137,126 @@ fn update_bambu_profile(
     state: State<AppState>,
     name: String,
     profile: BambuFilamentProfile,
-) -> Result<(), String> {
-    let manager_opt = state.bambu_studio.lock().unwrap();
-    match manager_opt.as_ref() {
-        Some(manager) => manager.update_profile(&name, &profile),
-        None => Err("Bambu Studio not configured".to_string()),
-    }
+) -> AppResult<()> {
+    let result = {
+        let manager_opt = state.bambu_studio.lock().unwrap();
+        match manager_opt.as_ref() {
+            Some(manager) => manager.update_profile(&name, &profile),
+            None => Err(bambu_studio_not_configured()),
+        }
+    };
+    let payload = serde_json::to_value(&profile).ok();
+    let entry = preset_history(HistoryEvent::PresetUpdate, &name, payload, &result);
+    log_history(&state, &entry);
+    result
 }
 
 #[tauri::command]
-fn delete_bambu_profile(state: State<AppState>, name: String) -> Result<(), String> {
-    let manager_opt = state.bambu_studio.lock().unwrap();
-    match manager_opt.as_ref() {
-        Some(manager) => manager.delete_profile(&name),
-        None => Err("Bambu Studio not configured".to_string()),
-    }
+fn delete_bambu_profile(state: State<AppState>, name: String) -> AppResult<()> {
+    let result = {
+        let manager_opt = state.bambu_studio.lock().unwrap();
+        match manager_opt.as_ref() {
+            Some(manager) => manager.delete_profile(&name),
+            None => Err(bambu_studio_not_configured()),
+        }
+    };
+    let entry = preset_history(HistoryEvent::PresetDelete, &name, None, &result);
+    log_history(&state, &entry);
+    result
 }
 
 #[tauri::command]
-fn sync_spoolman_to_bambu_studio(
-    state: State<AppState>,
+async fn sync_spoolman_to_bambu_studio(
+    state: State<'_, AppState>,
     vendor: String,
     name: String,
     material: String,
     color_hex: String,
     nozzle_temp: u16,
     bed_temp: u16,
-) -> Result<String, String> {
-    let manager_opt = state.bambu_studio.lock().unwrap();
-    match manager_opt.as_ref() {
-        Some(manager) => manager.create_from_spoolman(
-            &vendor,
-            &name,
-            &material,
-            &color_hex,
-            nozzle_temp,
-            bed_temp,
-            "X1C",
-        ),
-        None => Err("Bambu Studio not configured".to_string()),
-    }
+    profile_id: Option<i64>,
+    printer_id: Option<i64>,
+    nozzle_diameter: Option<f64>,
+    nozzle_type: Option<String>,
+) -> AppResult<String> {
+    let printer = with_db(&state, move |db| match printer_id {
+        Some(id) => db.get_printer(id).map(Some),
+        None => db.get_default_printer(),
+    })
+    .await?;
+    let printer_id = printer.as_ref().and_then(|p| p.id);
+    let model = printer
+        .map(|p| p.config.model)
+        .filter(|m| !m.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_PRINTER_MODEL.to_string());
+    let nozzle_type = nozzle_type.filter(|t| !t.is_empty());
+    let calibration = match profile_id {
+        Some(profile_id) => {
+            let nozzle_type = nozzle_type.clone();
+            with_db(&state, move |db| {
+                db.get_effective_calibration(
+                    profile_id,
+                    printer_id,
+                    nozzle_diameter.unwrap_or(DEFAULT_NOZZLE_DIAMETER),
+                    nozzle_type.as_deref(),
+                )
+            })
+            .await?
+        }
+        None => EffectiveCalibration::default(),
+    };
+    let result = {
+        let manager_opt = state.bambu_studio.lock().unwrap();
+        match manager_opt.as_ref() {
+            Some(manager) => manager.create_from_spoolman(
+                &vendor,
+                &name,
+                &material,
+                &color_hex,
+                nozzle_temp,
+                bed_temp,
+                &model,
+                &calibration,
+            ),
+            None => Err(bambu_studio_not_configured()),
+        }
+    };
+    let payload = serde_json::json!({
+        "vendor": vendor,
+        "material": material,
+        "color_hex": color_hex,
+        "nozzle_temp": nozzle_temp,
+        "bed_temp": bed_temp,
+        "profile_id": profile_id,
+        "printer_id": printer_id,
+        "printer_model": model,
+        "nozzle_diameter": nozzle_diameter,
+        "nozzle_type": nozzle_type,
+        "calibration": calibration,
+    });
+    let entry = preset_history(HistoryEvent::PresetCreate, &name, Some(payload), &result);
+    log_history(&state, &entry);
+    result
 }
 
 fn main() {
-    let db = Database::new().expect("Failed to initialize database");
+    let db = DbPool::open().expect("Failed to initialize database");
     let mqtt = BambuMqttClient::new().expect("Failed to initialize MQTT client");
     let spoolman = Arc::new(SpoolmanClient::new());
+    let spoolman_server = Arc::new(SpoolmanServerClient::new());
     let filament_colors = Arc::new(FilamentColorsClient::new());
+    let secrets = Arc::new(SecretStore::new().expect("Failed to initialize secret storage"));
+    if let Err(e) = db.get().and_then(|conn| move_plaintext_secrets(&conn, &secrets)) {
+        println!("⚠️  Could not move access codes into secret storage: {}", e);
+    }
+    match db.get().and_then(|conn| conn.get_settings()) {
+        Ok(settings) => {
+            let source = SpoolmanDbSource::from_setting(settings.spoolmandb_source.as_deref());
+            spoolman.set_source(source);
+            spoolman_server.set_base_url(settings.spoolman_server_url.as_deref());
+        }
+        Err(e) => println!("⚠️  Could not read the SpoolmanDB source, using the default: {}", e),
+    }
     
     let bambu_studio = match BambuStudioManager::new() {
         Ok(manager) => {
@@ -288,11 +1272,13 @@ fn main() {
     tauri::Builder::default()
         .plugin(tauri_plugin_shell::init())
         .manage(AppState {
-            db: Mutex::new(db),
+            db,
             mqtt: Mutex::new(mqtt),
             spoolman,
+            spoolman_server,
             filament_colors,
             bambu_studio: Mutex::new(bambu_studio),
+            secrets,
         })
         .invoke_handler(tauri::generate_handler![
             greet,
@@ -300,20 +1286,109 @@ fn main() {
             get_custom_profiles,
             get_brands,
             get_materials,
+            search_local,
             get_spoolman_materials,
             add_favorite,
+            add_favorite_from_spoolman,
+            get_profile,
+            get_profile_as_spoolman,
+            export_profiles,
+            export_profiles_to_file,
+            import_profiles,
+            import_profiles_from_file,
             remove_favorite,
             create_custom_profile,
             update_custom_profile,
+            get_profile_revisions,
+            diff_profile_revisions,
+            revert_profile,
             delete_custom_profile,
+            get_trash,
+            restore_profile,
+            purge_profile,
+            empty_trash,
+            get_tags,
+            create_tag,
+            update_tag,
+            delete_tag,
+            get_profile_tags,
+            set_profile_tags,
+            get_collections,
+            create_collection,
+            update_collection,
+            delete_collection,
+            reorder_collections,
+            get_collection_profiles,
+            add_profile_to_collection,
+            remove_profile_from_collection,
+            reorder_collection,
+            get_spools,
+            get_spool,
+            create_spool,
+            update_spool,
+            delete_spool,
+            record_spool_usage,
+            set_spool_weight,
+            get_spool_ledger,
+            get_spool_in_tray,
+            add_drying_entry,
+            get_drying_log,
+            delete_drying_entry,
+            get_spool_drying_status,
+            get_overdue_spools,
+            get_drying_recommendation,
+            get_temperature_limits,
+            add_calibration_result,
+            update_calibration_result,
+            delete_calibration_result,
+            get_calibration_results,
+            get_effective_calibration,
+            estimate_cost,
+            read_3mf_usage,
+            get_print_jobs,
+            get_print_job,
+            create_print_job,
+            update_print_job,
+            delete_print_job,
+            add_job_usage,
+            get_ams_usage,
+            claim_ams_usage,
             get_settings,
             save_settings,
+            list_backups,
+            create_backup,
+            restore_backup,
+            get_secret_status,
+            unlock_secrets,
+            get_printers,
+            get_printer,
+            create_printer,
+            update_printer,
+            delete_printer,
+            set_default_printer,
             test_printer_connection,
             get_ams_status,
+            get_printer_nozzles,
+            get_tray_assignments,
+            clear_tray_assignment,
             sync_to_ams,
             search_spoolman,
             get_spoolman_brands,
             sync_spoolman_db,
+            get_spoolman_status,
+            get_spoolman_server_info,
+            get_spoolman_server_vendors,
+            get_spoolman_server_filaments,
+            get_spoolman_server_spools,
+            get_spoolman_server_locations,
+            import_spoolman_server_spools,
+            import_spoolman_server_spool,
+            report_spoolman_usage,
+            get_favorite_updates,
+            accept_favorite_update,
+            reject_favorite_update,
+            get_history,
+            get_tray_history_at,
             get_filament_swatches,
             debug_filament,
             list_bambu_profiles,
@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+
+/// Typical drying settings for a material family (filament dryer or oven).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DryingRecommendation {
+    pub family: String,
+    pub temperature_c: u32,
+    pub hours: u32,
+    /// Absorbs enough moisture to affect prints within days of exposure.
+    pub hygroscopic: bool,
+    /// Days a spool can sit open (since opening or last drying) before it
+    /// should be dried again; `None` when exposure rarely matters.
+    pub max_open_days: Option<u32>,
+}
+
+/// (family, °C, hours, hygroscopic, max open days). Longer prefixes come
+/// first so `PETG` is not matched as `PET` and `PCTG` not as `PC`.
+const DRYING_TABLE: &[(&str, u32, u32, bool, Option<u32>)] = &[
+    ("PCTG", 65, 6, false, Some(14)),
+    ("PETG", 65, 6, false, Some(14)),
+    ("BVOH", 55, 8, true, Some(1)),
+    ("HIPS", 60, 4, false, None),
+    ("PET", 65, 6, false, Some(14)),
+    ("PVA", 55, 8, true, Some(1)),
+    ("TPU", 55, 8, true, Some(7)),
+    ("TPE", 55, 8, true, Some(7)),
+    ("ABS", 80, 4, false, None),
+    ("ASA", 80, 4, false, None),
+    ("PLA", 50, 6, false, None),
+    ("PA", 80, 12, true, Some(2)),
+    ("PC", 80, 8, true, Some(3)),
+];
+
+/// Upper-cased material name with "NYLON…" spelled as "PA…", ready for
+/// prefix matching against the tables above.
+fn normalize(material: &str) -> String {
+    let upper = material.trim().to_uppercase();
+    upper.strip_prefix("NYLON").map(|rest| format!("PA{}", rest)).unwrap_or(upper)
+}
+
+/// Temperatures a profile of a material family may use. They are wider
+/// than any vendor's recommended window; values outside are typos or
+/// would damage the printer.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct TemperatureLimits {
+    /// `None` for materials not in the table, which get generic limits.
+    pub family: Option<String>,
+    pub nozzle_min: i32,
+    pub nozzle_max: i32,
+    /// 0 means an unheated bed is fine.
+    pub bed_min: i32,
+    pub bed_max: i32,
+}
+
+/// (family, nozzle min, nozzle max, bed min, bed max) in °C, ordered like
+/// `DRYING_TABLE`.
+const TEMPERATURE_TABLE: &[(&str, i32, i32, i32, i32)] = &[
+    ("PCTG", 220, 280, 50, 100),
+    ("PETG", 200, 280, 50, 100),
+    ("BVOH", 180, 240, 0, 80),
+    ("HIPS", 210, 270, 70, 120),
+    ("PET", 210, 290, 50, 100),
+    ("PVA", 170, 230, 0, 80),
+    ("TPU", 180, 260, 0, 80),
+    ("TPE", 180, 260, 0, 80),
+    ("ABS", 220, 290, 70, 120),
+    ("ASA", 220, 290, 70, 120),
+    ("PLA", 170, 250, 0, 80),
+    ("PA", 230, 320, 0, 120),
+    ("PC", 240, 320, 70, 130),
+];
+
+/// Limits for materials outside `TEMPERATURE_TABLE` (PEEK, PEI, PPS, …).
+const GENERIC_LIMITS: (i32, i32, i32, i32) = (150, 450, 0, 160);
+
+/// Looks up temperature limits by material name, matched like
+/// `drying_recommendation`.
+pub fn temperature_limits(material: &str) -> TemperatureLimits {
+    let family = normalize(material);
+
+    match TEMPERATURE_TABLE.iter().find(|(prefix, ..)| family.starts_with(prefix)) {
+        Some(&(prefix, nozzle_min, nozzle_max, bed_min, bed_max)) => TemperatureLimits {
+            family: Some(prefix.to_string()),
+            nozzle_min,
+            nozzle_max,
+            bed_min,
+            bed_max,
+        },
+        None => {
+            let (nozzle_min, nozzle_max, bed_min, bed_max) = GENERIC_LIMITS;
+            TemperatureLimits {
+                family: None,
+                nozzle_min,
+                nozzle_max,
+                bed_min,
+                bed_max,
+            }
+        }
+    }
+}
+
+/// Looks up drying settings by material name, e.g. "PA6-CF" → PA,
+/// "PETG HF" → PETG. Returns `None` for unknown materials.
+pub fn drying_recommendation(material: &str) -> Option<DryingRecommendation> {
+    let family = normalize(material);
+
+    DRYING_TABLE
+        .iter()
+        .find(|(prefix, ..)| family.starts_with(prefix))
+        .map(|&(prefix, temperature_c, hours, hygroscopic, max_open_days)| DryingRecommendation {
+            family: prefix.to_string(),
+            temperature_c,
+            hours,
+            hygroscopic,
+            max_open_days,
+        })
+}
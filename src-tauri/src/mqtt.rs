@@ -1,24 +1,50 @@
+use crate::error::{AppError, AppResult, ErrorCode};
 use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS, TlsConfiguration, Transport};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BambuPrinterConfig {
     pub name: String,
+    #[serde(default)]
+    pub model: String,
     pub ip_address: String,
     pub access_code: String,
     pub serial_number: String,
+    #[serde(default)]
+    pub default_ams: u8,
+    #[serde(default)]
+    pub default_tray: u8,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct FilamentSyncCommand {
+    #[serde(default)]
+    pub ams_id: u8,
     pub slot_id: u8,
     pub brand: String,
     pub material: String,
     pub color: String,
     pub nozzle_temp: u16,
     pub bed_temp: u16,
+    /// Extruder the caller expects the AMS to feed (H2D: 0 = right, 1 = left).
+    #[serde(default)]
+    pub extruder_id: Option<u8>,
+    /// Nozzle diameter the profile was tuned for, checked against the target extruder.
+    #[serde(default)]
+    pub nozzle_diameter: Option<f32>,
+    /// Inventory spool being loaded; recorded as the tray's occupant on success.
+    #[serde(default)]
+    pub spool_id: Option<i64>,
+    /// Profile being loaded; recorded as the tray's intended profile on success.
+    #[serde(default)]
+    pub profile_id: Option<i64>,
+    /// Pressure advance for the tray. Filled from the profile's calibration
+    /// results when not given; left to the printer when neither has one.
+    #[serde(default)]
+    pub k_value: Option<f32>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -28,19 +54,54 @@ pub struct AMSTrayInfo {
     pub tray_color: String,
     pub nozzle_temp_min: u16,
     pub nozzle_temp_max: u16,
+    /// RFID spool serial of Bambu spools; `None` for third-party spools.
+    #[serde(default)]
+    pub tray_uuid: Option<String>,
+    /// Filament left in percent as estimated by the AMS; `None` when it does
+    /// not track the spool (reported as -1).
+    #[serde(default)]
+    pub remain: Option<u8>,
+    /// Net filament weight of the spool in grams, from the RFID tag.
+    #[serde(default)]
+    pub tray_weight: Option<f64>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct AMSStatus {
     pub ams_id: u8,
+    #[serde(default)]
+    pub extruder_id: u8,
     pub trays: Vec<AMSTrayInfo>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct NozzleInfo {
+    pub extruder_id: u8,
+    pub diameter: f32,
+    pub nozzle_type: String,
+}
+
+#[derive(Debug, Clone, Default)]
 pub struct PrinterState {
     pub is_printing: bool,
     pub is_paused: bool,
     pub print_state: Option<String>,
+    pub nozzles: Vec<NozzleInfo>,
+    /// AMS unit id → extruder id. Single-nozzle printers map every unit to 0.
+    pub ams_extruders: HashMap<u8, u8>,
+    /// Whether the report carried the `ams` block. Incremental reports leave
+    /// out AMS and nozzle data, so the mapping above may be missing.
+    pub complete: bool,
+}
+
+impl PrinterState {
+    pub fn extruder_for_ams(&self, ams_id: u8) -> u8 {
+        self.ams_extruders.get(&ams_id).copied().unwrap_or(0)
+    }
+
+    pub fn nozzle(&self, extruder_id: u8) -> Option<&NozzleInfo> {
+        self.nozzles.iter().find(|n| n.extruder_id == extruder_id)
+    }
 }
 
 #[derive(Debug)]
@@ -109,19 +170,194 @@ fn material_to_tray_info_idx(material: &str) -> String {
     }
 }
 
+/// Tray index used by `extrusion_cali_set`: four trays per AMS, while
+/// single-tray units (AMS HT, ids from 128) and the external spool
+/// (254/255) are addressed by their own id.
+fn global_tray_id(ams_id: u8, slot_id: u8) -> u16 {
+    if ams_id >= 128 {
+        ams_id as u16
+    } else {
+        ams_id as u16 * 4 + slot_id as u16
+    }
+}
+
+fn is_abrasive_material(material: &str) -> bool {
+    let upper = material.to_uppercase();
+    upper.contains("CF") || upper.contains("GF")
+}
+
+/// Normalizes H2D nozzle codes ("HS01", "SS00") and legacy type strings
+/// to `hardened_steel` / `stainless_steel`.
+fn normalize_nozzle_type(raw: &str) -> String {
+    let lower = raw.to_lowercase();
+    if lower.contains("hardened") || (raw.len() == 4 && raw.starts_with('H')) {
+        "hardened_steel".to_string()
+    } else if lower.contains("stainless") || (raw.len() == 4 && raw.starts_with('S')) {
+        "stainless_steel".to_string()
+    } else {
+        lower
+    }
+}
+
+fn json_f32(value: Option<&serde_json::Value>) -> Option<f32> {
+    value.and_then(|v| {
+        v.as_f64()
+            .map(|f| f as f32)
+            .or_else(|| v.as_str().and_then(|s| s.parse::<f32>().ok()))
+    })
+}
+
+/// Extruder fed by an AMS unit. The H2D encodes it in bits 8-11 of the
+/// hex `info` field; older firmware omits `info`, meaning extruder 0.
+fn ams_extruder_id(ams_unit: &serde_json::Value) -> u8 {
+    ams_unit
+        .get("info")
+        .and_then(|i| i.as_str())
+        .and_then(|s| u32::from_str_radix(s, 16).ok())
+        .map(|info| ((info >> 8) & 0x0F) as u8)
+        .unwrap_or(0)
+}
+
+fn ams_unit_id(ams_unit: &serde_json::Value, index: usize) -> u8 {
+    ams_unit
+        .get("id")
+        .and_then(|i| i.as_str().and_then(|s| s.parse::<u8>().ok()).or_else(|| i.as_u64().map(|v| v as u8)))
+        .unwrap_or(index as u8)
+}
+
+fn parse_nozzles(print_obj: &serde_json::Value) -> Vec<NozzleInfo> {
+    if let Some(info) = print_obj
+        .get("device")
+        .and_then(|d| d.get("nozzle"))
+        .and_then(|n| n.get("info"))
+        .and_then(|i| i.as_array())
+    {
+        return info
+            .iter()
+            .filter_map(|nozzle| {
+                let extruder_id = nozzle.get("id").and_then(|i| i.as_u64())? as u8;
+                Some(NozzleInfo {
+                    extruder_id,
+                    diameter: json_f32(nozzle.get("diameter")).unwrap_or(0.4),
+                    nozzle_type: normalize_nozzle_type(
+                        nozzle.get("type").and_then(|t| t.as_str()).unwrap_or(""),
+                    ),
+                })
+            })
+            .collect();
+    }
+
+    match json_f32(print_obj.get("nozzle_diameter")) {
+        Some(diameter) => vec![NozzleInfo {
+            extruder_id: 0,
+            diameter,
+            nozzle_type: normalize_nozzle_type(
+                print_obj.get("nozzle_type").and_then(|t| t.as_str()).unwrap_or(""),
+            ),
+        }],
+        None => Vec::new(),
+    }
+}
+
+fn parse_printer_state(print_obj: &serde_json::Value) -> PrinterState {
+    let gcode_state = print_obj.get("gcode_state").and_then(|s| s.as_str()).unwrap_or("");
+    let lifecycle = print_obj.get("lifecycle").and_then(|l| l.as_str()).unwrap_or("");
+
+    let mut ams_extruders = HashMap::new();
+    let complete = print_obj.get("ams").is_some();
+    if let Some(ams_array) = print_obj.get("ams").and_then(|a| a.get("ams")).and_then(|a| a.as_array()) {
+        for (ams_idx, ams_unit) in ams_array.iter().enumerate() {
+            ams_extruders.insert(ams_unit_id(ams_unit, ams_idx), ams_extruder_id(ams_unit));
+        }
+    }
+
+    PrinterState {
+        is_printing: gcode_state == "RUNNING" || lifecycle == "printing",
+        is_paused: gcode_state == "PAUSE" || lifecycle == "paused",
+        print_state: if gcode_state.is_empty() { None } else { Some(gcode_state.to_string()) },
+        nozzles: parse_nozzles(print_obj),
+        ams_extruders,
+        complete,
+    }
+}
+
+/// Checks that the sync targets the side the AMS actually feeds and that the
+/// nozzle on that side can run the material.
+fn check_nozzle_compatibility(state: &PrinterState, command: &FilamentSyncCommand) -> AppResult<()> {
+    if !state.complete {
+        println!("⚠️  Printer sent no AMS or nozzle data; skipping the extruder and nozzle checks");
+        return Ok(());
+    }
+
+    let extruder_id = state.extruder_for_ams(command.ams_id);
+
+    if let Some(expected) = command.extruder_id {
+        if expected != extruder_id {
+            return Err(AppError::new(
+                ErrorCode::NozzleMismatch,
+                format!("AMS {} feeds extruder {}, not extruder {}", command.ams_id, extruder_id, expected),
+            )
+            .with_details(serde_json::json!({
+                "ams_id": command.ams_id,
+                "extruder_id": extruder_id,
+                "expected_extruder_id": expected,
+            })));
+        }
+    }
+
+    let nozzle = match state.nozzle(extruder_id) {
+        Some(nozzle) => nozzle,
+        None => return Ok(()),
+    };
+
+    if let Some(diameter) = command.nozzle_diameter {
+        if (diameter - nozzle.diameter).abs() > 0.01 {
+            return Err(AppError::new(
+                ErrorCode::NozzleMismatch,
+                format!(
+                    "Extruder {} has a {} mm nozzle, profile expects {} mm",
+                    extruder_id, nozzle.diameter, diameter
+                ),
+            )
+            .with_details(serde_json::json!({
+                "extruder_id": extruder_id,
+                "nozzle_diameter": nozzle.diameter,
+                "expected_diameter": diameter,
+            })));
+        }
+    }
+
+    if is_abrasive_material(&command.material) && nozzle.nozzle_type == "stainless_steel" {
+        return Err(AppError::new(
+            ErrorCode::NozzleMismatch,
+            format!(
+                "{} is abrasive and extruder {} has a stainless steel nozzle",
+                command.material, extruder_id
+            ),
+        )
+        .with_details(serde_json::json!({
+            "extruder_id": extruder_id,
+            "nozzle_type": nozzle.nozzle_type,
+            "material": command.material,
+        })));
+    }
+
+    Ok(())
+}
+
 pub struct BambuMqttClient {
     #[allow(dead_code)]
     client_id: String,
 }
 
 impl BambuMqttClient {
-    pub fn new() -> Result<Self, String> {
+    pub fn new() -> AppResult<Self> {
         Ok(Self {
             client_id: format!("spoolsync_{}", uuid::Uuid::new_v4()),
         })
     }
 
-    pub fn test_connection(&self, config: BambuPrinterConfig) -> Result<String, String> {
+    pub fn test_connection(&self, config: BambuPrinterConfig) -> AppResult<String> {
         println!("\n🔌 MQTT CONNECTION TEST");
         println!("═══════════════════════════════════════");
         println!("Printer Name: {}", config.name);
@@ -131,7 +367,7 @@ impl BambuMqttClient {
         println!("═══════════════════════════════════════\n");
 
         let rt = tokio::runtime::Runtime::new()
-            .map_err(|e| format!("Failed to create runtime: {}", e))?;
+            .map_err(|e| AppError::internal(format!("Failed to create runtime: {}", e)))?;
 
         rt.block_on(async {
             println!("📡 Creating MQTT client...");
@@ -150,7 +386,7 @@ impl BambuMqttClient {
             let report_topic = format!("device/{}/report", config.serial_number);
             if let Err(e) = client.subscribe(&report_topic, QoS::AtMostOnce).await {
                 eprintln!("❌ Failed to subscribe: {}", e);
-                return Err(format!("Subscribe failed: {}", e));
+                return Err(e.into());
             }
             println!("✅ Subscribe request sent\n");
 
@@ -181,7 +417,7 @@ impl BambuMqttClient {
                         }
                         Err(e) => {
                             eprintln!("❌ Event loop error: {:?}", e);
-                            return Err(format!("Connection error: {:?}", e));
+                            return Err(e.into());
                         }
                     }
                 }
@@ -206,21 +442,44 @@ impl BambuMqttClient {
                     eprintln!("     → Check in printer: Settings → Network → Access Code");
                     eprintln!("  3. Printer MQTT is disabled or printer is offline");
                     eprintln!("  4. Firewall blocking port 8883");
-                    Err("Connection timeout - no response from printer".to_string())
+                    Err(AppError::new(ErrorCode::PrinterTimeout, "Connection timeout - no response from printer"))
                 }
             }
         })
     }
 
-    async fn check_printer_idle(&self, config: &BambuPrinterConfig) -> Result<(), String> {
+    /// Asks for a full report (`pushall`) and fails if the printer is busy.
+    /// Reports that arrive first are often incremental ones without AMS or
+    /// nozzle data; when no full report comes, the last one that showed the
+    /// printer idle is returned with `complete` unset.
+    async fn check_printer_idle(&self, config: &BambuPrinterConfig) -> AppResult<PrinterState> {
         let (client, mut event_loop) = self.create_mqtt_client(config).await?;
         
         let report_topic = format!("device/{}/report", config.serial_number);
         client.subscribe(&report_topic, QoS::AtMostOnce).await
-            .map_err(|e| format!("Subscribe failed: {}", e))?;
+            .map_err(AppError::from)?;
+
+        let pushall = serde_json::json!({
+            "pushing": {
+                "sequence_id": "0",
+                "command": "pushall",
+                "version": 1,
+                "push_target": 1,
+            }
+        });
+        client
+            .publish(
+                format!("device/{}/request", config.serial_number),
+                QoS::AtLeastOnce,
+                false,
+                pushall.to_string().as_bytes(),
+            )
+            .await
+            .map_err(AppError::from)?;
 
         println!("\n🔍 Checking printer state...");
         
+        let mut partial: Option<PrinterState> = None;
         let result = tokio::time::timeout(Duration::from_secs(10), async {
             loop {
                 match event_loop.poll().await {
@@ -234,19 +493,44 @@ impl BambuMqttClient {
                                         let gcode_state = print_obj.get("gcode_state").and_then(|s| s.as_str()).unwrap_or("");
                                         let lifecycle = print_obj.get("lifecycle").and_then(|l| l.as_str()).unwrap_or("");
                                         
-                                        println!("   State: {}", if gcode_state.is_empty() { "idle" } else { gcode_state });
-                                        println!("   Lifecycle: {}", if lifecycle.is_empty() { "ready" } else { lifecycle });
+                                        let has_run_state = print_obj.get("gcode_state").is_some() || print_obj.get("lifecycle").is_some();
+                                        if has_run_state {
+                                            println!("   State: {}", if gcode_state.is_empty() { "idle" } else { gcode_state });
+                                            println!("   Lifecycle: {}", if lifecycle.is_empty() { "ready" } else { lifecycle });
+                                        }
+                                        
+                                        let busy_details = serde_json::json!({
+                                            "gcode_state": gcode_state,
+                                            "lifecycle": lifecycle,
+                                        });
                                         
                                         if !gcode_state.is_empty() {
-                                            return Err(format!("❌ Printer is NOT idle! Current state: {}", gcode_state));
+                                            return Err(AppError::new(
+                                                ErrorCode::PrinterBusy,
+                                                format!("❌ Printer is NOT idle! Current state: {}", gcode_state),
+                                            ).with_details(busy_details));
                                         }
                                         
                                         if lifecycle == "printing" || lifecycle == "paused" {
-                                            return Err(format!("❌ Printer is in {} mode! Cannot modify AMS while active job", lifecycle));
+                                            return Err(AppError::new(
+                                                ErrorCode::PrinterBusy,
+                                                format!("❌ Printer is in {} mode! Cannot modify AMS while active job", lifecycle),
+                                            ).with_details(busy_details));
+                                        }
+                                        
+                                        let state = parse_printer_state(print_obj);
+                                        if !state.complete {
+                                            if has_run_state {
+                                                partial = Some(state);
+                                            }
+                                            continue;
+                                        }
+                                        for nozzle in &state.nozzles {
+                                            println!("   Extruder {}: {} mm {}", nozzle.extruder_id, nozzle.diameter, nozzle.nozzle_type);
                                         }
                                         
                                         println!("✅ Printer is IDLE - safe to sync");
-                                        return Ok(());
+                                        return Ok(state);
                                     }
                                 }
                             }
@@ -255,7 +539,7 @@ impl BambuMqttClient {
                     Ok(_) => {},
                     Err(e) => {
                         eprintln!("❌ Event loop error: {:?}", e);
-                        return Err(format!("Connection error: {:?}", e));
+                        return Err(e.into());
                     }
                 }
             }
@@ -263,28 +547,32 @@ impl BambuMqttClient {
 
         client.disconnect().await.ok();
         
-        match result {
-            Ok(Ok(())) => Ok(()),
-            Ok(Err(e)) => Err(e),
-            Err(_) => Err("Timeout checking printer state".to_string()),
+        match (result, partial) {
+            (Ok(Ok(state)), _) => Ok(state),
+            (Ok(Err(e)), _) => Err(e),
+            (Err(_), Some(state)) => {
+                println!("⚠️  No full report from the printer; continuing with a partial one");
+                Ok(state)
+            }
+            (Err(_), _) => Err(AppError::new(ErrorCode::PrinterTimeout, "Timeout checking printer state")),
         }
     }
 
-    pub fn get_ams_status(&self, config: BambuPrinterConfig) -> Result<Vec<AMSStatus>, String> {
+    pub fn get_ams_status(&self, config: BambuPrinterConfig) -> AppResult<Vec<AMSStatus>> {
         println!("\n📊 GET AMS STATUS");
         println!("═══════════════════════════════════════");
         println!("Printer: {} ({})", config.name, config.ip_address);
         println!("═══════════════════════════════════════\n");
 
         let rt = tokio::runtime::Runtime::new()
-            .map_err(|e| format!("Failed to create runtime: {}", e))?;
+            .map_err(|e| AppError::internal(format!("Failed to create runtime: {}", e)))?;
 
         rt.block_on(async {
             let (client, mut event_loop) = self.create_mqtt_client(&config).await?;
             
             let report_topic = format!("device/{}/report", config.serial_number);
             client.subscribe(&report_topic, QoS::AtMostOnce).await
-                .map_err(|e| format!("Subscribe failed: {}", e))?;
+                .map_err(AppError::from)?;
 
             println!("⏳ Waiting for AMS status message (30s timeout)...\n");
             
@@ -309,7 +597,9 @@ impl BambuMqttClient {
                                                 
                                                 for (ams_idx, ams_unit) in ams_array.iter().enumerate() {
                                                     if let Some(tray_array) = ams_unit.get("tray").and_then(|t| t.as_array()) {
-                                                        println!("📦 AMS {}: {} trays", ams_idx, tray_array.len());
+                                                        let ams_id = ams_unit_id(ams_unit, ams_idx);
+                                                        let extruder_id = ams_extruder_id(ams_unit);
+                                                        println!("📦 AMS {} (extruder {}): {} trays", ams_id, extruder_id, tray_array.len());
                                                         let mut trays = Vec::new();
                                                         
                                                         for (tray_idx, tray_obj) in tray_array.iter().enumerate() {
@@ -332,6 +622,20 @@ impl BambuMqttClient {
                                                                 .or_else(|| tray_obj.get("nozzle_temp_max").and_then(|t| t.as_u64()).map(|v| v as u16))
                                                                 .unwrap_or(0);
                                                             
+                                                            let tray_uuid = tray_obj.get("tray_uuid")
+                                                                .and_then(|u| u.as_str())
+                                                                .filter(|u| !u.is_empty() && u.chars().any(|c| c != '0'))
+                                                                .map(|u| u.to_string());
+                                                            
+                                                            let remain = tray_obj.get("remain")
+                                                                .and_then(|r| r.as_i64())
+                                                                .filter(|r| (0..=100).contains(r))
+                                                                .map(|r| r as u8);
+                                                            
+                                                            let tray_weight = tray_obj.get("tray_weight")
+                                                                .and_then(|w| w.as_str().and_then(|s| s.parse::<f64>().ok()).or_else(|| w.as_f64()))
+                                                                .filter(|w| *w > 0.0);
+                                                            
                                                             if !tray_type.is_empty() {
                                                                 println!("   Slot {}: {} (#{}) {}°C-{}°C", 
                                                                     tray_idx, tray_type, tray_color, nozzle_temp_min, nozzle_temp_max);
@@ -341,13 +645,17 @@ impl BambuMqttClient {
                                                                     tray_color,
                                                                     nozzle_temp_min,
                                                                     nozzle_temp_max,
+                                                                    tray_uuid,
+                                                                    remain,
+                                                                    tray_weight,
                                                                 });
                                                             }
                                                         }
                                                         
                                                         if !trays.is_empty() {
                                                             statuses.push(AMSStatus {
-                                                                ams_id: ams_idx as u8,
+                                                                ams_id,
+                                                                extruder_id,
                                                                 trays,
                                                             });
                                                         }
@@ -368,7 +676,7 @@ impl BambuMqttClient {
                         Ok(_) => {},
                         Err(e) => {
                             eprintln!("\n❌ Event loop error: {:?}", e);
-                            return Err(format!("Connection error: {:?}", e));
+                            return Err(e.into());
                         }
                     }
                 }
@@ -387,14 +695,78 @@ impl BambuMqttClient {
         })
     }
 
+    /// Asks for a full report and returns the nozzles it lists, one per
+    /// extruder. Unlike the idle check this also answers while printing.
+    pub fn get_nozzles(&self, config: BambuPrinterConfig) -> AppResult<Vec<NozzleInfo>> {
+        let rt = tokio::runtime::Runtime::new()
+            .map_err(|e| AppError::internal(format!("Failed to create runtime: {}", e)))?;
+
+        rt.block_on(async {
+            let (client, mut event_loop) = self.create_mqtt_client(&config).await?;
+
+            let report_topic = format!("device/{}/report", config.serial_number);
+            client.subscribe(&report_topic, QoS::AtMostOnce).await
+                .map_err(AppError::from)?;
+
+            let pushall = serde_json::json!({
+                "pushing": {
+                    "sequence_id": "0",
+                    "command": "pushall",
+                    "version": 1,
+                    "push_target": 1,
+                }
+            });
+            client
+                .publish(
+                    format!("device/{}/request", config.serial_number),
+                    QoS::AtLeastOnce,
+                    false,
+                    pushall.to_string().as_bytes(),
+                )
+                .await
+                .map_err(AppError::from)?;
+
+            let result = tokio::time::timeout(Duration::from_secs(10), async {
+                loop {
+                    match event_loop.poll().await {
+                        Ok(Event::Incoming(Packet::Publish(publish))) => {
+                            let Ok(json) = serde_json::from_slice::<serde_json::Value>(&publish.payload) else {
+                                continue;
+                            };
+                            if let Some(print_obj) = json.get("print") {
+                                let nozzles = parse_nozzles(print_obj);
+                                if !nozzles.is_empty() {
+                                    return Ok(nozzles);
+                                }
+                            }
+                        }
+                        Ok(_) => {},
+                        Err(e) => return Err(AppError::from(e)),
+                    }
+                }
+            }).await;
+
+            client.disconnect().await.ok();
+
+            result.unwrap_or_else(|_| {
+                Err(AppError::new(ErrorCode::PrinterTimeout, "Timeout waiting for the printer's nozzle report"))
+            })
+        })
+    }
+
+    /// Loads `command` into its tray. Without a `k_value`, `k_value_for` is
+    /// asked for one once the printer has reported the nozzle on the
+    /// extruder the AMS feeds.
     pub fn sync_filament(
         &self,
         config: BambuPrinterConfig,
-        command: FilamentSyncCommand,
-    ) -> Result<String, String> {
+        mut command: FilamentSyncCommand,
+        k_value_for: impl FnOnce(&NozzleInfo) -> AppResult<Option<f32>>,
+    ) -> AppResult<String> {
         println!("\n🧵 MQTT FILAMENT SYNC");
         println!("═══════════════════════════════════════");
         println!("Printer: {} ({})", config.name, config.ip_address);
+        println!("AMS: {}", command.ams_id);
         println!("Slot: {}", command.slot_id);
         println!("Brand: {}", command.brand);
         println!("Material: {}", command.material);
@@ -402,25 +774,42 @@ impl BambuMqttClient {
         println!("═══════════════════════════════════════\n");
 
         let rt = tokio::runtime::Runtime::new()
-            .map_err(|e| format!("Failed to create runtime: {}", e))?;
+            .map_err(|e| AppError::internal(format!("Failed to create runtime: {}", e)))?;
 
         rt.block_on(async {
-            if let Err(e) = self.check_printer_idle(&config).await {
-                eprintln!("\n{}", e);
-                eprintln!("\n⚠️ SYNC ABORTED: Cannot modify AMS settings while printer is active");
-                eprintln!("\n📝 Solution:");
-                eprintln!("   1. Stop or cancel the current print job");
-                eprintln!("   2. Wait for printer to return to idle state");
-                eprintln!("   3. Then retry the AMS sync");
+            let state = match self.check_printer_idle(&config).await {
+                Ok(state) => state,
+                Err(e) => {
+                    eprintln!("\n{}", e);
+                    eprintln!("\n⚠️ SYNC ABORTED: Cannot modify AMS settings while printer is active");
+                    eprintln!("\n📝 Solution:");
+                    eprintln!("   1. Stop or cancel the current print job");
+                    eprintln!("   2. Wait for printer to return to idle state");
+                    eprintln!("   3. Then retry the AMS sync");
+                    return Err(e);
+                }
+            };
+
+            if let Err(e) = check_nozzle_compatibility(&state, &command) {
+                eprintln!("\n⚠️ SYNC ABORTED: {}", e);
                 return Err(e);
             }
+            let extruder_id = state.extruder_for_ams(command.ams_id);
+            println!("🔧 AMS {} feeds extruder {}", command.ams_id, extruder_id);
+
+            if command.k_value.is_none() {
+                match state.nozzle(extruder_id).filter(|_| state.complete) {
+                    Some(nozzle) => command.k_value = k_value_for(nozzle)?,
+                    None => println!("⚠️  Nozzle of extruder {} unknown; leaving the K-value to the printer", extruder_id),
+                }
+            }
 
             let (client, mut event_loop) = self.create_mqtt_client(&config).await?;
 
             let report_topic = format!("device/{}/report", config.serial_number);
             println!("📡 Subscribing to report topic: {}", report_topic);
             client.subscribe(&report_topic, QoS::AtMostOnce).await
-                .map_err(|e| format!("Subscribe failed: {}", e))?;
+                .map_err(AppError::from)?;
 
             tokio::time::sleep(Duration::from_millis(500)).await;
 
@@ -433,8 +822,9 @@ impl BambuMqttClient {
                 "print": {
                     "sequence_id": "0",
                     "command": "ams_filament_setting",
-                    "ams_id": 0,
+                    "ams_id": command.ams_id,
                     "tray_id": command.slot_id,
+                    "slot_id": command.slot_id,
                     "tray_info_idx": tray_info_idx,
                     "tray_color": format!("{}FF", color_hex),
                     "nozzle_temp_min": command.nozzle_temp,
@@ -455,10 +845,37 @@ impl BambuMqttClient {
                     payload.to_string().as_bytes(),
                 )
                 .await
-                .map_err(|e| format!("Failed to publish: {}", e))?;
+                .map_err(AppError::from)?;
+
+            if let Some(k_value) = command.k_value {
+                let cali_payload = serde_json::json!({
+                    "print": {
+                        "sequence_id": "0",
+                        "command": "extrusion_cali_set",
+                        "tray_id": global_tray_id(command.ams_id, command.slot_id),
+                        "extruder_id": extruder_id,
+                        "k_value": k_value,
+                        "n_coef": 1.4,
+                        "nozzle_temp": command.nozzle_temp,
+                        "bed_temp": command.bed_temp,
+                    }
+                });
+                println!("📏 Setting K-value {} from calibration", k_value);
+                println!("📦 Payload: {}", cali_payload);
+                client
+                    .publish(
+                        format!("device/{}/request", config.serial_number),
+                        QoS::AtLeastOnce,
+                        false,
+                        cali_payload.to_string().as_bytes(),
+                    )
+                    .await
+                    .map_err(AppError::from)?;
+            }
 
             println!("✅ Message published, waiting for confirmation...");
             
+            let target_ams = command.ams_id;
             let target_slot = command.slot_id;
             let target_material = command.material.clone();
             let target_color = format!("{}FF", color_hex).to_uppercase();
@@ -472,7 +889,10 @@ impl BambuMqttClient {
                                     if let Some(print_obj) = json.get("print") {
                                         if let Some(ams_obj) = print_obj.get("ams") {
                                             if let Some(ams_array) = ams_obj.get("ams").and_then(|a| a.as_array()) {
-                                                for ams_unit in ams_array.iter() {
+                                                for (ams_idx, ams_unit) in ams_array.iter().enumerate() {
+                                                    if ams_unit_id(ams_unit, ams_idx) != target_ams {
+                                                        continue;
+                                                    }
                                                     if let Some(tray_array) = ams_unit.get("tray").and_then(|t| t.as_array()) {
                                                         if let Some(tray_obj) = tray_array.get(target_slot as usize) {
                                                             let current_type = tray_obj.get("tray_type")
@@ -502,7 +922,7 @@ impl BambuMqttClient {
                         Ok(_) => {},
                         Err(e) => {
                             eprintln!("❌ Event loop error: {:?}", e);
-                            return Err(format!("Connection error: {:?}", e));
+                            return Err(e.into());
                         }
                     }
                 }
@@ -514,16 +934,16 @@ impl BambuMqttClient {
                 Ok(Ok(())) => {
                     println!("✅ Sync confirmed and persisted");
                     Ok(format!(
-                        "Synced {} {} to AMS slot {}",
-                        command.brand, command.material, command.slot_id
+                        "Synced {} {} to AMS {} slot {}",
+                        command.brand, command.material, command.ams_id, command.slot_id
                     ))
                 }
                 Ok(Err(e)) => Err(e),
                 Err(_) => {
                     println!("⚠️ No confirmation received within 10s, but command was sent");
                     Ok(format!(
-                        "Sent {} {} to AMS slot {} (no confirmation)",
-                        command.brand, command.material, command.slot_id
+                        "Sent {} {} to AMS {} slot {} (no confirmation)",
+                        command.brand, command.material, command.ams_id, command.slot_id
                     ))
                 }
             }
@@ -533,7 +953,7 @@ impl BambuMqttClient {
     async fn create_mqtt_client(
         &self,
         config: &BambuPrinterConfig,
-    ) -> Result<(AsyncClient, rumqttc::EventLoop), String> {
+    ) -> AppResult<(AsyncClient, rumqttc::EventLoop)> {
         println!("🔧 Configuring MQTT options...");
         println!("   Host: {}:8883", config.ip_address);
         println!("   Username: bblp");
@@ -561,3 +981,120 @@ impl BambuMqttClient {
         Ok((client, event_loop))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(ams_id: u8, material: &str) -> FilamentSyncCommand {
+        FilamentSyncCommand {
+            ams_id,
+            slot_id: 0,
+            brand: "Polymaker".to_string(),
+            material: material.to_string(),
+            color: "#FFFFFF".to_string(),
+            nozzle_temp: 220,
+            bed_temp: 60,
+            extruder_id: None,
+            nozzle_diameter: None,
+            spool_id: None,
+            profile_id: None,
+            k_value: None,
+        }
+    }
+
+    /// `pushall` answer of an H2D: AMS 0 on the right extruder, AMS 1 on
+    /// the left, which has a 0.6 mm hardened steel nozzle.
+    fn h2d_full_report() -> serde_json::Value {
+        serde_json::json!({
+            "command": "push_status",
+            "gcode_state": "",
+            "ams": {
+                "ams": [
+                    { "id": "0", "info": "0003", "tray": [] },
+                    { "id": "1", "info": "0103", "tray": [] }
+                ]
+            },
+            "device": {
+                "nozzle": {
+                    "info": [
+                        { "id": 0, "diameter": 0.4, "type": "HS01" },
+                        { "id": 1, "diameter": "0.6", "type": "hardened_steel" }
+                    ]
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn parses_full_h2d_report() {
+        let state = parse_printer_state(&h2d_full_report());
+        assert!(state.complete);
+        assert!(!state.is_printing);
+        assert_eq!(state.extruder_for_ams(0), 0);
+        assert_eq!(state.extruder_for_ams(1), 1);
+        let left = state.nozzle(1).unwrap();
+        assert_eq!(left.diameter, 0.6);
+        assert_eq!(left.nozzle_type, "hardened_steel");
+        assert_eq!(state.nozzle(0).unwrap().nozzle_type, "hardened_steel");
+    }
+
+    #[test]
+    fn parses_full_single_nozzle_report() {
+        let state = parse_printer_state(&serde_json::json!({
+            "command": "push_status",
+            "gcode_state": "RUNNING",
+            "nozzle_diameter": "0.4",
+            "nozzle_type": "stainless_steel",
+            "ams": { "ams": [{ "id": "0", "tray": [] }] }
+        }));
+        assert!(state.complete);
+        assert!(state.is_printing);
+        assert_eq!(state.print_state.as_deref(), Some("RUNNING"));
+        assert_eq!(state.extruder_for_ams(0), 0);
+        assert_eq!(state.nozzle(0).unwrap().nozzle_type, "stainless_steel");
+    }
+
+    #[test]
+    fn parses_partial_report() {
+        let state = parse_printer_state(&serde_json::json!({
+            "command": "push_status",
+            "mc_percent": 42,
+            "nozzle_temper": 24.5
+        }));
+        assert!(!state.complete);
+        assert!(state.nozzles.is_empty());
+        assert!(state.ams_extruders.is_empty());
+        assert_eq!(state.print_state, None);
+    }
+
+    #[test]
+    fn checks_nozzle_of_the_extruder_the_ams_feeds() {
+        let state = parse_printer_state(&h2d_full_report());
+
+        let mut left = command(1, "PLA-CF");
+        left.extruder_id = Some(1);
+        left.nozzle_diameter = Some(0.6);
+        assert!(check_nozzle_compatibility(&state, &left).is_ok());
+
+        left.nozzle_diameter = Some(0.4);
+        let error = check_nozzle_compatibility(&state, &left).unwrap_err();
+        assert_eq!(error.code, ErrorCode::NozzleMismatch);
+
+        let mut wrong_side = command(0, "PLA");
+        wrong_side.extruder_id = Some(1);
+        assert!(check_nozzle_compatibility(&state, &wrong_side).is_err());
+    }
+
+    #[test]
+    fn partial_report_skips_nozzle_checks() {
+        let state = parse_printer_state(&serde_json::json!({
+            "command": "push_status",
+            "gcode_state": ""
+        }));
+        let mut left = command(1, "PLA-CF");
+        left.extruder_id = Some(1);
+        left.nozzle_diameter = Some(0.6);
+        assert!(check_nozzle_compatibility(&state, &left).is_ok());
+    }
+}
@@ -0,0 +1,364 @@
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use crate::error::{AppError, AppResult, ErrorCode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+const SERVICE: &str = "spoolsync-desktop";
+const HANDLE_PREFIX: &str = "secret:";
+const CHECK_ENTRY: &str = "__check";
+const CHECK_VALUE: &str = "spoolsync";
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SecretBackend {
+    Keyring,
+    EncryptedFile,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SecretStatus {
+    pub backend: SecretBackend,
+    pub unlocked: bool,
+    pub initialized: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EncryptedEntry {
+    nonce: String,
+    ciphertext: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SecretFile {
+    version: u32,
+    salt: String,
+    entries: HashMap<String, EncryptedEntry>,
+}
+
+/// Stores printer access codes outside the database. Values live in the
+/// Secret Service keyring when one answers, otherwise in `secrets.json`
+/// encrypted with a key derived from a user passphrase. Callers only keep
+/// the `secret:<uuid>` handle.
+pub struct SecretStore {
+    backend: SecretBackend,
+    file_path: PathBuf,
+    key: Mutex<Option<[u8; 32]>>,
+}
+
+pub fn is_handle(value: &str) -> bool {
+    value.starts_with(HANDLE_PREFIX)
+}
+
+impl SecretStore {
+    pub fn new() -> AppResult<Self> {
+        let data_dir = dirs::data_local_dir()
+            .ok_or_else(|| AppError::new(ErrorCode::Io, "No data directory"))?;
+        let app_dir = data_dir.join("spoolsync-desktop");
+        fs::create_dir_all(&app_dir).map_err(|e| io_error("Failed to create dir", e))?;
+
+        let backend = if keyring_available() {
+            SecretBackend::Keyring
+        } else {
+            SecretBackend::EncryptedFile
+        };
+        println!("🔑 Secret storage backend: {:?}", backend);
+
+        Ok(Self {
+            backend,
+            file_path: app_dir.join("secrets.json"),
+            key: Mutex::new(None),
+        })
+    }
+
+    pub fn status(&self) -> SecretStatus {
+        SecretStatus {
+            backend: self.backend,
+            unlocked: self.is_unlocked(),
+            initialized: self.backend == SecretBackend::Keyring || self.file_path.exists(),
+        }
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        self.backend == SecretBackend::Keyring || self.key.lock().unwrap().is_some()
+    }
+
+    /// Unlocks the encrypted file, creating it with this passphrase on first use.
+    pub fn unlock(&self, passphrase: &str) -> AppResult<()> {
+        if self.backend == SecretBackend::Keyring {
+            return Ok(());
+        }
+        if passphrase.is_empty() {
+            return Err(AppError::invalid("Passphrase must not be empty"));
+        }
+
+        let mut file = match self.read_file()? {
+            Some(file) => file,
+            None => {
+                let mut salt = [0u8; 16];
+                OsRng.fill_bytes(&mut salt);
+                SecretFile {
+                    version: 1,
+                    salt: hex::encode(salt),
+                    entries: HashMap::new(),
+                }
+            }
+        };
+
+        let salt = hex::decode(&file.salt).map_err(|e| secrets_error("Corrupt secrets file", e))?;
+        let key = derive_key(passphrase, &salt)?;
+
+        match file.entries.get(CHECK_ENTRY) {
+            Some(check) => {
+                if decrypt(&key, check).ok().as_deref() != Some(CHECK_VALUE) {
+                    return Err(AppError::invalid("Wrong passphrase"));
+                }
+            }
+            None => {
+                file.entries.insert(CHECK_ENTRY.to_string(), encrypt(&key, CHECK_VALUE)?);
+                self.write_file(&file)?;
+            }
+        }
+
+        *self.key.lock().unwrap() = Some(key);
+        Ok(())
+    }
+
+    /// Saves a secret and returns its handle.
+    pub fn store(&self, value: &str) -> AppResult<String> {
+        let handle = format!("{}{}", HANDLE_PREFIX, uuid::Uuid::new_v4());
+
+        match self.backend {
+            SecretBackend::Keyring => {
+                keyring::Entry::new(SERVICE, &handle)
+                    .and_then(|entry| entry.set_password(value))
+                    .map_err(|e| secrets_error("Keyring error", e))?;
+            }
+            SecretBackend::EncryptedFile => {
+                let key = self.unlocked_key()?;
+                let mut file = self
+                    .read_file()?
+                    .ok_or_else(locked)?;
+                file.entries.insert(handle.clone(), encrypt(&key, value)?);
+                self.write_file(&file)?;
+            }
+        }
+
+        Ok(handle)
+    }
+
+    pub fn get(&self, handle: &str) -> AppResult<String> {
+        match self.backend {
+            SecretBackend::Keyring => keyring::Entry::new(SERVICE, handle)
+                .and_then(|entry| entry.get_password())
+                .map_err(|e| secrets_error("Keyring error", e)),
+            SecretBackend::EncryptedFile => {
+                let key = self.unlocked_key()?;
+                let file = self
+                    .read_file()?
+                    .ok_or_else(locked)?;
+                let entry = file
+                    .entries
+                    .get(handle)
+                    .ok_or_else(|| AppError::not_found(format!("Secret {} not found", handle)))?;
+                decrypt(&key, entry)
+            }
+        }
+    }
+
+    pub fn delete(&self, handle: &str) -> AppResult<()> {
+        match self.backend {
+            SecretBackend::Keyring => match keyring::Entry::new(SERVICE, handle).and_then(|e| e.delete_credential()) {
+                Ok(()) | Err(keyring::Error::NoEntry) => Ok(()),
+                Err(e) => Err(secrets_error("Keyring error", e)),
+            },
+            SecretBackend::EncryptedFile => {
+                if let Some(mut file) = self.read_file()? {
+                    if file.entries.remove(handle).is_some() {
+                        self.write_file(&file)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the plaintext for a handle; values that are not handles are
+    /// passed through (an unsaved code typed into a form).
+    pub fn resolve(&self, value: &str) -> AppResult<String> {
+        if is_handle(value) {
+            self.get(value)
+        } else {
+            Ok(value.to_string())
+        }
+    }
+
+    /// Turns a form value into a handle. Existing handles and empty values
+    /// are returned unchanged.
+    pub fn store_if_plaintext(&self, value: &str) -> AppResult<String> {
+        if value.is_empty() || is_handle(value) {
+            return Ok(value.to_string());
+        }
+        self.store(value)
+    }
+
+    fn unlocked_key(&self) -> AppResult<[u8; 32]> {
+        self.key
+            .lock()
+            .unwrap()
+            .ok_or_else(locked)
+    }
+
+    fn read_file(&self) -> AppResult<Option<SecretFile>> {
+        if !self.file_path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&self.file_path)
+            .map_err(|e| io_error("Failed to read secrets file", e))?;
+        serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|e| secrets_error("Corrupt secrets file", e))
+    }
+
+    fn write_file(&self, file: &SecretFile) -> AppResult<()> {
+        let content = serde_json::to_string_pretty(file)
+            .map_err(|e| AppError::internal(format!("Failed to serialize secrets: {}", e)))?;
+        let tmp_path = self.file_path.with_extension("json.tmp");
+        fs::write(&tmp_path, content).map_err(|e| io_error("Failed to write secrets file", e))?;
+        restrict_permissions(&tmp_path);
+        fs::rename(&tmp_path, &self.file_path)
+            .map_err(|e| io_error("Failed to write secrets file", e))
+    }
+}
+
+fn keyring_available() -> bool {
+    match keyring::Entry::new(SERVICE, "availability-probe").and_then(|e| e.get_password()) {
+        Ok(_) | Err(keyring::Error::NoEntry) => true,
+        Err(e) => {
+            println!("⚠️  Keyring unavailable: {}", e);
+            false
+        }
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> AppResult<[u8; 32]> {
+    let mut key = [0u8; 32];
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| secrets_error("Key derivation failed", e))?;
+    Ok(key)
+}
+
+fn encrypt(key: &[u8; 32], value: &str) -> AppResult<EncryptedEntry> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+        .encrypt(&nonce, value.as_bytes())
+        .map_err(|e| secrets_error("Encryption failed", e))?;
+    Ok(EncryptedEntry {
+        nonce: hex::encode(nonce),
+        ciphertext: hex::encode(ciphertext),
+    })
+}
+
+fn decrypt(key: &[u8; 32], entry: &EncryptedEntry) -> AppResult<String> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key));
+    let nonce = hex::decode(&entry.nonce).map_err(|e| secrets_error("Corrupt secret", e))?;
+    if nonce.len() != 12 {
+        return Err(AppError::new(ErrorCode::Secrets, "Corrupt secret: bad nonce length"));
+    }
+    let ciphertext = hex::decode(&entry.ciphertext)
+        .map_err(|e| secrets_error("Corrupt secret", e))?;
+    let plaintext = cipher
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+        .map_err(|_| AppError::new(ErrorCode::Secrets, "Failed to decrypt secret"))?;
+    String::from_utf8(plaintext).map_err(|e| secrets_error("Corrupt secret", e))
+}
+
+#[cfg(unix)]
+fn restrict_permissions(path: &std::path::Path) {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600)).ok();
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_path: &std::path::Path) {}
+
+fn io_error(context: &str, error: impl std::fmt::Display) -> AppError {
+    AppError::new(ErrorCode::Io, format!("{}: {}", context, error))
+}
+
+fn secrets_error(context: &str, error: impl std::fmt::Display) -> AppError {
+    AppError::new(ErrorCode::Secrets, format!("{}: {}", context, error))
+}
+
+fn locked() -> AppError {
+    AppError::new(ErrorCode::SecretsLocked, "Secret storage is locked")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SecretBackend, SecretStore};
+    use crate::error::ErrorCode;
+    use std::sync::Mutex;
+
+    fn encrypted_file(dir: &tempfile::TempDir) -> SecretStore {
+        SecretStore {
+            backend: SecretBackend::EncryptedFile,
+            file_path: dir.path().join("secrets.json"),
+            key: Mutex::new(None),
+        }
+    }
+
+    #[test]
+    fn encrypted_file_round_trips_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = encrypted_file(&dir);
+        assert!(!store.status().initialized);
+        store.unlock("correct horse").unwrap();
+
+        let handle = store.store_if_plaintext("12345678").unwrap();
+        assert!(super::is_handle(&handle));
+        assert_eq!(store.store_if_plaintext(&handle).unwrap(), handle);
+        assert_eq!(store.resolve(&handle).unwrap(), "12345678");
+        let content = std::fs::read_to_string(dir.path().join("secrets.json")).unwrap();
+        assert!(!content.contains("12345678"));
+
+        let restarted = encrypted_file(&dir);
+        assert!(restarted.status().initialized);
+        restarted.unlock("correct horse").unwrap();
+        assert_eq!(restarted.get(&handle).unwrap(), "12345678");
+        restarted.delete(&handle).unwrap();
+        assert_eq!(restarted.get(&handle).unwrap_err().code, ErrorCode::NotFound);
+    }
+
+    #[test]
+    fn wrong_passphrase_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        encrypted_file(&dir).unlock("correct horse").unwrap();
+
+        let store = encrypted_file(&dir);
+        assert_eq!(store.unlock("battery staple").unwrap_err().code, ErrorCode::InvalidInput);
+        assert_eq!(store.unlock("").unwrap_err().code, ErrorCode::InvalidInput);
+        assert!(!store.is_unlocked());
+    }
+
+    #[test]
+    fn locked_store_neither_saves_nor_reads() {
+        let dir = tempfile::tempdir().unwrap();
+        let unlocked = encrypted_file(&dir);
+        unlocked.unlock("correct horse").unwrap();
+        let handle = unlocked.store("12345678").unwrap();
+
+        let store = encrypted_file(&dir);
+        assert_eq!(store.store_if_plaintext("87654321").unwrap_err().code, ErrorCode::SecretsLocked);
+        assert_eq!(store.get(&handle).unwrap_err().code, ErrorCode::SecretsLocked);
+        // Empty values and handles need no storage.
+        assert_eq!(store.store_if_plaintext("").unwrap(), "");
+        assert_eq!(store.store_if_plaintext(&handle).unwrap(), handle);
+    }
+}
@@ -1,7 +1,28 @@
+use crate::error::{AppError, AppResult, ErrorCode};
+use reqwest::header::{HeaderName, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tokio::sync::Mutex;
 
+mod checkout;
+mod search;
+
+pub use search::{SearchBoosts, RECENT_DAYS};
+
+pub const DEFAULT_SPOOLMANDB_URL: &str = "https://donkie.github.io/SpoolmanDB";
+
+/// SpoolmanDB as of the last snapshot, used when the app has never synced
+/// and is offline. `build.rs` copies it from `resources/` (refreshed with
+/// `npm run update:spoolmandb`) or from `SPOOLMANDB_SNAPSHOT`; an empty
+/// array bundles nothing.
+const BUNDLED_SNAPSHOT: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/spoolmandb-filaments.json"));
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct SpoolmanFilament {
     pub id: String,
@@ -46,92 +67,391 @@ pub struct SpoolmanResponse {
     pub total: usize,
 }
 
+/// Stable fingerprint of a SpoolmanDB entry, used to notice upstream edits
+/// to filaments that were saved as favorites.
+pub fn content_hash(filament: &SpoolmanFilament) -> String {
+    let json = serde_json::to_string(filament).unwrap_or_default();
+    hex::encode(Sha256::digest(json.as_bytes()))
+}
+
+/// Where the catalog is loaded from, set by the `spoolmandb_source` setting.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SpoolmanDbSource {
+    /// Site serving `filaments.json`: SpoolmanDB itself, a mirror or a fork.
+    Url(String),
+    /// A compiled `filaments.json`.
+    File(PathBuf),
+    /// A checkout of the SpoolmanDB repository, compiled from `filaments/*.json`.
+    Checkout(PathBuf),
+}
+
+impl SpoolmanDbSource {
+    /// Interprets the setting: empty means SpoolmanDB, `http(s)://` a base
+    /// URL, a directory a checkout (or a folder holding `filaments.json`)
+    /// and anything else a file.
+    pub fn from_setting(value: Option<&str>) -> Self {
+        let value = value.map(str::trim).unwrap_or_default();
+        if value.is_empty() {
+            return SpoolmanDbSource::Url(DEFAULT_SPOOLMANDB_URL.to_string());
+        }
+        if value.starts_with("http://") || value.starts_with("https://") {
+            let base = value.trim_end_matches('/');
+            let base = base.strip_suffix("/filaments.json").unwrap_or(base);
+            return SpoolmanDbSource::Url(base.to_string());
+        }
+
+        let path = PathBuf::from(value.strip_prefix("file://").unwrap_or(value));
+        if path.is_dir() && !path.join("filaments").is_dir() && path.join("filaments.json").is_file() {
+            SpoolmanDbSource::File(path.join("filaments.json"))
+        } else if path.is_dir() {
+            SpoolmanDbSource::Checkout(path)
+        } else {
+            SpoolmanDbSource::File(path)
+        }
+    }
+
+    /// Local sources must exist when they are configured.
+    pub fn check(&self) -> AppResult<()> {
+        let exists = match self {
+            SpoolmanDbSource::Url(_) => true,
+            SpoolmanDbSource::File(path) => path.is_file(),
+            SpoolmanDbSource::Checkout(path) => path.join("filaments").is_dir(),
+        };
+        if exists {
+            Ok(())
+        } else {
+            Err(AppError::invalid(format!(
+                "{} is not a filaments.json file or a SpoolmanDB checkout",
+                self
+            )))
+        }
+    }
+}
+
+impl fmt::Display for SpoolmanDbSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpoolmanDbSource::Url(url) => f.write_str(url),
+            SpoolmanDbSource::File(path) | SpoolmanDbSource::Checkout(path) => {
+                write!(f, "{}", path.display())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheSource {
+    /// Downloaded or revalidated this session.
+    Network,
+    /// Read from the on-disk cache; the server could not be reached.
+    Disk,
+    /// The snapshot shipped with the app; nothing was ever downloaded.
+    Bundled,
+    /// Read from a local file or checkout.
+    Local,
+}
+
+/// What the browser is showing and how fresh it is.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct SpoolmanCacheStatus {
+    pub source: CacheSource,
+    /// The configured URL or path.
+    pub location: String,
+    /// Unix seconds of the last download, revalidation or local read.
+    pub synced_at: Option<u64>,
+    pub filament_count: usize,
+}
+
+/// Validators from the last download, stored next to the cached file.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+struct CacheMeta {
+    /// Base URL the cache was downloaded from; other sources ignore it.
+    #[serde(default)]
+    url: Option<String>,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    synced_at: Option<u64>,
+}
+
+struct Cache {
+    filaments: Vec<SpoolmanFilament>,
+    meta: CacheMeta,
+    source: CacheSource,
+    origin: SpoolmanDbSource,
+}
+
+fn now_secs() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+fn cache_io_error(context: &str, e: std::io::Error) -> AppError {
+    AppError {
+        message: format!("{}: {}", context, e),
+        ..e.into()
+    }
+}
+
+/// Reads the cached download from `base_url`, if there is a usable one.
+fn read_disk_cache(dir: &Path, base_url: &str) -> Option<(Vec<SpoolmanFilament>, CacheMeta)> {
+    let meta: CacheMeta = std::fs::read(dir.join("meta.json"))
+        .ok()
+        .and_then(|meta| serde_json::from_slice(&meta).ok())
+        .unwrap_or_default();
+    // Caches written before the source was configurable came from SpoolmanDB.
+    if meta.url.as_deref().unwrap_or(DEFAULT_SPOOLMANDB_URL) != base_url {
+        return None;
+    }
+    let data = std::fs::read(dir.join("filaments.json")).ok()?;
+    let filaments = match serde_json::from_slice::<Vec<SpoolmanFilament>>(&data) {
+        Ok(filaments) => filaments,
+        Err(e) => {
+            println!("⚠️  Ignoring unreadable SpoolmanDB cache: {}", e);
+            return None;
+        }
+    };
+    Some((filaments, meta))
+}
+
+/// Writes through temporary files so a crash never leaves a torn cache.
+fn write_disk_cache(dir: &Path, body: &[u8], meta: &CacheMeta) -> AppResult<()> {
+    std::fs::create_dir_all(dir).map_err(|e| cache_io_error("Failed to create cache dir", e))?;
+    let files = [("filaments.json", body.to_vec()), ("meta.json", serde_json::to_vec(meta)?)];
+    for (name, data) in files {
+        let tmp = dir.join(format!("{}.tmp", name));
+        std::fs::write(&tmp, data)
+            .and_then(|_| std::fs::rename(&tmp, dir.join(name)))
+            .map_err(|e| cache_io_error("Failed to write cache", e))?;
+    }
+    Ok(())
+}
+
+fn header_string(response: &reqwest::Response, name: HeaderName) -> Option<String> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+fn read_local(source: &SpoolmanDbSource) -> AppResult<Vec<SpoolmanFilament>> {
+    match source {
+        SpoolmanDbSource::Checkout(path) => checkout::compile(path),
+        SpoolmanDbSource::File(path) => {
+            let data = std::fs::read(path).map_err(|e| AppError {
+                message: format!("Failed to read {}: {}", path.display(), e),
+                ..e.into()
+            })?;
+            serde_json::from_slice(&data).map_err(|e| {
+                AppError::new(ErrorCode::Parse, format!("Failed to parse {}: {}", path.display(), e))
+            })
+        }
+        SpoolmanDbSource::Url(_) => Err(AppError::internal("Not a local SpoolmanDB source")),
+    }
+}
+
+fn bundled_snapshot() -> Option<Vec<SpoolmanFilament>> {
+    serde_json::from_slice::<Vec<SpoolmanFilament>>(BUNDLED_SNAPSHOT)
+        .ok()
+        .filter(|filaments| !filaments.is_empty())
+}
+
 pub struct SpoolmanClient {
     client: reqwest::Client,
-    base_url: String,
-    cache: Mutex<Option<Vec<SpoolmanFilament>>>,
+    source: std::sync::Mutex<SpoolmanDbSource>,
+    /// Where downloads are kept between launches; `None` keeps them in memory only.
+    cache_dir: Option<PathBuf>,
+    cache: Mutex<Option<Cache>>,
 }
 
 impl SpoolmanClient {
     pub fn new() -> Self {
+        let cache_dir = dirs::data_local_dir()
+            .map(|dir| dir.join("spoolsync-desktop").join("spoolmandb"));
         Self {
-            client: reqwest::Client::new(),
-            base_url: "https://donkie.github.io/SpoolmanDB".to_string(),
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            source: std::sync::Mutex::new(SpoolmanDbSource::from_setting(None)),
+            cache_dir,
             cache: Mutex::new(None),
         }
     }
 
-    async fn ensure_cache(&self) -> Result<(), String> {
-        let cache = self.cache.lock().await;
+    /// Takes effect on the next read; the loaded catalog is kept until then.
+    pub fn set_source(&self, source: SpoolmanDbSource) {
+        println!("🗄️  SpoolmanDB source: {}", source);
+        *self.source.lock().unwrap() = source;
+    }
+
+    fn source(&self) -> SpoolmanDbSource {
+        self.source.lock().unwrap().clone()
+    }
+
+    /// Loads the catalog once per session and source. A downloaded catalog
+    /// is revalidated against the disk cache, and when the server cannot be
+    /// reached the disk cache or, for SpoolmanDB itself, the bundled
+    /// snapshot is used as is.
+    async fn ensure_cache(&self) -> AppResult<()> {
+        let mut cache = self.cache.lock().await;
+        let source = self.source();
         
-        if cache.is_some() {
+        if cache.as_ref().is_some_and(|cache| cache.origin == source) {
             println!("✅ Using cached SpoolmanDB data");
             return Ok(());
         }
 
-        drop(cache);
+        let base_url = match &source {
+            SpoolmanDbSource::Url(base_url) => base_url.clone(),
+            _ => {
+                *cache = Some(Cache {
+                    filaments: read_local(&source)?,
+                    meta: CacheMeta { synced_at: Some(now_secs()), ..CacheMeta::default() },
+                    source: CacheSource::Local,
+                    origin: source,
+                });
+                let count = cache.as_ref().unwrap().filaments.len();
+                println!("✅ Loaded {} filaments from local SpoolmanDB", count);
+                return Ok(());
+            }
+        };
+
+        let disk = self.cache_dir.as_deref().and_then(|dir| read_disk_cache(dir, &base_url));
+        let meta = disk.as_ref().map(|(_, meta)| meta.clone()).unwrap_or_default();
+        let (filaments, meta, cache_source) = match self.fetch(&base_url, &meta).await {
+            Ok(Some((filaments, meta))) => (filaments, meta, CacheSource::Network),
+            Ok(None) => {
+                let (filaments, mut meta) = disk.expect("304 is only requested with a disk cache");
+                meta.synced_at = Some(now_secs());
+                self.save_meta(&meta);
+                (filaments, meta, CacheSource::Network)
+            }
+            Err(e) => match (disk, bundled_snapshot()) {
+                (Some((filaments, meta)), _) => {
+                    println!("⚠️  SpoolmanDB unreachable, using disk cache: {}", e);
+                    (filaments, meta, CacheSource::Disk)
+                }
+                (None, Some(filaments)) if base_url == DEFAULT_SPOOLMANDB_URL => {
+                    println!("⚠️  SpoolmanDB unreachable, using bundled snapshot: {}", e);
+                    (filaments, CacheMeta::default(), CacheSource::Bundled)
+                }
+                _ => return Err(e),
+            },
+        };
+
+        println!("✅ Cached {} filaments from SpoolmanDB", filaments.len());
+        *cache = Some(Cache {
+            filaments,
+            meta,
+            source: cache_source,
+            origin: source,
+        });
+        Ok(())
+    }
 
-        let url = format!("{}/filaments.json", self.base_url);
+    /// Downloads `filaments.json` unless the validators in `meta` are still
+    /// current, in which case `None` is returned.
+    async fn fetch(
+        &self,
+        base_url: &str,
+        meta: &CacheMeta,
+    ) -> AppResult<Option<(Vec<SpoolmanFilament>, CacheMeta)>> {
+        let url = format!("{}/filaments.json", base_url);
         println!("📥 Downloading SpoolmanDB from: {}", url);
 
-        let response = self
-            .client
-            .get(&url)
+        let mut request = self.client.get(&url);
+        if let Some(etag) = &meta.etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &meta.last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
+        }
+
+        let response = request
             .send()
             .await
-            .map_err(|e| format!("Failed to fetch: {}", e))?;
+            .map_err(|e| AppError { message: format!("Failed to fetch: {}", e), ..e.into() })?;
 
         println!("✅ SpoolmanDB response status: {}", response.status());
+        let revalidating = meta.etag.is_some() || meta.last_modified.is_some();
+        if response.status() == StatusCode::NOT_MODIFIED && revalidating {
+            return Ok(None);
+        }
+        let response = response.error_for_status()?;
+
+        let meta = CacheMeta {
+            url: Some(base_url.to_string()),
+            etag: header_string(&response, ETAG),
+            last_modified: header_string(&response, LAST_MODIFIED),
+            synced_at: Some(now_secs()),
+        };
+
+        let body = response.bytes().await?;
+        let filaments: Vec<SpoolmanFilament> = serde_json::from_slice(&body).map_err(|e| {
+            eprintln!("JSON parse error: {:?}", e);
+            AppError::new(ErrorCode::Parse, format!("Failed to parse JSON: {}", e))
+        })?;
+
+        if let Some(dir) = &self.cache_dir {
+            if let Err(e) = write_disk_cache(dir, &body, &meta) {
+                println!("⚠️  Could not save SpoolmanDB cache: {}", e);
+            }
+        }
 
-        let filaments: Vec<SpoolmanFilament> = response
-            .json()
-            .await
-            .map_err(|e| {
-                eprintln!("JSON parse error: {:?}", e);
-                format!("Failed to parse JSON: {}", e)
-            })?;
+        Ok(Some((filaments, meta)))
+    }
 
-        println!("✅ Cached {} filaments from SpoolmanDB", filaments.len());
+    fn save_meta(&self, meta: &CacheMeta) {
+        let Some(dir) = &self.cache_dir else {
+            return;
+        };
+        let result = serde_json::to_vec(meta)
+            .map_err(AppError::from)
+            .and_then(|data| std::fs::write(dir.join("meta.json"), data).map_err(AppError::from));
+        if let Err(e) = result {
+            println!("⚠️  Could not save SpoolmanDB cache: {}", e);
+        }
+    }
 
-        let mut cache = self.cache.lock().await;
-        *cache = Some(filaments);
+    pub async fn status(&self) -> AppResult<SpoolmanCacheStatus> {
+        self.ensure_cache().await?;
 
-        Ok(())
+        let cache = self.cache.lock().await;
+        let cache = cache.as_ref().unwrap();
+        Ok(SpoolmanCacheStatus {
+            source: cache.source,
+            location: cache.origin.to_string(),
+            synced_at: cache.meta.synced_at,
+            filament_count: cache.filaments.len(),
+        })
     }
 
+    /// Ranked, typo-tolerant search; see `search::search`.
     pub async fn search_filaments(
         &self,
         query: Option<String>,
         vendor: Option<String>,
         material: Option<String>,
+        boosts: &SearchBoosts,
         limit: usize,
         offset: usize,
-    ) -> Result<SpoolmanResponse, String> {
+    ) -> AppResult<SpoolmanResponse> {
         self.ensure_cache().await?;
 
         let cache = self.cache.lock().await;
-        let mut filaments = cache.as_ref().unwrap().clone();
+        let filaments = search::search(
+            &cache.as_ref().unwrap().filaments,
+            query.as_deref(),
+            vendor.as_deref(),
+            material.as_deref(),
+            boosts,
+        );
         drop(cache);
 
-        if let Some(q) = query {
-            let q_lower = q.to_lowercase();
-            filaments.retain(|f| {
-                f.name.to_lowercase().contains(&q_lower)
-                    || f.manufacturer.to_lowercase().contains(&q_lower)
-                    || f.material.to_lowercase().contains(&q_lower)
-            });
-        }
-
-        if let Some(v) = vendor {
-            let v_lower = v.to_lowercase();
-            filaments.retain(|f| f.manufacturer.to_lowercase() == v_lower);
-        }
-
-        if let Some(m) = material {
-            let m_lower = m.to_lowercase();
-            filaments.retain(|f| f.material.to_lowercase() == m_lower);
-        }
-
         let total = filaments.len();
         let items = filaments
             .into_iter()
@@ -142,11 +462,18 @@ impl SpoolmanClient {
         Ok(SpoolmanResponse { items, total })
     }
 
-    pub async fn get_brands(&self) -> Result<Vec<String>, String> {
+    pub async fn get_all(&self) -> AppResult<Vec<SpoolmanFilament>> {
         self.ensure_cache().await?;
 
         let cache = self.cache.lock().await;
-        let filaments = cache.as_ref().unwrap();
+        Ok(cache.as_ref().unwrap().filaments.clone())
+    }
+
+    pub async fn get_brands(&self) -> AppResult<Vec<String>> {
+        self.ensure_cache().await?;
+
+        let cache = self.cache.lock().await;
+        let filaments = &cache.as_ref().unwrap().filaments;
 
         let mut brands: Vec<String> = filaments
             .iter()
@@ -161,11 +488,11 @@ impl SpoolmanClient {
         Ok(brands)
     }
 
-    pub async fn get_materials(&self) -> Result<Vec<String>, String> {
+    pub async fn get_materials(&self) -> AppResult<Vec<String>> {
         self.ensure_cache().await?;
 
         let cache = self.cache.lock().await;
-        let filaments = cache.as_ref().unwrap();
+        let filaments = &cache.as_ref().unwrap().filaments;
 
         let materials_set: HashSet<String> = filaments
             .iter()
@@ -180,14 +507,222 @@ impl SpoolmanClient {
         Ok(materials)
     }
 
-    pub async fn sync_database(&self) -> Result<(), String> {
+    /// Revalidates with the server, or re-reads a local source, even if the
+    /// catalog is loaded. Unlike the first load, failing to reach the
+    /// server is an error here.
+    pub async fn sync_database(&self) -> AppResult<()> {
         println!("🔄 Force syncing SpoolmanDB...");
-        let mut cache = self.cache.lock().await;
-        *cache = None;
-        drop(cache);
-        
         self.ensure_cache().await?;
+        let mut cache = self.cache.lock().await;
+        let current = cache.as_mut().unwrap();
+        let base_url = match &current.origin {
+            SpoolmanDbSource::Url(base_url) => base_url.clone(),
+            local => {
+                current.filaments = read_local(local)?;
+                current.meta.synced_at = Some(now_secs());
+                println!("✅ SpoolmanDB synced successfully");
+                return Ok(());
+            }
+        };
+        let meta = match current.source {
+            CacheSource::Bundled => CacheMeta::default(),
+            _ => current.meta.clone(),
+        };
+
+        match self.fetch(&base_url, &meta).await? {
+            Some((filaments, meta)) => {
+                current.filaments = filaments;
+                current.meta = meta;
+            }
+            None => {
+                current.meta.synced_at = Some(now_secs());
+                self.save_meta(&current.meta);
+            }
+        }
+        current.source = CacheSource::Network;
         println!("✅ SpoolmanDB synced successfully");
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    const CATALOG: &str = r#"[{"id":"acme_pla_red_1000_175_n","manufacturer":"Acme","name":"Red","material":"PLA","density":1.24,"diameter":1.75}]"#;
+
+    const POLYMAKER: &str = r#"{
+        "manufacturer": "Polymaker",
+        "filaments": [{
+            "name": "PolyTerra™ {color_name}",
+            "material": "PLA",
+            "density": 1.31,
+            "weights": [{ "weight": 1000, "spool_weight": 140, "spool_type": "cardboard" }],
+            "diameters": [1.75, 2.85],
+            "extruder_temp": 210,
+            "colors": [
+                { "name": "Charcoal Black", "hex": "000000" },
+                { "name": "Rainbow", "hexes": ["FF0000", "00FF00"], "multi_color_direction": "longitudinal" }
+            ]
+        }]
+    }"#;
+
+    /// A client whose download cache lives in `dir`.
+    fn client(dir: &Path, source: SpoolmanDbSource) -> SpoolmanClient {
+        let client = SpoolmanClient { cache_dir: Some(dir.join("cache")), ..SpoolmanClient::new() };
+        client.set_source(source);
+        client
+    }
+
+    /// Serves `CATALOG` at `/db/filaments.json` with an ETag and counts the
+    /// requests answered with 304.
+    async fn serve(not_modified: Arc<AtomicUsize>) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0u8; 4096];
+                let n = socket.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_lowercase();
+                let response = if !request.starts_with("get /db/filaments.json ") {
+                    "HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n".to_string()
+                } else if request.contains("if-none-match: \"v1\"") {
+                    not_modified.fetch_add(1, Ordering::SeqCst);
+                    "HTTP/1.1 304 Not Modified\r\netag: \"v1\"\r\nconnection: close\r\n\r\n".to_string()
+                } else {
+                    format!(
+                        "HTTP/1.1 200 OK\r\netag: \"v1\"\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                        CATALOG.len(),
+                        CATALOG
+                    )
+                };
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        format!("http://{}/db/", addr)
+    }
+
+    fn write_checkout(dir: &Path, files: &[(&str, &str)]) -> PathBuf {
+        let checkout = dir.join("SpoolmanDB");
+        std::fs::create_dir_all(checkout.join("filaments")).unwrap();
+        for (name, content) in files {
+            std::fs::write(checkout.join("filaments").join(name), content).unwrap();
+        }
+        checkout
+    }
+
+    #[test]
+    fn interprets_the_setting() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            SpoolmanDbSource::from_setting(Some("  ")),
+            SpoolmanDbSource::Url(DEFAULT_SPOOLMANDB_URL.to_string())
+        );
+        assert_eq!(
+            SpoolmanDbSource::from_setting(Some("https://example.com/db/filaments.json")),
+            SpoolmanDbSource::Url("https://example.com/db".to_string())
+        );
+
+        let file = dir.path().join("filaments.json");
+        std::fs::write(&file, CATALOG).unwrap();
+        let folder = dir.path().to_str().unwrap();
+        assert_eq!(SpoolmanDbSource::from_setting(Some(folder)), SpoolmanDbSource::File(file.clone()));
+        let url = format!("file://{}", file.display());
+        assert_eq!(SpoolmanDbSource::from_setting(Some(&url)), SpoolmanDbSource::File(file));
+
+        let checkout = write_checkout(dir.path(), &[]);
+        assert_eq!(
+            SpoolmanDbSource::from_setting(checkout.to_str()),
+            SpoolmanDbSource::Checkout(checkout.clone())
+        );
+        assert!(SpoolmanDbSource::Checkout(checkout).check().is_ok());
+    }
+
+    #[test]
+    fn rejects_missing_local_sources() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing.json");
+        let error = SpoolmanDbSource::File(missing).check().unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidInput);
+        // A folder without `filaments/` is no checkout.
+        let error = SpoolmanDbSource::Checkout(dir.path().to_path_buf()).check().unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidInput);
+    }
+
+    #[tokio::test]
+    async fn url_source_revalidates_its_disk_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let not_modified = Arc::new(AtomicUsize::new(0));
+        let url = serve(Arc::clone(&not_modified)).await;
+        let source = SpoolmanDbSource::from_setting(Some(&url));
+        assert_eq!(source, SpoolmanDbSource::Url(url.trim_end_matches('/').to_string()));
+
+        let status = client(dir.path(), source.clone()).status().await.unwrap();
+        assert_eq!((status.source, status.filament_count), (CacheSource::Network, 1));
+        assert_eq!(not_modified.load(Ordering::SeqCst), 0);
+
+        // The next launch asks whether its copy is still current.
+        let status = client(dir.path(), source).status().await.unwrap();
+        assert_eq!((status.source, status.filament_count), (CacheSource::Network, 1));
+        assert_eq!(not_modified.load(Ordering::SeqCst), 1);
+
+        // A mirror without the file fails; the bundled snapshot is SpoolmanDB's only.
+        let mirror = SpoolmanDbSource::Url(format!("{}missing", url));
+        let error = client(dir.path(), mirror).status().await.unwrap_err();
+        assert_eq!(error.code, ErrorCode::Network);
+    }
+
+    #[tokio::test]
+    async fn file_source_is_read_as_is() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("catalog.json");
+        std::fs::write(&file, CATALOG).unwrap();
+
+        let local = client(dir.path(), SpoolmanDbSource::File(file.clone()));
+        let status = local.status().await.unwrap();
+        assert_eq!((status.source, status.filament_count), (CacheSource::Local, 1));
+        assert_eq!(status.location, file.display().to_string());
+
+        let missing = SpoolmanDbSource::File(dir.path().join("other.json"));
+        assert_eq!(client(dir.path(), missing).status().await.unwrap_err().code, ErrorCode::NotFound);
+        std::fs::write(&file, "[{\"id\": 1}]").unwrap();
+        let malformed = SpoolmanDbSource::File(file);
+        assert_eq!(client(dir.path(), malformed).status().await.unwrap_err().code, ErrorCode::Parse);
+    }
+
+    #[tokio::test]
+    async fn checkout_source_is_compiled() {
+        let dir = tempfile::tempdir().unwrap();
+        let checkout = write_checkout(dir.path(), &[("polymaker.json", POLYMAKER), ("README.md", "not json")]);
+
+        let client = client(dir.path(), SpoolmanDbSource::Checkout(checkout));
+        let filaments = client.get_all().await.unwrap();
+        assert_eq!(filaments.len(), 4);
+        assert_eq!(filaments[0].id, "polymaker_pla_polyterracharcoalblack_1000_175_c");
+        assert_eq!(filaments[0].name, "PolyTerra™ Charcoal Black");
+        assert_eq!(filaments[0].spool_weight, Some(140.0));
+        let rainbow = filaments.iter().find(|f| f.color_hexes.is_some()).unwrap();
+        assert_eq!(rainbow.multi_color_direction.as_deref(), Some("longitudinal"));
+        assert_eq!(client.status().await.unwrap().source, CacheSource::Local);
+    }
+
+    #[tokio::test]
+    async fn checkout_errors_name_the_problem() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let empty = dir.path().join("empty");
+        std::fs::create_dir_all(&empty).unwrap();
+        let error = client(dir.path(), SpoolmanDbSource::Checkout(empty)).status().await.unwrap_err();
+        assert_eq!(error.code, ErrorCode::NotFound);
+        assert!(error.message.contains("filaments"), "{}", error.message);
+
+        let checkout = write_checkout(dir.path(), &[("polymaker.json", POLYMAKER), ("broken.json", "{\"manufacturer\": ")]);
+        let error = client(dir.path(), SpoolmanDbSource::Checkout(checkout)).status().await.unwrap_err();
+        assert_eq!(error.code, ErrorCode::Parse);
+        assert!(error.message.contains("broken.json"), "{}", error.message);
+    }
+}
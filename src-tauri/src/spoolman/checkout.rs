@@ -0,0 +1,172 @@
+//! Compiles the per-manufacturer source files of a SpoolmanDB checkout
+//! (`filaments/*.json`) into the flat list published as `filaments.json`:
+//! one entry per color, weight and diameter.
+
+use super::SpoolmanFilament;
+use crate::error::{AppError, AppResult, ErrorCode};
+use serde::Deserialize;
+use std::path::Path;
+
+#[derive(Debug, Deserialize)]
+struct ManufacturerFile {
+    manufacturer: String,
+    filaments: Vec<SourceFilament>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SourceFilament {
+    /// May contain `{color_name}`.
+    name: String,
+    material: String,
+    density: f64,
+    weights: Vec<SourceWeight>,
+    diameters: Vec<f64>,
+    colors: Vec<SourceColor>,
+    #[serde(default)]
+    extruder_temp: Option<i32>,
+    #[serde(default)]
+    extruder_temp_range: Option<Vec<i32>>,
+    #[serde(default)]
+    bed_temp: Option<i32>,
+    #[serde(default)]
+    bed_temp_range: Option<Vec<i32>>,
+    #[serde(default)]
+    finish: Option<String>,
+    #[serde(default)]
+    multi_color_direction: Option<String>,
+    #[serde(default)]
+    pattern: Option<String>,
+    #[serde(default)]
+    translucent: bool,
+    #[serde(default)]
+    glow: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct SourceWeight {
+    weight: f64,
+    #[serde(default)]
+    spool_weight: Option<f64>,
+    #[serde(default)]
+    spool_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SourceColor {
+    name: String,
+    #[serde(default)]
+    hex: Option<String>,
+    #[serde(default)]
+    hexes: Option<Vec<String>>,
+    #[serde(default)]
+    multi_color_direction: Option<String>,
+    #[serde(default)]
+    translucent: Option<bool>,
+    #[serde(default)]
+    glow: Option<bool>,
+}
+
+/// Lowercase ASCII letters and digits only, as in SpoolmanDB ids.
+fn id_part(value: &str) -> String {
+    value
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .collect()
+}
+
+/// `{manufacturer}_{material}_{name}_{weight}_{diameter}_{spool type}`,
+/// e.g. `polymaker_pla_polyterracharcoalblack_1000_175_c`.
+fn filament_id(
+    manufacturer: &str,
+    material: &str,
+    name: &str,
+    weight: f64,
+    diameter: f64,
+    spool_type: Option<&str>,
+) -> String {
+    let spool = spool_type
+        .and_then(|t| t.chars().next())
+        .map(|c| c.to_ascii_lowercase())
+        .unwrap_or('n');
+    format!(
+        "{}_{}_{}_{}_{}_{}",
+        id_part(manufacturer),
+        id_part(material),
+        id_part(name),
+        weight.round() as i64,
+        id_part(&diameter.to_string()),
+        spool
+    )
+}
+
+fn compile_file(file: ManufacturerFile, filaments: &mut Vec<SpoolmanFilament>) {
+    let manufacturer = file.manufacturer;
+    for source in file.filaments {
+        for color in &source.colors {
+            let name = source.name.replace("{color_name}", &color.name);
+            for weight in &source.weights {
+                for &diameter in &source.diameters {
+                    filaments.push(SpoolmanFilament {
+                        id: filament_id(
+                            &manufacturer,
+                            &source.material,
+                            &name,
+                            weight.weight,
+                            diameter,
+                            weight.spool_type.as_deref(),
+                        ),
+                        manufacturer: manufacturer.clone(),
+                        name: name.clone(),
+                        material: source.material.clone(),
+                        density: source.density,
+                        diameter,
+                        color_hex: color.hex.clone(),
+                        color_hexes: color.hexes.clone(),
+                        weight: Some(weight.weight),
+                        spool_weight: weight.spool_weight,
+                        spool_type: weight.spool_type.clone(),
+                        extruder_temp: source.extruder_temp,
+                        extruder_temp_range: source.extruder_temp_range.clone(),
+                        bed_temp: source.bed_temp,
+                        bed_temp_range: source.bed_temp_range.clone(),
+                        finish: source.finish.clone(),
+                        multi_color_direction: color
+                            .multi_color_direction
+                            .clone()
+                            .or_else(|| source.multi_color_direction.clone()),
+                        pattern: source.pattern.clone(),
+                        translucent: color.translucent.unwrap_or(source.translucent),
+                        glow: color.glow.unwrap_or(source.glow),
+                    });
+                }
+            }
+        }
+    }
+}
+
+/// Reads every `filaments/*.json` under `checkout`, in file name order.
+pub fn compile(checkout: &Path) -> AppResult<Vec<SpoolmanFilament>> {
+    let dir = checkout.join("filaments");
+    let read_error = |e: std::io::Error| AppError {
+        message: format!("Failed to read {}: {}", dir.display(), e),
+        ..e.into()
+    };
+    let mut paths = std::fs::read_dir(&dir)
+        .map_err(read_error)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(read_error)?;
+    paths.retain(|path| path.extension().is_some_and(|ext| ext == "json"));
+    paths.sort();
+
+    let mut filaments = Vec::new();
+    for path in paths {
+        let data = std::fs::read(&path).map_err(read_error)?;
+        let file: ManufacturerFile = serde_json::from_slice(&data).map_err(|e| {
+            AppError::new(ErrorCode::Parse, format!("Failed to parse {}: {}", path.display(), e))
+        })?;
+        compile_file(file, &mut filaments);
+    }
+    Ok(filaments)
+}
@@ -0,0 +1,299 @@
+//! Typo-tolerant, ranked search over the SpoolmanDB catalog. Every query
+//! word must match a word of the name, manufacturer, material or finish,
+//! exactly, as a prefix, inside a compound word or within a few typos.
+
+use super::SpoolmanFilament;
+use std::collections::{HashMap, HashSet};
+
+/// Days after which a use no longer lifts a filament.
+pub const RECENT_DAYS: f64 = 90.0;
+
+const FAVORITE_BOOST: f64 = 0.5;
+/// For a use today, fading to nothing at `RECENT_DAYS`.
+const RECENT_BOOST: f64 = 0.5;
+
+const NAME_WEIGHT: f64 = 1.0;
+const MANUFACTURER_WEIGHT: f64 = 1.0;
+const MATERIAL_WEIGHT: f64 = 1.2;
+const FINISH_WEIGHT: f64 = 0.6;
+
+/// Ranking lifts, keyed by SpoolmanDB id.
+#[derive(Debug, Clone, Default)]
+pub struct SearchBoosts {
+    pub favorites: HashSet<String>,
+    /// Days since the filament was last synced to an AMS or used in a print.
+    pub recent: HashMap<String, f64>,
+}
+
+impl SearchBoosts {
+    fn factor(&self, id: &str) -> f64 {
+        let mut factor = 1.0;
+        if self.favorites.contains(id) {
+            factor += FAVORITE_BOOST;
+        }
+        if let Some(days) = self.recent.get(id) {
+            factor += RECENT_BOOST * (1.0 - days / RECENT_DAYS).clamp(0.0, 1.0);
+        }
+        factor
+    }
+}
+
+/// Lowercase alphanumeric runs: "PolyTerra™ PLA-CF" is `polyterra pla cf`.
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Typos tolerated in a word of `len` characters.
+fn allowed_typos(len: usize) -> usize {
+    match len {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// Optimal string alignment distance: insertions, deletions,
+/// substitutions and swaps of neighbours each count one.
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut rows = vec![(0..=b.len()).collect::<Vec<_>>()];
+    for i in 1..=a.len() {
+        let mut row = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            row[j] = (rows[i - 1][j] + 1)
+                .min(row[j - 1] + 1)
+                .min(rows[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                row[j] = row[j].min(rows[i - 2][j - 2] + 1);
+            }
+        }
+        rows.push(row);
+    }
+    rows[a.len()][b.len()]
+}
+
+struct Field {
+    words: Vec<String>,
+    /// All words run together, so "petghf" finds "PETG HF".
+    compact: String,
+    weight: f64,
+}
+
+impl Field {
+    fn new(text: &str, weight: f64) -> Self {
+        let words = tokenize(text);
+        Self {
+            compact: words.concat(),
+            words,
+            weight,
+        }
+    }
+
+    /// How well one query word matches this field, from 0 (not at all) to 1.
+    fn word_score(&self, word: &str) -> f64 {
+        if self.compact == word {
+            return 1.0;
+        }
+        let chars: Vec<char> = word.chars().collect();
+        let typos = allowed_typos(chars.len());
+        let mut best: f64 = 0.0;
+        for candidate in &self.words {
+            let score = if candidate == word {
+                1.0
+            } else if candidate.starts_with(word) {
+                0.8
+            } else if typos > 0 {
+                let candidate: Vec<char> = candidate.chars().collect();
+                if candidate.len().abs_diff(chars.len()) <= typos {
+                    let distance = edit_distance(&chars, &candidate);
+                    if distance <= typos { 0.75 - 0.1 * distance as f64 } else { 0.0 }
+                } else if candidate.len() > chars.len() {
+                    // A typo in a word still being typed.
+                    let distance = edit_distance(&chars, &candidate[..chars.len()]);
+                    if distance <= typos { 0.5 - 0.1 * distance as f64 } else { 0.0 }
+                } else {
+                    0.0
+                }
+            } else {
+                0.0
+            };
+            best = best.max(score);
+        }
+        if best < 0.6 && chars.len() >= 3 && self.compact.contains(word) {
+            best = 0.6;
+        }
+        best
+    }
+}
+
+/// Sums each query word's best weighted match across `fields`, with a
+/// bonus for a field the whole query spells out. `None` when any word
+/// matches nothing.
+fn match_score(words: &[String], fields: &[Field]) -> Option<f64> {
+    let mut total = 0.0;
+    for word in words {
+        let best = fields
+            .iter()
+            .map(|field| field.word_score(word) * field.weight)
+            .fold(0.0, f64::max);
+        if best == 0.0 {
+            return None;
+        }
+        total += best;
+    }
+    let whole = words.concat();
+    if let Some(field) = fields.iter().find(|field| field.compact == whole) {
+        total += field.weight;
+    }
+    Some(total)
+}
+
+/// Whether `field` is `filter` up to case and punctuation, so "pla-cf"
+/// selects "PLA-CF" but "PLA" does not.
+fn filter_matches(filter: &[String], field: &str) -> bool {
+    filter.is_empty() || tokenize(field) == filter
+}
+
+/// Filters by the optional vendor and material, which must match exactly,
+/// and the query, which tolerates typos, and sorts by score, best first;
+/// ties keep catalog order. Without a query every filament scores the
+/// same, so only the boosts order the results.
+pub fn search(
+    filaments: &[SpoolmanFilament],
+    query: Option<&str>,
+    vendor: Option<&str>,
+    material: Option<&str>,
+    boosts: &SearchBoosts,
+) -> Vec<SpoolmanFilament> {
+    let query = query.map(tokenize).unwrap_or_default();
+    let vendor = vendor.map(tokenize).unwrap_or_default();
+    let material = material.map(tokenize).unwrap_or_default();
+
+    let mut scored: Vec<(f64, &SpoolmanFilament)> = filaments
+        .iter()
+        .filter(|filament| {
+            filter_matches(&vendor, &filament.manufacturer) && filter_matches(&material, &filament.material)
+        })
+        .filter_map(|filament| {
+            let fields = [
+                Field::new(&filament.name, NAME_WEIGHT),
+                Field::new(&filament.manufacturer, MANUFACTURER_WEIGHT),
+                Field::new(&filament.material, MATERIAL_WEIGHT),
+                Field::new(filament.finish.as_deref().unwrap_or_default(), FINISH_WEIGHT),
+            ];
+            let score = 1.0 + match_score(&query, &fields)?;
+            Some((score * boosts.factor(&filament.id), filament))
+        })
+        .collect();
+
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.into_iter().map(|(_, filament)| filament.clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filament(id: &str, manufacturer: &str, name: &str, material: &str) -> SpoolmanFilament {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "manufacturer": manufacturer,
+            "name": name,
+            "material": material,
+            "density": 1.24,
+            "diameter": 1.75
+        }))
+        .unwrap()
+    }
+
+    fn catalog() -> Vec<SpoolmanFilament> {
+        vec![
+            filament("acme_petg", "Acme", "Petg Thing", "PETG"),
+            filament("polyterra", "Polymaker", "PolyTerra™ Charcoal Black", "PLA"),
+            filament("bambu_petg_hf", "Bambu Lab", "PETG HF Black", "PETG HF"),
+            filament("polylite", "Polymaker", "PolyLite Black", "PLA"),
+            filament("elegoo_petg", "Elegoo", "Rapid PETG", "PETG"),
+            filament("acme_pctg", "Acme", "Clear", "PCTG"),
+            filament("acme_pla_cf", "Acme", "Plain", "PLA-CF"),
+            filament("acme_pla_hf", "Acme", "Fast", "PLA HF"),
+        ]
+    }
+
+    fn ids(query: Option<&str>, vendor: Option<&str>, material: Option<&str>, boosts: &SearchBoosts) -> Vec<String> {
+        search(&catalog(), query, vendor, material, boosts)
+            .into_iter()
+            .map(|filament| filament.id)
+            .collect()
+    }
+
+    fn query(text: &str) -> Vec<String> {
+        ids(Some(text), None, None, &SearchBoosts::default())
+    }
+
+    #[test]
+    fn tolerates_typos_in_the_query() {
+        assert_eq!(query("polyterra"), ["polyterra"]);
+        assert_eq!(query("polytera"), ["polyterra"]);
+        assert_eq!(query("polyterrra"), ["polyterra"]);
+        assert_eq!(query("ploymaker"), ["polyterra", "polylite"]);
+        assert_eq!(query("terra"), ["polyterra"]);
+        assert!(query("xyzzy").is_empty());
+    }
+
+    #[test]
+    fn ranks_the_field_the_whole_query_spells_first() {
+        assert_eq!(query("petg hf"), ["bambu_petg_hf"]);
+        assert_eq!(query("petghf"), ["bambu_petg_hf"]);
+        assert_eq!(query("petg"), ["acme_petg", "elegoo_petg", "bambu_petg_hf", "acme_pctg"]);
+    }
+
+    #[test]
+    fn filters_are_exact() {
+        let none = SearchBoosts::default();
+        assert_eq!(ids(None, None, Some("PETG"), &none), ["acme_petg", "elegoo_petg"]);
+        assert_eq!(ids(None, None, Some("pla"), &none), ["polyterra", "polylite"]);
+        assert_eq!(ids(None, None, Some("pla cf"), &none), ["acme_pla_cf"]);
+        assert_eq!(ids(None, None, Some("PLA-HF"), &none), ["acme_pla_hf"]);
+        assert_eq!(ids(None, Some("POLYMAKER"), Some("PLA"), &none), ["polyterra", "polylite"]);
+        assert!(ids(None, Some("bambu"), None, &none).is_empty());
+        assert!(ids(None, Some("ploymaker"), None, &none).is_empty());
+        assert_eq!(ids(Some("black"), Some("Bambu Lab"), None, &none), ["bambu_petg_hf"]);
+    }
+
+    #[test]
+    fn favorites_and_recent_uses_rank_higher() {
+        let mut boosts = SearchBoosts::default();
+        boosts.favorites.insert("elegoo_petg".to_string());
+        assert_eq!(ids(Some("petg"), None, None, &boosts)[0], "elegoo_petg");
+
+        let mut boosts = SearchBoosts::default();
+        boosts.recent.insert("polylite".to_string(), 1.0);
+        assert_eq!(ids(Some("polymaker"), None, None, &boosts), ["polylite", "polyterra"]);
+
+        // A use fades over `RECENT_DAYS`.
+        boosts.recent.insert("polyterra".to_string(), 30.0);
+        assert_eq!(ids(Some("polymaker"), None, None, &boosts), ["polylite", "polyterra"]);
+        boosts.recent.insert("polylite".to_string(), RECENT_DAYS + 10.0);
+        assert_eq!(ids(Some("polymaker"), None, None, &boosts), ["polyterra", "polylite"]);
+    }
+
+    #[test]
+    fn boosts_alone_order_an_unfiltered_list() {
+        let mut boosts = SearchBoosts::default();
+        boosts.favorites.insert("acme_pctg".to_string());
+        boosts.recent.insert("polylite".to_string(), 2.0);
+        let ids = ids(None, None, None, &boosts);
+        assert_eq!(ids[..3], ["acme_pctg", "polylite", "acme_petg"]);
+    }
+
+    #[test]
+    fn edit_distance_counts_swaps_once() {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        assert_eq!(edit_distance(&chars("ploy"), &chars("poly")), 1);
+        assert_eq!(edit_distance(&chars("petg"), &chars("pctg")), 1);
+        assert_eq!(edit_distance(&chars(""), &chars("pla")), 3);
+    }
+}
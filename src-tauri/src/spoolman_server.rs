@@ -0,0 +1,458 @@
+//! Client for a self-hosted Spoolman server (`/api/v1`), which tracks the
+//! physical spools; `spoolman.rs` reads the SpoolmanDB catalog instead.
+
+use crate::db::DbPool;
+use crate::error::{AppError, AppResult, ErrorCode};
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ServerInfo {
+    pub version: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ServerVendor {
+    pub id: i64,
+    pub name: String,
+    #[serde(default)]
+    pub comment: Option<String>,
+    #[serde(default)]
+    pub empty_spool_weight: Option<f64>,
+    #[serde(default)]
+    pub external_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ServerFilament {
+    pub id: i64,
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub vendor: Option<ServerVendor>,
+    #[serde(default)]
+    pub material: Option<String>,
+    /// Price of a full spool.
+    #[serde(default)]
+    pub price: Option<f64>,
+    pub density: f64,
+    pub diameter: f64,
+    /// Net filament weight of a full spool, g.
+    #[serde(default)]
+    pub weight: Option<f64>,
+    #[serde(default)]
+    pub spool_weight: Option<f64>,
+    #[serde(default)]
+    pub article_number: Option<String>,
+    #[serde(default)]
+    pub comment: Option<String>,
+    #[serde(default)]
+    pub settings_extruder_temp: Option<i32>,
+    #[serde(default)]
+    pub settings_bed_temp: Option<i32>,
+    /// `RRGGBB` or `RRGGBBAA`, without `#`.
+    #[serde(default)]
+    pub color_hex: Option<String>,
+    /// Comma-separated, for multi-color filaments.
+    #[serde(default)]
+    pub multi_color_hexes: Option<String>,
+    #[serde(default)]
+    pub multi_color_direction: Option<String>,
+    /// The SpoolmanDB id when the filament was created from SpoolmanDB.
+    #[serde(default)]
+    pub external_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ServerSpool {
+    pub id: i64,
+    #[serde(default)]
+    pub registered: Option<String>,
+    #[serde(default)]
+    pub first_used: Option<String>,
+    #[serde(default)]
+    pub last_used: Option<String>,
+    pub filament: ServerFilament,
+    #[serde(default)]
+    pub price: Option<f64>,
+    #[serde(default)]
+    pub initial_weight: Option<f64>,
+    #[serde(default)]
+    pub spool_weight: Option<f64>,
+    #[serde(default)]
+    pub remaining_weight: Option<f64>,
+    #[serde(default)]
+    pub used_weight: f64,
+    #[serde(default)]
+    pub location: Option<String>,
+    #[serde(default)]
+    pub lot_nr: Option<String>,
+    #[serde(default)]
+    pub comment: Option<String>,
+    #[serde(default)]
+    pub archived: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct UseRequest {
+    use_weight: f64,
+}
+
+pub struct SpoolmanServerClient {
+    client: reqwest::Client,
+    /// e.g. `http://localhost:7912`; `None` until configured in the settings.
+    base_url: Mutex<Option<String>>,
+    /// Held while queued usage is reported, so it is never sent twice.
+    reporting: tokio::sync::Mutex<()>,
+}
+
+impl SpoolmanServerClient {
+    pub fn new() -> Self {
+        Self {
+            client: reqwest::Client::builder()
+                .timeout(REQUEST_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            base_url: Mutex::new(None),
+            reporting: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Empty or `None` disconnects. A trailing `/api/v1` is accepted.
+    pub fn set_base_url(&self, url: Option<&str>) {
+        let url = url
+            .map(|url| url.trim().trim_end_matches('/').trim_end_matches("/api/v1").to_string())
+            .filter(|url| !url.is_empty());
+        match &url {
+            Some(url) => println!("🧵 Spoolman server: {}", url),
+            None => println!("🧵 Spoolman server: not configured"),
+        }
+        *self.base_url.lock().unwrap() = url;
+    }
+
+    pub fn is_configured(&self) -> bool {
+        self.base_url.lock().unwrap().is_some()
+    }
+
+
+    fn url(&self, path: &str) -> AppResult<String> {
+        let base_url = self.base_url.lock().unwrap().clone().ok_or_else(|| {
+            AppError::invalid("No Spoolman server is configured. Set its URL in Settings.")
+        })?;
+        Ok(format!("{}/api/v1/{}", base_url, path))
+    }
+
+    async fn send<T: DeserializeOwned>(&self, request: reqwest::RequestBuilder) -> AppResult<T> {
+        let response = request
+            .header("User-Agent", "SpoolSync/1.0")
+            .send()
+            .await
+            .map_err(|e| AppError::new(ErrorCode::Network, format!("Spoolman server unreachable: {}", e)))?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            // Spoolman reports errors as `{"message": "..."}`.
+            let message = serde_json::from_str::<serde_json::Value>(&body)
+                .ok()
+                .and_then(|value| value.get("message").and_then(|m| m.as_str()).map(str::to_string))
+                .unwrap_or(body);
+            let code = if status == StatusCode::NOT_FOUND { ErrorCode::NotFound } else { ErrorCode::Network };
+            return Err(AppError::new(code, format!("Spoolman server error: {} - {}", status, message))
+                .with_details(serde_json::json!({ "status": status.as_u16() })));
+        }
+
+        response
+            .json()
+            .await
+            .map_err(|e| AppError::new(ErrorCode::Parse, format!("Failed to parse Spoolman response: {}", e)))
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> AppResult<T> {
+        let url = self.url(path)?;
+        self.send(self.client.get(&url)).await
+    }
+
+    /// Doubles as the connection test.
+    pub async fn info(&self) -> AppResult<ServerInfo> {
+        self.get("info").await
+    }
+
+    pub async fn get_vendors(&self) -> AppResult<Vec<ServerVendor>> {
+        self.get("vendor").await
+    }
+
+    pub async fn get_filaments(&self) -> AppResult<Vec<ServerFilament>> {
+        self.get("filament").await
+    }
+
+    pub async fn get_spools(&self, allow_archived: bool) -> AppResult<Vec<ServerSpool>> {
+        let spools: Vec<ServerSpool> = self
+            .get(&format!("spool?allow_archived={}", allow_archived))
+            .await?;
+        println!("🧵 Loaded {} spools from Spoolman", spools.len());
+        Ok(spools)
+    }
+
+    pub async fn get_spool(&self, id: i64) -> AppResult<ServerSpool> {
+        self.get(&format!("spool/{}", id)).await
+    }
+
+    pub async fn get_locations(&self) -> AppResult<Vec<String>> {
+        self.get("location").await
+    }
+
+    /// Subtracts `grams` from the spool on the server and returns it updated.
+    pub async fn use_spool(&self, id: i64, grams: f64) -> AppResult<ServerSpool> {
+        let url = self.url(&format!("spool/{}/use", id))?;
+        println!("🧵 Reporting {:.1} g used on Spoolman spool {}", grams, id);
+        self.send(self.client.put(&url).json(&UseRequest { use_weight: grams }))
+            .await
+    }
+
+    /// Reports queued usage of spools linked to Spoolman and returns how
+    /// many spools were reported. Each spool's usage is taken off the queue
+    /// before it is sent, so a failure to record the report can never send
+    /// it twice; a failed report puts it back for the next attempt.
+    pub async fn report_usage(&self, pool: &DbPool) -> AppResult<usize> {
+        if !self.is_configured() {
+            return Ok(0);
+        }
+        let _reporting = self.reporting.lock().await;
+        let pending = pool.get()?.get_unreported_usage()?;
+        for usage in &pending {
+            pool.get()?.mark_usage_reported(usage.spool_id, usage.grams)?;
+            if let Err(e) = self.use_spool(usage.spoolman_id, usage.grams).await {
+                if let Err(requeue) = pool.get().and_then(|db| db.requeue_usage(usage.spool_id, usage.grams)) {
+                    println!("⚠️  Lost {:.1} g of usage for spool {}: {}", usage.grams, usage.spool_id, requeue);
+                }
+                return Err(e);
+            }
+        }
+        Ok(pending.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{open_temp, Printer, SpoolmanImportReport};
+    use crate::mqtt::BambuPrinterConfig;
+    use std::sync::Arc;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// What the stand-in server holds for its one spool, id 7.
+    #[derive(Default)]
+    struct StandIn {
+        remaining: f64,
+        fail_use: bool,
+        /// `use_weight` of every accepted `PUT /spool/7/use`.
+        uses: Vec<f64>,
+    }
+
+    fn spool_json(remaining: f64) -> String {
+        serde_json::json!({
+            "id": 7,
+            "registered": "2024-01-02T03:04:05Z",
+            "first_used": "2024-02-01T00:00:00Z",
+            "filament": {
+                "id": 3,
+                "name": "PolyTerra Charcoal Black",
+                "vendor": { "id": 1, "name": "Polymaker", "extra": {} },
+                "material": "PLA",
+                "price": 20.0,
+                "density": 1.31,
+                "diameter": 1.75,
+                "weight": 1000.0,
+                "settings_extruder_temp": 210,
+                "color_hex": "000000",
+                "external_id": "polymaker_pla_polyterracharcoalblack_1000_175_c",
+                "extra": {}
+            },
+            "initial_weight": 1000.0,
+            "remaining_weight": remaining,
+            "used_weight": 1000.0 - remaining,
+            "location": "Shelf A",
+            "lot_nr": "L1",
+            "archived": false,
+            "extra": {}
+        })
+        .to_string()
+    }
+
+    fn respond(state: &Mutex<StandIn>, request_line: &str, body: &str) -> (&'static str, String) {
+        let mut state = state.lock().unwrap();
+        let target = request_line.split(' ').take(2).collect::<Vec<_>>().join(" ");
+        match target.as_str() {
+            "GET /api/v1/info" => ("200 OK", r#"{"version":"0.22.1"}"#.to_string()),
+            "GET /api/v1/spool?allow_archived=false" => ("200 OK", format!("[{}]", spool_json(state.remaining))),
+            "GET /api/v1/spool/7" => ("200 OK", spool_json(state.remaining)),
+            "PUT /api/v1/spool/7/use" if state.fail_use => {
+                ("500 Internal Server Error", r#"{"message":"Database is locked"}"#.to_string())
+            }
+            "PUT /api/v1/spool/7/use" => {
+                let grams = serde_json::from_str::<serde_json::Value>(body).unwrap()["use_weight"]
+                    .as_f64()
+                    .unwrap();
+                state.uses.push(grams);
+                state.remaining -= grams;
+                ("200 OK", spool_json(state.remaining))
+            }
+            _ => ("404 Not Found", r#"{"message":"Item not found"}"#.to_string()),
+        }
+    }
+
+    /// Serves one request per connection on a free local port and returns
+    /// the base URL, given with `/api/v1/` as users often paste it.
+    async fn serve(state: Arc<Mutex<StandIn>>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut data = Vec::new();
+                let mut buf = [0u8; 4096];
+                let (head, body) = loop {
+                    let n = socket.read(&mut buf).await.unwrap();
+                    data.extend_from_slice(&buf[..n]);
+                    let text = String::from_utf8_lossy(&data).to_string();
+                    if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                        let length = head
+                            .lines()
+                            .find_map(|line| line.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse().unwrap()))
+                            .unwrap_or(0);
+                        if body.len() >= length || n == 0 {
+                            break (head.to_string(), body.to_string());
+                        }
+                    }
+                };
+                let (status, out) = respond(&state, head.lines().next().unwrap_or_default(), &body);
+                let response = format!(
+                    "HTTP/1.1 {}\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    status,
+                    out.len(),
+                    out
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        format!("http://{}/api/v1/", addr)
+    }
+
+    async fn client(remaining: f64) -> (SpoolmanServerClient, Arc<Mutex<StandIn>>) {
+        let state = Arc::new(Mutex::new(StandIn { remaining, ..Default::default() }));
+        let client = SpoolmanServerClient::new();
+        client.set_base_url(Some(&serve(Arc::clone(&state)).await));
+        (client, state)
+    }
+
+    #[tokio::test]
+    async fn maps_errors() {
+        let unconfigured = SpoolmanServerClient::new();
+        assert_eq!(unconfigured.info().await.unwrap_err().code, ErrorCode::InvalidInput);
+
+        let (client, _) = client(800.0).await;
+        assert_eq!(client.info().await.unwrap().version, "0.22.1");
+        let error = client.get_spool(99).await.unwrap_err();
+        assert_eq!(error.code, ErrorCode::NotFound);
+        assert!(error.message.contains("Item not found"), "{}", error.message);
+    }
+
+    #[tokio::test]
+    async fn imports_spools_and_keeps_tray_assignment() {
+        let (client, state) = client(800.0).await;
+        let (_dir, pool) = open_temp();
+        let db = pool.get().unwrap();
+
+        let report = db.import_server_spools(&client.get_spools(false).await.unwrap()).unwrap();
+        assert_eq!((report.created, report.updated, report.profiles_created), (1, 0, 1));
+        let spool = db.get_spool(report.spool_ids[0]).unwrap();
+        assert_eq!(spool.spoolman_id.as_deref(), Some("7"));
+        assert_eq!(spool.remaining_weight, 800.0);
+        assert_eq!(spool.opened_date.as_deref(), Some("2024-02-01"));
+        let profile = db.get_profile(spool.profile_id.unwrap()).unwrap();
+        assert!(profile.is_favorite);
+        assert_eq!((profile.brand.as_str(), profile.color.as_str()), ("Polymaker", "#000000"));
+        assert_eq!(profile.price_per_kg, Some(20.0));
+
+        let printer_id = db
+            .create_printer(Printer {
+                id: None,
+                config: BambuPrinterConfig {
+                    name: "X1C".to_string(),
+                    model: String::new(),
+                    ip_address: "192.0.2.1".to_string(),
+                    access_code: "12345678".to_string(),
+                    serial_number: "00M00A000000000".to_string(),
+                    default_ams: 0,
+                    default_tray: 0,
+                },
+                is_default: true,
+            })
+            .unwrap();
+        let spool_id = spool.id.unwrap();
+        db.assign_spool_to_tray(spool_id, printer_id, 0, 2).unwrap();
+
+        // Spoolman is authoritative for the weight; the tray stays as picked.
+        state.lock().unwrap().remaining = 640.0;
+        let remote = client.get_spool(7).await.unwrap();
+        let mut report = SpoolmanImportReport::default();
+        assert_eq!(db.import_server_spool(&remote, &mut report).unwrap(), spool_id);
+        assert_eq!((report.created, report.updated, report.profiles_created), (0, 1, 0));
+        let spool = db.get_spool(spool_id).unwrap();
+        assert_eq!(spool.remaining_weight, 640.0);
+        assert_eq!((spool.printer_id, spool.ams_id, spool.tray_id), (Some(printer_id), Some(0), Some(2)));
+        assert_eq!(db.get_spool_in_tray(printer_id, 0, 2).unwrap().unwrap().id, Some(spool_id));
+    }
+
+    #[tokio::test]
+    async fn reports_usage_once() {
+        let (client, state) = client(800.0).await;
+        let (_dir, pool) = open_temp();
+        let spool_id = {
+            let db = pool.get().unwrap();
+            let report = db.import_server_spools(&client.get_spools(false).await.unwrap()).unwrap();
+            let spool_id = report.spool_ids[0];
+            db.record_spool_usage(spool_id, 25.0, None).unwrap();
+            // Weighing is not usage and is never reported.
+            db.set_spool_weight(spool_id, 770.0, None).unwrap();
+            spool_id
+        };
+
+        // A refresh before the report must not count the usage twice.
+        pool.get().unwrap().import_server_spools(&client.get_spools(false).await.unwrap()).unwrap();
+        assert_eq!(pool.get().unwrap().get_spool(spool_id).unwrap().remaining_weight, 775.0);
+
+        assert_eq!(client.report_usage(&pool).await.unwrap(), 1);
+        assert_eq!(client.report_usage(&pool).await.unwrap(), 0);
+        let state = state.lock().unwrap();
+        assert_eq!(state.uses, vec![25.0]);
+        assert_eq!(state.remaining, 775.0);
+    }
+
+    #[tokio::test]
+    async fn failed_report_is_retried() {
+        let (client, state) = client(800.0).await;
+        let (_dir, pool) = open_temp();
+        {
+            let db = pool.get().unwrap();
+            let report = db.import_server_spools(&client.get_spools(false).await.unwrap()).unwrap();
+            db.record_spool_usage(report.spool_ids[0], 12.5, None).unwrap();
+        }
+
+        state.lock().unwrap().fail_use = true;
+        assert_eq!(client.report_usage(&pool).await.unwrap_err().code, ErrorCode::Network);
+        assert_eq!(pool.get().unwrap().get_unreported_usage().unwrap()[0].grams, 12.5);
+
+        state.lock().unwrap().fail_use = false;
+        assert_eq!(client.report_usage(&pool).await.unwrap(), 1);
+        assert!(pool.get().unwrap().get_unreported_usage().unwrap().is_empty());
+        assert_eq!(state.lock().unwrap().uses, vec![12.5]);
+    }
+}
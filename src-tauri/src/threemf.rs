@@ -0,0 +1,228 @@
+use crate::error::{AppError, AppResult, ErrorCode};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{Read, Seek};
+
+/// Filament use of one slot as reported by the slicer that produced a
+/// 3MF project. Slots are 1-based, as in the slicer.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ThreeMfFilament {
+    pub slot: u32,
+    pub material: Option<String>,
+    pub color: Option<String>,
+    pub grams: f64,
+    pub meters: Option<f64>,
+}
+
+/// Reads per-filament usage from a sliced 3MF. Bambu Studio and
+/// OrcaSlicer write `Metadata/slice_info.config` (summed over all plates);
+/// PrusaSlicer-style projects only carry `filament used [g]` in their
+/// config, which is used as a fallback.
+pub fn read_filament_usage(path: &str) -> AppResult<Vec<ThreeMfFilament>> {
+    let file = File::open(path).map_err(|e| AppError {
+        message: format!("Failed to open {}: {}", path, e),
+        ..e.into()
+    })?;
+    read_usage(file)
+}
+
+fn read_usage<R: Read + Seek>(reader: R) -> AppResult<Vec<ThreeMfFilament>> {
+    let mut archive = zip::ZipArchive::new(reader)
+        .map_err(|e| AppError::new(ErrorCode::Parse, format!("Not a 3MF archive: {}", e)))?;
+
+    if let Some(content) = read_entry(&mut archive, "Metadata/slice_info.config")? {
+        let filaments = parse_slice_info(&content);
+        if !filaments.is_empty() {
+            return Ok(filaments);
+        }
+    }
+
+    for name in ["Metadata/Slic3r_PE.config", "Metadata/project_settings.config"] {
+        if let Some(content) = read_entry(&mut archive, name)? {
+            let filaments = parse_filament_used(&content);
+            if !filaments.is_empty() {
+                return Ok(filaments);
+            }
+        }
+    }
+
+    Err(AppError::invalid("No filament usage found; was the project sliced before saving?"))
+}
+
+fn read_entry<R: Read + Seek>(archive: &mut zip::ZipArchive<R>, name: &str) -> AppResult<Option<String>> {
+    let mut entry = match archive.by_name(name) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(AppError::new(ErrorCode::Parse, format!("Failed to read {}: {}", name, e))),
+    };
+    let mut content = String::new();
+    entry
+        .read_to_string(&mut content)
+        .map_err(|e| AppError::new(ErrorCode::Parse, format!("Failed to read {}: {}", name, e)))?;
+    Ok(Some(content))
+}
+
+/// Attributes of every `<tag .../>` element, in document order.
+fn elements<'a>(xml: &'a str, tag: &str) -> Vec<BTreeMap<&'a str, &'a str>> {
+    let open = format!("<{} ", tag);
+    let mut result = Vec::new();
+    let mut rest = xml;
+
+    while let Some(start) = rest.find(&open) {
+        let body = &rest[start + open.len()..];
+        let end = body.find('>').unwrap_or(body.len());
+        let mut attributes = BTreeMap::new();
+        let mut attrs = &body[..end];
+
+        while let Some(eq) = attrs.find("=\"") {
+            let key = attrs[..eq].trim();
+            let value_start = eq + 2;
+            let Some(value_len) = attrs[value_start..].find('"') else {
+                break;
+            };
+            attributes.insert(key, &attrs[value_start..value_start + value_len]);
+            attrs = &attrs[value_start + value_len + 1..];
+        }
+
+        result.push(attributes);
+        rest = &body[end..];
+    }
+
+    result
+}
+
+fn parse_slice_info(xml: &str) -> Vec<ThreeMfFilament> {
+    let mut by_slot: BTreeMap<u32, ThreeMfFilament> = BTreeMap::new();
+
+    for filament in elements(xml, "filament") {
+        let Some(slot) = filament.get("id").and_then(|id| id.parse().ok()) else {
+            continue;
+        };
+        let grams: f64 = filament.get("used_g").and_then(|g| g.parse().ok()).unwrap_or(0.0);
+        let meters: Option<f64> = filament.get("used_m").and_then(|m| m.parse().ok());
+
+        let entry = by_slot.entry(slot).or_insert_with(|| ThreeMfFilament {
+            slot,
+            material: filament.get("type").map(|t| t.to_string()),
+            color: filament.get("color").map(|c| c.to_string()),
+            grams: 0.0,
+            meters: None,
+        });
+        entry.grams += grams;
+        if let Some(meters) = meters {
+            entry.meters = Some(entry.meters.unwrap_or(0.0) + meters);
+        }
+    }
+
+    by_slot.into_values().filter(|f| f.grams > 0.0).collect()
+}
+
+fn config_values<'a>(config: &'a str, key: &str) -> Vec<&'a str> {
+    config
+        .lines()
+        .filter_map(|line| {
+            let (name, value) = line.trim_start_matches(';').split_once('=')?;
+            (name.trim() == key).then(|| value.trim())
+        })
+        .next()
+        .map(|value| value.split([',', ';']).map(str::trim).collect())
+        .unwrap_or_default()
+}
+
+fn parse_filament_used(config: &str) -> Vec<ThreeMfFilament> {
+    let materials = config_values(config, "filament_type");
+    let colors = config_values(config, "filament_colour");
+    let meters = config_values(config, "filament used [mm]");
+
+    config_values(config, "filament used [g]")
+        .into_iter()
+        .enumerate()
+        .filter_map(|(index, grams)| {
+            let grams: f64 = grams.parse().ok()?;
+            (grams > 0.0).then(|| ThreeMfFilament {
+                slot: index as u32 + 1,
+                material: materials.get(index).map(|m| m.to_string()),
+                color: colors.get(index).map(|c| c.to_string()),
+                grams,
+                meters: meters
+                    .get(index)
+                    .and_then(|mm| mm.parse::<f64>().ok())
+                    .map(|mm| mm / 1000.0),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_usage;
+    use crate::error::ErrorCode;
+    use std::io::{Cursor, Write};
+
+    fn archive(entries: &[(&str, &str)]) -> Cursor<Vec<u8>> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in entries {
+            writer.start_file(*name, zip::write::SimpleFileOptions::default()).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        let mut cursor = writer.finish().unwrap();
+        cursor.set_position(0);
+        cursor
+    }
+
+    #[test]
+    fn slice_info_is_summed_per_slot_across_plates() {
+        let slice_info = r##"<?xml version="1.0" encoding="UTF-8"?>
+<config>
+  <plate>
+    <metadata key="index" value="1"/>
+    <filament id="1" tray_info_idx="GFA00" type="PLA" color="#FFFFFF" used_m="10.50" used_g="31.25" />
+    <filament id="3" tray_info_idx="GFG99" type="PETG" color="#000000" used_m="0.00" used_g="0.00" />
+  </plate>
+  <plate>
+    <metadata key="index" value="2"/>
+    <filament id="1" type="PLA" color="#FFFFFF" used_m="2.00" used_g="5.75" />
+    <filament id="2" type="PLA-CF" color="#FF0000" used_g="12.00" />
+  </plate>
+</config>"##;
+
+        let filaments = read_usage(archive(&[("Metadata/slice_info.config", slice_info)])).unwrap();
+
+        assert_eq!(filaments.len(), 2);
+        assert_eq!(filaments[0].slot, 1);
+        assert_eq!(filaments[0].material.as_deref(), Some("PLA"));
+        assert_eq!(filaments[0].color.as_deref(), Some("#FFFFFF"));
+        assert_eq!(filaments[0].grams, 37.0);
+        assert_eq!(filaments[0].meters, Some(12.5));
+        assert_eq!(filaments[1].slot, 2);
+        assert_eq!(filaments[1].grams, 12.0);
+        assert_eq!(filaments[1].meters, None);
+    }
+
+    #[test]
+    fn prusaslicer_config_is_the_fallback() {
+        let config = "; filament used [mm] = 1500.00, 0.00\n\
+                      ; filament used [g] = 4.50, 0.00\n\
+                      ; filament_type = PETG;PLA\n\
+                      ; filament_colour = #FF8000;#FFFFFF\n";
+
+        let filaments = read_usage(archive(&[("Metadata/Slic3r_PE.config", config)])).unwrap();
+
+        assert_eq!(filaments.len(), 1);
+        assert_eq!(filaments[0].slot, 1);
+        assert_eq!(filaments[0].material.as_deref(), Some("PETG"));
+        assert_eq!(filaments[0].color.as_deref(), Some("#FF8000"));
+        assert_eq!(filaments[0].grams, 4.5);
+        assert_eq!(filaments[0].meters, Some(1.5));
+    }
+
+    #[test]
+    fn unsliced_project_and_non_zip_are_rejected() {
+        let error = read_usage(archive(&[("3D/3dmodel.model", "<model/>")])).unwrap_err();
+        assert_eq!(error.code, ErrorCode::InvalidInput);
+
+        let error = read_usage(Cursor::new(b"not a zip".to_vec())).unwrap_err();
+        assert_eq!(error.code, ErrorCode::Parse);
+    }
+}
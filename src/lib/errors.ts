@@ -0,0 +1,56 @@
+export type ErrorCode =
+  | 'database'
+  | 'database_locked'
+  | 'not_found'
+  | 'invalid_input'
+  | 'printer_busy'
+  | 'printer_auth'
+  | 'printer_unreachable'
+  | 'printer_timeout'
+  | 'nozzle_mismatch'
+  | 'network'
+  | 'parse'
+  | 'io'
+  | 'secrets_locked'
+  | 'secrets'
+  | 'bambu_studio_unavailable'
+  | 'internal';
+
+// Shape of every error rejected by a Tauri command.
+export interface AppError {
+  code: ErrorCode;
+  message: string;
+  details?: Record<string, unknown>;
+}
+
+// One entry of `details.fields` on an `invalid_input` error.
+export interface FieldError {
+  field: string;
+  message: string;
+}
+
+export function isAppError(error: unknown): error is AppError {
+  return (
+    typeof error === 'object' &&
+    error !== null &&
+    typeof (error as AppError).code === 'string' &&
+    typeof (error as AppError).message === 'string'
+  );
+}
+
+export function errorMessage(error: unknown): string {
+  if (isAppError(error)) return error.message;
+  if (error instanceof Error) return error.message;
+  return String(error);
+}
+
+// Field errors keyed by field name, empty for any other kind of error.
+export function fieldErrors(error: unknown): Record<string, string> {
+  if (!isAppError(error) || error.code !== 'invalid_input') return {};
+  const fields = (error.details?.fields ?? []) as FieldError[];
+  const byField: Record<string, string> = {};
+  for (const { field, message } of fields) {
+    byField[field] = byField[field] ? `${byField[field]}; ${message}` : message;
+  }
+  return byField;
+}
@@ -1,5 +1,6 @@
 import { writable } from 'svelte/store';
 import { invoke } from '@tauri-apps/api/core';
+import type { SpoolmanFilament } from './spoolman';
 
 export interface FilamentProfile {
   id?: number;
@@ -12,6 +13,22 @@ export interface FilamentProfile {
   diameter: number;
   is_favorite: boolean;
   is_custom: boolean;
+  spoolman_id?: string | null;
+  name?: string | null;
+  color_hexes?: string[] | null;
+  nozzle_temp_range?: number[] | null;
+  bed_temp_range?: number[] | null;
+  weight?: number | null;
+  spool_weight?: number | null;
+  spool_type?: string | null;
+  finish?: string | null;
+  multi_color_direction?: string | null;
+  pattern?: string | null;
+  translucent?: boolean;
+  glow?: boolean;
+  notes?: string | null;
+  price_per_kg?: number | null;
+  currency?: string | null;
 }
 
 export const favorites = writable<FilamentProfile[]>([]);
@@ -65,6 +82,14 @@ export async function addFavorite(profile: FilamentProfile): Promise<number> {
   return id;
 }
 
+export async function addFavoriteFromSpoolman(filament: SpoolmanFilament): Promise<number> {
+  console.log('Adding SpoolmanDB favorite:', filament.id);
+  const id = await invoke<number>('add_favorite_from_spoolman', { filament });
+  console.log('Favorite added with ID:', id);
+  await loadFavorites();
+  return id;
+}
+
 export async function removeFavorite(id: number) {
   console.log('Removing favorite:', id);
   await invoke('remove_favorite', { id });
@@ -80,15 +105,133 @@ export async function createCustomProfile(profile: FilamentProfile): Promise<num
   return id;
 }
 
-export async function updateCustomProfile(profile: FilamentProfile) {
+export async function updateCustomProfile(profile: FilamentProfile, note?: string) {
   console.log('Updating custom profile:', profile);
-  await invoke('update_custom_profile', { profile });
+  await invoke('update_custom_profile', { profile, note: note || null });
+  await loadCustomProfiles();
+}
+
+export interface TemperatureLimits {
+  family: string | null;
+  nozzle_min: number;
+  nozzle_max: number;
+  bed_min: number;
+  bed_max: number;
+}
+
+export async function getTemperatureLimits(material: string): Promise<TemperatureLimits> {
+  return await invoke<TemperatureLimits>('get_temperature_limits', { material });
+}
+
+export interface ProfileRevision {
+  id: number;
+  profile_id: number;
+  revision: number;
+  profile: FilamentProfile;
+  note: string | null;
+  created_at: string;
+}
+
+export interface RevisionDiff {
+  profile_id: number;
+  from_revision: number;
+  to_revision: number;
+  changes: Record<string, { from: unknown; to: unknown }>;
+}
+
+export async function getProfileRevisions(profileId: number): Promise<ProfileRevision[]> {
+  return await invoke<ProfileRevision[]>('get_profile_revisions', { profileId });
+}
+
+export async function diffProfileRevisions(profileId: number, from: number, to: number): Promise<RevisionDiff> {
+  return await invoke<RevisionDiff>('diff_profile_revisions', { profileId, from, to });
+}
+
+export async function revertProfile(profileId: number, revision: number) {
+  console.log('Reverting profile', profileId, 'to revision', revision);
+  await invoke('revert_profile', { profileId, revision });
   await loadCustomProfiles();
 }
 
+export interface CalibrationResult {
+  id?: number;
+  profile_id: number;
+  printer_id?: number | null;
+  nozzle_diameter: number;
+  nozzle_type?: string | null;
+  flow_ratio?: number | null;
+  pressure_advance?: number | null;
+  max_volumetric_speed?: number | null;
+  nozzle_temp?: number | null;
+  notes?: string | null;
+  calibrated_at?: string | null;
+}
+
+export interface EffectiveCalibration {
+  flow_ratio: number | null;
+  pressure_advance: number | null;
+  max_volumetric_speed: number | null;
+  nozzle_temp: number | null;
+}
+
+export async function getCalibrationResults(profileId: number): Promise<CalibrationResult[]> {
+  return await invoke<CalibrationResult[]>('get_calibration_results', { profileId });
+}
+
+export async function addCalibrationResult(result: CalibrationResult): Promise<number> {
+  return await invoke<number>('add_calibration_result', { result });
+}
+
+export async function updateCalibrationResult(result: CalibrationResult) {
+  await invoke('update_calibration_result', { result });
+}
+
+export async function deleteCalibrationResult(id: number) {
+  await invoke('delete_calibration_result', { id });
+}
+
+export async function getEffectiveCalibration(
+  profileId: number,
+  printerId?: number,
+  nozzleDiameter?: number,
+  nozzleType?: string
+): Promise<EffectiveCalibration> {
+  return await invoke<EffectiveCalibration>('get_effective_calibration', {
+    profileId,
+    printerId: printerId ?? null,
+    nozzleDiameter: nozzleDiameter ?? null,
+    nozzleType: nozzleType ?? null,
+  });
+}
+
 export async function deleteCustomProfile(id: number) {
   console.log('Deleting custom profile:', id);
   await invoke('delete_custom_profile', { id });
   await loadCustomProfiles();
   await loadFavorites();
 }
+
+export interface TrashedProfile extends FilamentProfile {
+  deleted_at: string;
+}
+
+export async function getTrash(): Promise<TrashedProfile[]> {
+  return await invoke<TrashedProfile[]>('get_trash');
+}
+
+export async function restoreProfile(id: number) {
+  console.log('Restoring profile:', id);
+  await invoke('restore_profile', { id });
+  await loadCustomProfiles();
+  await loadFavorites();
+}
+
+export async function purgeProfile(id: number) {
+  console.log('Purging profile:', id);
+  await invoke('purge_profile', { id });
+}
+
+export async function emptyTrash(): Promise<number> {
+  console.log('Emptying trash');
+  return await invoke<number>('empty_trash');
+}
@@ -1,5 +1,6 @@
 import { invoke } from '@tauri-apps/api/core';
 import { writable } from 'svelte/store';
+import { errorMessage } from '../errors';
 
 export interface BambuPrinterConfig {
   ip: string;
@@ -29,7 +30,7 @@ export async function testConnection(config: BambuPrinterConfig): Promise<string
     return result;
   } catch (error) {
     connectionStatus.set('error');
-    connectionMessage.set(String(error));
+    connectionMessage.set(errorMessage(error));
     setTimeout(() => connectionStatus.set('idle'), 5000);
     throw error;
   }
@@ -48,7 +49,7 @@ export async function syncToAMS(
     return result;
   } catch (error) {
     connectionStatus.set('error');
-    connectionMessage.set(String(error));
+    connectionMessage.set(errorMessage(error));
     setTimeout(() => connectionStatus.set('idle'), 5000);
     throw error;
   }
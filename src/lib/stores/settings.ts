@@ -9,6 +9,7 @@ export interface Settings {
   default_ams: number;
   default_tray: number;
   auto_sync: boolean;
+  spoolman_server_url?: string | null;
 }
 
 export const settings = writable<Settings>({
@@ -11,6 +11,11 @@ export interface SpoolmanFilament {
   weight: number | null;
   spool_weight: number | null;
   color_hex: string | null;
+  color_hexes?: string[] | null;
+  spool_type?: string | null;
+  finish?: string | null;
+  multi_color_direction?: string | null;
+  pattern?: string | null;
   extruder_temp: number | null;
   bed_temp: number | null;
   extruder_temp_range: number[] | null;
@@ -156,12 +161,30 @@ export async function loadColors() {
   }
 }
 
+export interface SpoolmanCacheStatus {
+  // network: fetched or revalidated this session; disk: offline copy; bundled: shipped snapshot;
+  // local: a filaments.json or checkout on this machine
+  source: 'network' | 'disk' | 'bundled' | 'local';
+  location: string;
+  synced_at: number | null;
+  filament_count: number;
+}
+
+export async function getSpoolmanStatus(): Promise<SpoolmanCacheStatus> {
+  return await invoke<SpoolmanCacheStatus>('get_spoolman_status');
+}
+
+// Drops the lists derived from the catalog, e.g. after its source changed.
+export function clearSpoolmanCache() {
+  cachedBrands = null;
+  cachedMaterials = null;
+  cachedColors = null;
+}
+
 export async function syncSpoolmanDB() {
   try {
     await invoke('sync_spoolman_db');
-    cachedBrands = null;
-    cachedMaterials = null;
-    cachedColors = null;
+    clearSpoolmanCache();
     await loadBrands();
     await loadMaterials();
     await loadColors();
@@ -0,0 +1,102 @@
+import { invoke } from '@tauri-apps/api/core';
+
+// A self-hosted Spoolman server, which tracks physical spools. The
+// SpoolmanDB catalog is in ./spoolman.
+
+export interface ServerInfo {
+  version: string;
+}
+
+export interface ServerVendor {
+  id: number;
+  name: string;
+  comment?: string | null;
+  empty_spool_weight?: number | null;
+  external_id?: string | null;
+}
+
+export interface ServerFilament {
+  id: number;
+  name?: string | null;
+  vendor?: ServerVendor | null;
+  material?: string | null;
+  price?: number | null;
+  density: number;
+  diameter: number;
+  weight?: number | null;
+  spool_weight?: number | null;
+  settings_extruder_temp?: number | null;
+  settings_bed_temp?: number | null;
+  color_hex?: string | null;
+  multi_color_hexes?: string | null;
+  external_id?: string | null;
+}
+
+export interface ServerSpool {
+  id: number;
+  filament: ServerFilament;
+  initial_weight?: number | null;
+  remaining_weight?: number | null;
+  used_weight: number;
+  location?: string | null;
+  lot_nr?: string | null;
+  comment?: string | null;
+  archived: boolean;
+}
+
+export interface SpoolmanImportReport {
+  spool_ids: number[];
+  created: number;
+  updated: number;
+  profiles_created: number;
+}
+
+// The local spool an import creates or refreshes.
+export interface LocalSpool {
+  id: number;
+  profile_id: number | null;
+  spoolman_id: string | null;
+  initial_weight: number;
+  remaining_weight: number;
+  location: string | null;
+}
+
+export function spoolLabel(spool: ServerSpool): string {
+  const filament = spool.filament;
+  const name = [filament.vendor?.name, filament.name ?? filament.material].filter(Boolean).join(' ');
+  const remaining = spool.remaining_weight != null ? ` · ${Math.round(spool.remaining_weight)} g` : '';
+  return `#${spool.id} ${name}${remaining}`;
+}
+
+export async function getSpoolmanServerInfo(): Promise<ServerInfo> {
+  return await invoke<ServerInfo>('get_spoolman_server_info');
+}
+
+export async function getSpoolmanServerVendors(): Promise<ServerVendor[]> {
+  return await invoke<ServerVendor[]>('get_spoolman_server_vendors');
+}
+
+export async function getSpoolmanServerFilaments(): Promise<ServerFilament[]> {
+  return await invoke<ServerFilament[]>('get_spoolman_server_filaments');
+}
+
+export async function getSpoolmanServerSpools(allowArchived = false): Promise<ServerSpool[]> {
+  return await invoke<ServerSpool[]>('get_spoolman_server_spools', { allowArchived });
+}
+
+export async function getSpoolmanServerLocations(): Promise<string[]> {
+  return await invoke<string[]>('get_spoolman_server_locations');
+}
+
+export async function importSpoolmanServerSpools(): Promise<SpoolmanImportReport> {
+  console.log('Importing spools from Spoolman');
+  return await invoke<SpoolmanImportReport>('import_spoolman_server_spools');
+}
+
+export async function importSpoolmanServerSpool(id: number): Promise<LocalSpool> {
+  return await invoke<LocalSpool>('import_spoolman_server_spool', { id });
+}
+
+export async function reportSpoolmanUsage(): Promise<number> {
+  return await invoke<number>('report_spoolman_usage');
+}